@@ -3,16 +3,33 @@
 
 no-bug-reports = No bug reports are available, nothing exported
 bug-report-written-to = The bug report saved to '{$file_name}'
+problem-report-summary = { $failed_runs ->
+        [one] One previous run failed
+       *[other] { $failed_runs } previous runs failed
+    }, { $panicked_runs ->
+        [0] none of them panicked
+        [one] one of them panicked
+       *[other] { $panicked_runs } of them panicked
+    }.
 
 problem-report-dialog = Problem report
-    .file-description = One or more previous application executions that were unsuccessful can be exported and sent for investigation. The report will not contain any video or audio data. This dialog will be displayed the next time you start the program if you close it.
+    .file-description = One or more previous application executions that were unsuccessful can be exported and sent for investigation. The report will not contain any video or audio data. This dialog will be displayed the next time you start the program if you close it, unless you choose otherwise below.
     .success-title = Report creation succeeded
     .success-description = The report file saved to '{$file_name}'. If your request relates to encoded video or audio data, please send it together with your created report file to {$support_mail}
     .error-create-title = Report creation failed
     .error-move-title = Move to trash failed
+    .progress-description = Creating...
+    .progress-files = {$files_done} of {$files_total} files
     .btn-choose-file = Choose report file...
     .btn-back = Back
     .btn-move-to-trash = Move to trash
+    .btn-snooze = Remind me in {$days} days
+    .consent-title = When problems are detected
+    .consent-ask-every-time = Ask every time
+    .consent-always-export = Always export to the folder of the report file
+    .consent-always-upload = Always upload
+    .consent-never-ask = Never ask
+    .btn-cancel = Cancel
     .all-files = { -all-files }
     .zip-archive = { -zip-archive }
 
@@ -20,10 +37,25 @@ create-report-dialog = Create report file
     .file-description = If you need assistance, you can generate a report file of the current session. The report will not contain any video or audio data.
     .progress-title = Report creating
     .progress-description = Creating...
+    .progress-files = {$files_done} of {$files_total} files
     .success-title = Report creation succeeded
     .success-description = The report file saved to '{$file_name}'. If your request relates to encoded video or audio data, please send it together with your created report file to {$support_mail}
     .error-title = Report creation failed
     .btn-choose-file = Choose report file...
     .btn-choose-other-file = Choose other report file...
+    .btn-cancel = Cancel
     .all-files = { -all-files }
     .zip-archive = { -zip-archive }
+
+send-report-group = Send report
+    .btn-cancel = Cancel
+    .btn-upload = Upload report to support
+    .upload-progress = Uploading... {$percent}%
+    .upload-success = The report was uploaded
+    .upload-error = Upload failed: {$error}
+    .upload-cancelled = Upload cancelled
+    .btn-send-mail = Send report by email
+    .mail-sending = Sending...
+    .mail-success = The report was sent
+    .mail-error = Sending failed: {$error}
+    .mail-cancelled = Sending cancelled
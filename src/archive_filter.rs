@@ -0,0 +1,175 @@
+use anyhow::{Context, Result};
+use glob::Pattern;
+use std::path::Path;
+
+/// Rules deciding which files of the run directories are added to an archive.
+///
+/// Glob patterns are matched against the entry name inside the archive, a `*` also matches path separators.
+/// MIME types are guessed from the file extension and may end with a `/*` wildcard, e.g. `video/*`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveFilter {
+    /// If not empty, only files matching at least one of these glob patterns are added.
+    pub include: Vec<String>,
+    /// Files matching one of these glob patterns are never added.
+    pub exclude: Vec<String>,
+    /// Files with one of these MIME types are never added.
+    pub exclude_mime_types: Vec<String>,
+    /// Files larger than this number of bytes are never added.
+    pub max_file_size: Option<u64>,
+}
+
+impl Default for ArchiveFilter {
+    fn default() -> Self {
+        Self {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            exclude_mime_types: vec!["video/*".into(), "audio/*".into()],
+            max_file_size: None,
+        }
+    }
+}
+
+impl ArchiveFilter {
+    pub(crate) fn compile(&self) -> Result<CompiledArchiveFilter> {
+        let compile_patterns = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| Pattern::new(pattern).with_context(|| format!("Invalid glob pattern '{pattern}'")))
+                .collect::<Result<Vec<_>>>()
+        };
+        Ok(CompiledArchiveFilter {
+            include: compile_patterns(&self.include)?,
+            exclude: compile_patterns(&self.exclude)?,
+            exclude_mime_types: self.exclude_mime_types.clone(),
+            max_file_size: self.max_file_size,
+        })
+    }
+}
+
+pub(crate) struct CompiledArchiveFilter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    exclude_mime_types: Vec<String>,
+    max_file_size: Option<u64>,
+}
+
+impl CompiledArchiveFilter {
+    /// Returns the reason why a file is excluded or `None` if it is added to the archive.
+    pub(crate) fn exclude_reason(&self, name: &Path, size: u64) -> Option<String> {
+        let name_str = name.to_string_lossy();
+        if !self.include.is_empty() && !self.include.iter().any(|pattern| pattern.matches(&name_str)) {
+            return Some("not matched by any include pattern".into());
+        }
+        if let Some(pattern) = self.exclude.iter().find(|pattern| pattern.matches(&name_str)) {
+            return Some(format!("matched exclude pattern '{pattern}'"));
+        }
+        for mime in mime_guess::from_path(name).iter() {
+            if let Some(mime_type) = self
+                .exclude_mime_types
+                .iter()
+                .find(|mime_type| mime_type_matches(mime_type, mime.essence_str()))
+            {
+                return Some(format!(
+                    "MIME type '{}' matched excluded type '{mime_type}'",
+                    mime.essence_str()
+                ));
+            }
+        }
+        if let Some(max_file_size) = self.max_file_size {
+            if size > max_file_size {
+                return Some(format!(
+                    "file size {size} bytes exceeds maximum of {max_file_size} bytes"
+                ));
+            }
+        }
+        None
+    }
+}
+
+fn mime_type_matches(pattern: &str, mime_type: &str) -> bool {
+    match pattern.strip_suffix("/*") {
+        Some(top_level) => mime_type
+            .split_once('/')
+            .is_some_and(|(mime_top_level, _)| mime_top_level.eq_ignore_ascii_case(top_level)),
+        None => pattern.eq_ignore_ascii_case(mime_type),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exclude_reason(filter: &ArchiveFilter, name: &str, size: u64) -> Option<String> {
+        filter.compile().unwrap().exclude_reason(Path::new(name), size)
+    }
+
+    #[test]
+    fn include_patterns() {
+        let filter = ArchiveFilter {
+            include: vec!["*.log".into(), "run/sysinfo.txt".into()],
+            ..Default::default()
+        };
+        assert_eq!(exclude_reason(&filter, "run/app.log", 0), None);
+        assert_eq!(exclude_reason(&filter, "run/nested/app.log", 0), None);
+        assert_eq!(exclude_reason(&filter, "run/sysinfo.txt", 0), None);
+        assert!(exclude_reason(&filter, "run/other.txt", 0).is_some());
+    }
+
+    #[test]
+    fn exclude_patterns() {
+        let filter = ArchiveFilter {
+            exclude: vec!["*/cache/*".into()],
+            ..Default::default()
+        };
+        assert_eq!(
+            exclude_reason(&filter, "run/cache/data.bin", 0).as_deref(),
+            Some("matched exclude pattern '*/cache/*'")
+        );
+        assert_eq!(exclude_reason(&filter, "run/data.bin", 0), None);
+    }
+
+    #[test]
+    fn invalid_pattern() {
+        let filter = ArchiveFilter {
+            exclude: vec!["[".into()],
+            ..Default::default()
+        };
+        assert!(filter.compile().is_err());
+    }
+
+    #[test]
+    fn mime_types() {
+        let filter = ArchiveFilter::default();
+        assert!(exclude_reason(&filter, "run/record.mp4", 0).is_some());
+        assert!(exclude_reason(&filter, "run/record.WAV", 0).is_some());
+        assert_eq!(exclude_reason(&filter, "run/app.log", 0), None);
+
+        let filter = ArchiveFilter {
+            exclude_mime_types: vec!["image/png".into()],
+            ..Default::default()
+        };
+        assert!(exclude_reason(&filter, "run/screenshot.png", 0).is_some());
+        assert_eq!(exclude_reason(&filter, "run/photo.jpg", 0), None);
+        assert_eq!(exclude_reason(&filter, "run/record.mp4", 0), None);
+    }
+
+    #[test]
+    fn mime_type_patterns() {
+        assert!(mime_type_matches("video/*", "video/mp4"));
+        assert!(mime_type_matches("Video/*", "video/mp4"));
+        assert!(!mime_type_matches("video/*", "audio/mpeg"));
+        assert!(!mime_type_matches("video/*", "video"));
+        assert!(mime_type_matches("text/plain", "TEXT/PLAIN"));
+        assert!(!mime_type_matches("text/plain", "text/html"));
+    }
+
+    #[test]
+    fn max_file_size() {
+        let filter = ArchiveFilter {
+            max_file_size: Some(100),
+            ..Default::default()
+        };
+        assert_eq!(exclude_reason(&filter, "run/app.log", 100), None);
+        assert!(exclude_reason(&filter, "run/app.log", 101).is_some());
+    }
+}
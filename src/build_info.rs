@@ -0,0 +1,244 @@
+use anyhow::{Context, Result};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Write, path::Path};
+
+/// File in each run directory with the [`BuildInfo`] of the application that produced the run.
+pub const BUILD_INFO_FILE_NAME: &str = "build_info.json";
+/// First line of the build section appended to `.panic` files.
+pub(crate) const BUILD_SECTION_HEADER: &str = "=> build:";
+
+static BUILD_INFO: OnceCell<BuildInfo> = OnceCell::new();
+
+/// Build of the host application, captured at compile time by [`build_info!`](crate::build_info!).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildInfo {
+    pub package_name: String,
+    pub package_version: String,
+    #[serde(default)]
+    pub git_commit: Option<String>,
+    /// `true` if the working tree had uncommitted changes.
+    #[serde(default)]
+    pub git_dirty: Option<bool>,
+    /// Cargo profile, e.g. `debug` or `release`.
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Target triple, e.g. `x86_64-unknown-linux-gnu`.
+    #[serde(default)]
+    pub target: Option<String>,
+    #[serde(default)]
+    pub rustc_version: Option<String>,
+    /// Enabled features of the host crate.
+    #[serde(default)]
+    pub features: Vec<String>,
+}
+
+impl BuildInfo {
+    /// Formats the version with the git commit, e.g. `1.2.3 (0123abcd, dirty)`.
+    pub fn version_description(&self) -> String {
+        match (self.git_commit.as_ref(), self.git_dirty) {
+            (Some(commit), Some(true)) => format!("{} ({commit}, dirty)", self.package_version),
+            (Some(commit), _) => format!("{} ({commit})", self.package_version),
+            (None, _) => self.package_version.clone(),
+        }
+    }
+
+    /// Formats the build information as human readable text.
+    pub fn to_text(&self) -> String {
+        fn or_unknown(value: &Option<String>) -> &str {
+            value.as_deref().unwrap_or("unknown")
+        }
+
+        let mut out = String::new();
+        _ = writeln!(out, "Package:  {} {}", self.package_name, self.version_description());
+        _ = writeln!(out, "Profile:  {}", or_unknown(&self.profile));
+        _ = writeln!(out, "Target:   {}", or_unknown(&self.target));
+        _ = writeln!(out, "Rustc:    {}", or_unknown(&self.rustc_version));
+        _ = writeln!(out, "Features: {}", self.features.join(", "));
+        out
+    }
+}
+
+/// Captures the [`BuildInfo`] of the calling crate at compile time.
+///
+/// Name and version are always available. Git commit, profile, target, rustc version and features
+/// are only available if the build script of the calling crate calls [`build_info::emit_build_env`](crate::build_info::emit_build_env).
+///
+/// ```ignore
+/// mxl_investigator::build_info::build_info_set(mxl_investigator::build_info!());
+/// ```
+#[macro_export]
+macro_rules! build_info {
+    () => {
+        $crate::build_info::BuildInfo {
+            package_name: env!("CARGO_PKG_NAME").to_string(),
+            package_version: env!("CARGO_PKG_VERSION").to_string(),
+            git_commit: option_env!("MXL_INVESTIGATOR_BUILD_GIT_COMMIT").map(str::to_string),
+            git_dirty: option_env!("MXL_INVESTIGATOR_BUILD_GIT_DIRTY").map(|dirty| dirty == "true"),
+            profile: option_env!("MXL_INVESTIGATOR_BUILD_PROFILE").map(str::to_string),
+            target: option_env!("MXL_INVESTIGATOR_BUILD_TARGET").map(str::to_string),
+            rustc_version: option_env!("MXL_INVESTIGATOR_BUILD_RUSTC_VERSION").map(str::to_string),
+            features: $crate::build_info::parse_features(option_env!("MXL_INVESTIGATOR_BUILD_FEATURES")),
+        }
+    };
+}
+
+/// Splits the comma separated features emitted by [`emit_build_env`], used by [`build_info!`](crate::build_info!).
+#[doc(hidden)]
+pub fn parse_features(features: Option<&str>) -> Vec<String> {
+    features
+        .unwrap_or_default()
+        .split(',')
+        .filter(|feature| !feature.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Sets the build of the application, which is recorded in every run directory, archive manifest and `.panic` file.
+///
+/// Can only be set once, it should be set before [`crate::proc_dir::proc_dir`] is called the first time.
+pub fn build_info_set(info: BuildInfo) -> Result<()> {
+    BUILD_INFO
+        .set(info)
+        .map_err(|_| anyhow::anyhow!("Build information already set"))?;
+    // The run directory may exist already
+    if let Some(run_dir) = crate::proc_dir::proc_dir_if_initialized() {
+        write_to_run_dir(run_dir);
+    }
+    Ok(())
+}
+
+pub fn build_info() -> Option<&'static BuildInfo> {
+    BUILD_INFO.get()
+}
+
+pub(crate) fn write_to_run_dir(run_dir: &Path) {
+    let Some(info) = build_info() else {
+        return;
+    };
+    let path = run_dir.join(BUILD_INFO_FILE_NAME);
+    let result = serde_json::to_vec_pretty(info)
+        .map_err(anyhow::Error::from)
+        .and_then(|content| {
+            std::fs::write(&path, content).with_context(|| format!("Cannot write file '{}'", path.to_string_lossy()))
+        });
+    if let Err(err) = result {
+        log::warn!("{err:?}");
+    }
+}
+
+/// Emits the environment variables read by [`build_info!`](crate::build_info!), called by the build script
+/// of the host application.
+///
+/// Feature names are derived from the `CARGO_FEATURE_*` variables, so they are lowercase with `-` instead of `_`.
+/// Cargo maps both `-` and `_` to `_` in these variables, a feature `foo_bar` is therefore reported as `foo-bar`.
+pub fn emit_build_env() {
+    fn emit(name: &str, value: &str) {
+        println!("cargo:rustc-env=MXL_INVESTIGATOR_BUILD_{name}={value}");
+    }
+
+    fn command_output(program: &str, args: &[&str]) -> Option<String> {
+        let output = std::process::Command::new(program).args(args).output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    if let Some(commit) = command_output("git", &["rev-parse", "HEAD"]) {
+        emit("GIT_COMMIT", &commit);
+        let dirty = command_output("git", &["status", "--porcelain", "--untracked-files=no"])
+            .is_some_and(|status| !status.is_empty());
+        emit("GIT_DIRTY", if dirty { "true" } else { "false" });
+    }
+    // Emitting a rerun line disables the default rerun on any package change, so the sources are listed to keep
+    // the dirty flag current
+    for path in ["src", "Cargo.toml"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={path}");
+        }
+    }
+    // Rerun on commits, checkouts and changes of the index
+    if let Some(git_dir) = command_output("git", &["rev-parse", "--git-dir"]) {
+        for file in ["HEAD", "logs/HEAD", "index"] {
+            let path = Path::new(&git_dir).join(file);
+            if path.exists() {
+                println!("cargo:rerun-if-changed={}", path.to_string_lossy());
+            }
+        }
+    }
+    if let Ok(profile) = std::env::var("PROFILE") {
+        emit("PROFILE", &profile);
+    }
+    if let Ok(target) = std::env::var("TARGET") {
+        emit("TARGET", &target);
+    }
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    if let Some(version) = command_output(&rustc, &["--version"]) {
+        emit("RUSTC_VERSION", &version);
+    }
+    let mut features = std::env::vars()
+        .filter_map(|(name, _)| {
+            name.strip_prefix("CARGO_FEATURE_")
+                .map(|feature| feature.to_lowercase().replace('_', "-"))
+        })
+        .collect::<Vec<_>>();
+    features.sort();
+    emit("FEATURES", &features.join(","));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_info() -> BuildInfo {
+        BuildInfo {
+            package_name: "my-app".to_string(),
+            package_version: "1.2.3".to_string(),
+            git_commit: Some("0123abcd".to_string()),
+            git_dirty: Some(true),
+            profile: Some("release".to_string()),
+            target: None,
+            rustc_version: Some("rustc 1.80.0".to_string()),
+            features: vec!["default".to_string(), "foo-bar".to_string()],
+        }
+    }
+
+    #[test]
+    fn version_description() {
+        let mut info = build_info();
+        assert_eq!(info.version_description(), "1.2.3 (0123abcd, dirty)");
+        info.git_dirty = Some(false);
+        assert_eq!(info.version_description(), "1.2.3 (0123abcd)");
+        info.git_dirty = None;
+        assert_eq!(info.version_description(), "1.2.3 (0123abcd)");
+        // Without a commit the dirty flag is meaningless
+        info.git_commit = None;
+        info.git_dirty = Some(true);
+        assert_eq!(info.version_description(), "1.2.3");
+    }
+
+    #[test]
+    fn text() {
+        assert_eq!(
+            build_info().to_text(),
+            "Package:  my-app 1.2.3 (0123abcd, dirty)\n\
+             Profile:  release\n\
+             Target:   unknown\n\
+             Rustc:    rustc 1.80.0\n\
+             Features: default, foo-bar\n"
+        );
+    }
+
+    #[test]
+    fn features() {
+        assert!(parse_features(None).is_empty());
+        assert!(parse_features(Some("")).is_empty());
+        assert_eq!(parse_features(Some("default")), ["default"]);
+        assert_eq!(parse_features(Some("default,foo-bar,,x")), ["default", "foo-bar", "x"]);
+
+        let info = crate::build_info!();
+        assert_eq!(info.package_name, env!("CARGO_PKG_NAME"));
+        assert_eq!(info.package_version, env!("CARGO_PKG_VERSION"));
+    }
+}
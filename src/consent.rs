@@ -0,0 +1,218 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+pub(crate) const CONSENT_FILE_NAME: &str = "consent.json";
+/// Number of days the problem report is snoozed by the dialog.
+pub const DEFAULT_SNOOZE_DAYS: u32 = 7;
+
+/// Choice of the user how problems of previous runs are handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProblemReportConsent {
+    /// Presents the problem report dialog on every start with failed runs.
+    #[default]
+    AskEveryTime,
+    /// Exports the failed runs to the export directory without asking.
+    AlwaysExport,
+    /// Uploads the failed runs to the configured endpoint without asking.
+    AlwaysUpload,
+    /// Never asks, the failed runs are kept until they are removed by the retention.
+    NeverAsk,
+}
+
+impl ProblemReportConsent {
+    /// All choices in the order shown by the dialog.
+    pub const ALL: [ProblemReportConsent; 4] = [
+        ProblemReportConsent::AskEveryTime,
+        ProblemReportConsent::AlwaysExport,
+        ProblemReportConsent::AlwaysUpload,
+        ProblemReportConsent::NeverAsk,
+    ];
+}
+
+/// Persisted consent of the user, stored in the data directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsentState {
+    #[serde(default)]
+    pub problem_report: ProblemReportConsent,
+    /// Directory for [`ProblemReportConsent::AlwaysExport`].
+    #[serde(default)]
+    pub export_dir: Option<PathBuf>,
+    /// End of the snooze as RFC 3339 timestamp, the problem report is not offered before.
+    #[serde(default)]
+    pub snoozed_until: Option<String>,
+}
+
+impl ConsentState {
+    pub fn is_snoozed(&self) -> bool {
+        self.snoozed_until
+            .as_deref()
+            .and_then(|until| humantime::parse_rfc3339(until).ok())
+            .is_some_and(|until| SystemTime::now() < until)
+    }
+
+    fn snooze(&mut self, days: u32) {
+        let until = SystemTime::now() + Duration::from_secs(u64::from(days) * 24 * 60 * 60);
+        self.snoozed_until = Some(humantime::format_rfc3339_seconds(until).to_string());
+    }
+
+    fn action(self, upload_configured: bool) -> ProblemReportAction {
+        if self.is_snoozed() {
+            return ProblemReportAction::Ignore;
+        }
+        match self.problem_report {
+            ProblemReportConsent::AskEveryTime => ProblemReportAction::Ask,
+            ProblemReportConsent::AlwaysExport => match self.export_dir {
+                Some(export_dir) => ProblemReportAction::Export(export_dir),
+                None => ProblemReportAction::Ask,
+            },
+            ProblemReportConsent::AlwaysUpload if upload_configured => ProblemReportAction::Upload,
+            ProblemReportConsent::AlwaysUpload => ProblemReportAction::Ask,
+            ProblemReportConsent::NeverAsk => ProblemReportAction::Ignore,
+        }
+    }
+}
+
+/// Action for the failed runs according to the consent of the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProblemReportAction {
+    /// Present the problem report dialog.
+    Ask,
+    /// Export the failed runs into the directory.
+    Export(PathBuf),
+    /// Upload the failed runs.
+    Upload,
+    /// Do nothing, the user does not want to be asked or snoozed the problem report.
+    Ignore,
+}
+
+fn consent_file_path() -> PathBuf {
+    crate::misc::get_data_dir().join(CONSENT_FILE_NAME)
+}
+
+/// Reads the consent of the user, the default if nothing was stored yet.
+pub fn consent_state() -> Result<ConsentState> {
+    let path = consent_file_path();
+    if !path.exists() {
+        return Ok(ConsentState::default());
+    }
+    let content = std::fs::read(&path).with_context(|| format!("Cannot read file '{}'", path.to_string_lossy()))?;
+    serde_json::from_slice(&content).with_context(|| format!("Cannot parse file '{}'", path.to_string_lossy()))
+}
+
+pub fn consent_set_state(state: &ConsentState) -> Result<()> {
+    let path = consent_file_path();
+    let content = serde_json::to_vec_pretty(state)?;
+    // Write a temporary file first to not lose the consent if the program is aborted while writing
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, content)
+        .with_context(|| format!("Cannot write file '{}'", tmp_path.to_string_lossy()))?;
+    std::fs::rename(&tmp_path, &path).with_context(|| format!("Cannot write file '{}'", path.to_string_lossy()))
+}
+
+fn consent_update(update: impl FnOnce(&mut ConsentState)) -> Result<()> {
+    let mut state = consent_state()?;
+    update(&mut state);
+    consent_set_state(&state)
+}
+
+pub fn consent_set_problem_report(consent: ProblemReportConsent) -> Result<()> {
+    consent_update(|state| state.problem_report = consent)
+}
+
+pub fn consent_set_export_dir(export_dir: Option<PathBuf>) -> Result<()> {
+    consent_update(|state| state.export_dir = export_dir)
+}
+
+/// Does not offer the problem report for the given number of days.
+pub fn consent_snooze(days: u32) -> Result<()> {
+    consent_update(|state| state.snooze(days))
+}
+
+pub fn consent_clear_snooze() -> Result<()> {
+    consent_update(|state| state.snoozed_until = None)
+}
+
+/// Returns the action for failed runs, which the host queries before presenting the problem report dialog.
+///
+/// Automatic choices fall back to [`ProblemReportAction::Ask`] if they cannot be performed,
+/// e.g. if no export directory is set or no upload endpoint is configured.
+pub fn problem_report_action() -> Result<ProblemReportAction> {
+    Ok(consent_state()?.action(upload_is_configured()))
+}
+
+fn upload_is_configured() -> bool {
+    #[cfg(feature = "upload")]
+    return crate::upload::upload_is_configured();
+    #[cfg(not(feature = "upload"))]
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(problem_report: ProblemReportConsent) -> ConsentState {
+        ConsentState {
+            problem_report,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn snooze_window() {
+        let mut snoozed = state(ProblemReportConsent::AskEveryTime);
+        assert!(!snoozed.is_snoozed());
+        snoozed.snooze(DEFAULT_SNOOZE_DAYS);
+        assert!(snoozed.is_snoozed());
+        assert_eq!(snoozed.clone().action(false), ProblemReportAction::Ignore);
+
+        // The snooze ends at the stored time
+        snoozed.snooze(0);
+        assert!(!snoozed.is_snoozed());
+        assert_eq!(snoozed.action(false), ProblemReportAction::Ask);
+
+        let expired = ConsentState {
+            snoozed_until: Some("2020-01-01T00:00:00Z".to_string()),
+            ..state(ProblemReportConsent::NeverAsk)
+        };
+        assert!(!expired.is_snoozed());
+        let invalid = ConsentState {
+            snoozed_until: Some("tomorrow".to_string()),
+            ..Default::default()
+        };
+        assert!(!invalid.is_snoozed());
+    }
+
+    #[test]
+    fn automatic_actions() {
+        let export_dir = PathBuf::from("/reports");
+        let export = ConsentState {
+            export_dir: Some(export_dir.clone()),
+            ..state(ProblemReportConsent::AlwaysExport)
+        };
+        assert_eq!(export.action(false), ProblemReportAction::Export(export_dir));
+        // Without a directory the user is asked instead
+        assert_eq!(
+            state(ProblemReportConsent::AlwaysExport).action(true),
+            ProblemReportAction::Ask
+        );
+
+        assert_eq!(
+            state(ProblemReportConsent::AlwaysUpload).action(true),
+            ProblemReportAction::Upload
+        );
+        assert_eq!(
+            state(ProblemReportConsent::AlwaysUpload).action(false),
+            ProblemReportAction::Ask
+        );
+        assert_eq!(
+            state(ProblemReportConsent::NeverAsk).action(true),
+            ProblemReportAction::Ignore
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::proc_dir::ArchiveProgress;
 use mxl_relm4_components::relm4::gtk;
 use std::path::PathBuf;
 
@@ -10,6 +11,13 @@ pub(super) mod internal {
         SwitchForwardTo(gtk::Widget),
         OpenFileChooser,
         CreateReport(PathBuf),
+        CancelReport,
+    }
+
+    #[derive(Debug)]
+    pub enum CommandMsg {
+        Progress(ArchiveProgress),
+        Finished(anyhow::Result<()>),
     }
 }
 
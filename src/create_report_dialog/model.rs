@@ -1,17 +1,39 @@
+use crate::{
+    proc_dir::CancellationToken,
+    send_report_group::model::SendReportGroup,
+    support_contact::{SupportContact, SupportMessageContext},
+};
 use mxl_relm4_components::{relm4::Controller, relm4_components::save_dialog::SaveDialog};
 
 #[derive(Debug)]
 pub struct CreateReportDialogInit {
     pub app_name: &'static str,
+    pub app_version: Option<&'static str>,
     pub binary_name: &'static str,
+    /// Support contact of the dialog, the contact set by [`crate::support_contact::support_set_contact`] if `None`.
+    pub support_contact: Option<SupportContact>,
 }
 
 #[derive(Debug)]
 pub struct CreateReportDialog {
     pub(super) app_name: &'static str,
+    pub(super) app_version: Option<&'static str>,
     pub(super) binary_name: &'static str,
+    pub(super) support_contact: Option<SupportContact>,
     pub(super) file_name: String,
     pub(super) file_chooser: Controller<SaveDialog>,
+    pub(super) send_group: Controller<SendReportGroup>,
+    pub(super) cancellation: Option<CancellationToken>,
 }
 
-impl CreateReportDialog {}
+impl CreateReportDialog {
+    pub(super) fn support_contact(&self) -> SupportContact {
+        self.support_contact
+            .clone()
+            .unwrap_or_else(crate::support_contact::support_contact)
+    }
+
+    pub(super) fn message_context(&self) -> SupportMessageContext {
+        SupportMessageContext::new(self.app_name, self.app_version, std::path::Path::new(&self.file_name))
+    }
+}
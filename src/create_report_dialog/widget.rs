@@ -1,8 +1,15 @@
 use super::{
-    messages::{internal::PrivateMsg, CreateReportDialogInput, CreateReportDialogOutput},
+    messages::{
+        internal::{CommandMsg, PrivateMsg},
+        CreateReportDialogInput, CreateReportDialogOutput,
+    },
     model::{CreateReportDialog, CreateReportDialogInit},
 };
-use crate::localization::helper::fl;
+use crate::{
+    localization::helper::fl,
+    proc_dir::{ArchiveCancelled, ArchiveOptions, ArchiveProgress, CancellationToken},
+    send_report_group::{messages::SendReportGroupInput, model::SendReportGroup},
+};
 use mxl_relm4_components::{
     relm4::{
         self,
@@ -15,39 +22,12 @@ use mxl_relm4_components::{
 };
 use relm4_icons::icon_names;
 
-macro_rules! report_subject_fmt {
-    () => {
-        "Report file for {app_name}"
-    };
-}
-macro_rules! report_body_fmt {
-    () => {
-        "Hello X-Software Support,\n\
-\n\
-\n\
-I would like get assistance for {app_name}.\n\
-\n\
-Thanks!"
-    };
-}
-
-fn create_report_email_link(app_name: &str) -> String {
-    use urlencoding::encode;
-
-    format!(
-        "<a href=\"mailto:{email}?subject={subject}&amp;body={body}\">{email}</a>",
-        email = crate::misc::SUPPORT_EMAIL,
-        subject = encode(&format!(report_subject_fmt!(), app_name = app_name)),
-        body = encode(&format!(report_body_fmt!(), app_name = app_name))
-    )
-}
-
 #[relm4::component(pub)]
 impl Component for CreateReportDialog {
     type Init = CreateReportDialogInit;
     type Input = CreateReportDialogInput;
     type Output = CreateReportDialogOutput;
-    type CommandOutput = anyhow::Result<()>;
+    type CommandOutput = CommandMsg;
 
     view! {
         adw::Window {
@@ -96,18 +76,23 @@ impl Component for CreateReportDialog {
 
                         #[name(progress_page)]
                         gtk::Box {
-                            set_orientation: gtk::Orientation::Horizontal,
+                            set_orientation: gtk::Orientation::Vertical,
                             set_spacing: 8,
-                            set_halign: gtk::Align::Center,
+                            set_valign: gtk::Align::Center,
+                            set_margin_all: 32,
 
-                            gtk::Spinner {
-                                #[watch]
-                                set_spinning: true,
-                                set_size_request: (32, 32),
-                            },
                             gtk::Label {
                                 add_css_class: "title-2",
-                                set_label: &&fl!("create-report-dialog", "progress-description"),
+                                set_label: &fl!("create-report-dialog", "progress-description"),
+                            },
+                            #[name(progress_bar)]
+                            gtk::ProgressBar {
+                                set_show_text: true,
+                            },
+                            gtk::Button {
+                                set_label: &fl!("create-report-dialog", "btn-cancel"),
+                                set_halign: gtk::Align::Center,
+                                connect_clicked => CreateReportDialogInput::PrivateMessage(PrivateMsg::CancelReport),
                             },
                         },
 
@@ -116,7 +101,10 @@ impl Component for CreateReportDialog {
                             set_title: &fl!("create-report-dialog", "success-title"),
                             add_css_class: "success",
                             #[watch]
-                            set_description: Some(&fl!("create-report-dialog", "success-description", file_name = model.file_name.clone(), support_mail = create_report_email_link(model.app_name))),
+                            set_description: Some(&fl!("create-report-dialog", "success-description", file_name = model.file_name.clone(), support_mail = model.support_contact().markup(&model.message_context()))),
+
+                            #[local_ref]
+                            send_group -> adw::PreferencesGroup {},
                         },
 
                         #[name(error_page)]
@@ -148,8 +136,12 @@ impl Component for CreateReportDialog {
     fn init(init: Self::Init, root: Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self> {
         let model = CreateReportDialog {
             app_name: init.app_name,
+            app_version: init.app_version,
             binary_name: init.binary_name,
+            support_contact: init.support_contact,
             file_name: String::default(),
+            cancellation: None,
+            send_group: SendReportGroup::builder().launch(()).detach(),
             file_chooser: {
                 let builder = SaveDialog::builder();
                 let widget = builder.widget();
@@ -183,6 +175,14 @@ impl Component for CreateReportDialog {
             },
         };
 
+        // A running upload or mail is cancelled with the dialog
+        let send_group_sender = model.send_group.sender().clone();
+        root.upcast_ref::<gtk::Window>().connect_close_request(move |_| {
+            send_group_sender.emit(SendReportGroupInput::Cancel);
+            glib::Propagation::Proceed
+        });
+
+        let send_group = model.send_group.widget();
         let widgets = view_output!();
         mxl_relm4_components::gtk::do_close_on_escape(root.upcast_ref::<gtk::Window>());
 
@@ -212,9 +212,42 @@ impl Component for CreateReportDialog {
                     self.file_name = path.to_string_lossy().to_string();
                     widgets.stack_view.set_transition_type(gtk::StackTransitionType::None);
                     widgets.stack_view.set_visible_child(&widgets.progress_page);
-                    sender.spawn_oneshot_command(move || crate::proc_dir::proc_dir_archive(&path));
+                    widgets.progress_bar.set_fraction(0.0);
+                    widgets.progress_bar.set_text(None);
+                    let contact = self.support_contact();
+                    let context = self.message_context();
+                    self.send_group.emit(SendReportGroupInput::SetReport {
+                        path: path.clone(),
+                        subject: contact.subject(&context),
+                        body: contact.body(&context),
+                    });
+                    let cancellation = CancellationToken::new();
+                    self.cancellation = Some(cancellation.clone());
+                    sender.spawn_command(move |out| {
+                        let mut last_percent = None;
+                        let progress_out = out.clone();
+                        let options = ArchiveOptions {
+                            progress: Some(Box::new(move |progress: &ArchiveProgress| {
+                                // Limit the number of updates sent to the user interface
+                                let percent = (progress.fraction() * 100.0) as u32;
+                                if last_percent != Some(percent) || progress.files_done == progress.files_total {
+                                    last_percent = Some(percent);
+                                    progress_out.send(CommandMsg::Progress(*progress)).unwrap_or_default();
+                                }
+                            })),
+                            cancellation: Some(cancellation),
+                            ..Default::default()
+                        };
+                        let result = crate::proc_dir::proc_dir_archive_with_options(&path, options).map(|_| ());
+                        out.send(CommandMsg::Finished(result)).unwrap_or_default();
+                    });
                     self.update_view(widgets, sender);
                 }
+                PrivateMsg::CancelReport => {
+                    if let Some(cancellation) = &self.cancellation {
+                        cancellation.cancel();
+                    }
+                }
             },
             CreateReportDialogInput::Present(transient_for) => {
                 widgets.stack_view.set_transition_type(gtk::StackTransitionType::None);
@@ -235,17 +268,38 @@ impl Component for CreateReportDialog {
         sender: ComponentSender<Self>,
         _root: &Self::Root,
     ) {
-        if let Err(err) = message {
-            widgets
-                .error_page
-                .set_description(Some(glib::markup_escape_text(&format!("{:?}", err)).as_str()));
-            sender.input(CreateReportDialogInput::PrivateMessage(PrivateMsg::SwitchForwardTo(
-                widgets.error_page.clone().into(),
-            )));
-        } else {
-            sender.input(CreateReportDialogInput::PrivateMessage(PrivateMsg::SwitchForwardTo(
-                widgets.success_page.clone().into(),
-            )));
+        match message {
+            CommandMsg::Progress(progress) => {
+                widgets.progress_bar.set_fraction(progress.fraction());
+                widgets.progress_bar.set_text(Some(&fl!(
+                    "create-report-dialog",
+                    "progress-files",
+                    files_done = progress.files_done,
+                    files_total = progress.files_total
+                )));
+            }
+            CommandMsg::Finished(result) => {
+                self.cancellation = None;
+                match result {
+                    Err(err) if err.is::<ArchiveCancelled>() => {
+                        widgets.stack_view.set_transition_type(gtk::StackTransitionType::None);
+                        widgets.stack_view.set_visible_child(&widgets.start_page);
+                    }
+                    Err(err) => {
+                        widgets
+                            .error_page
+                            .set_description(Some(glib::markup_escape_text(&format!("{:?}", err)).as_str()));
+                        sender.input(CreateReportDialogInput::PrivateMessage(PrivateMsg::SwitchForwardTo(
+                            widgets.error_page.clone().into(),
+                        )));
+                    }
+                    Ok(()) => {
+                        sender.input(CreateReportDialogInput::PrivateMessage(PrivateMsg::SwitchForwardTo(
+                            widgets.success_page.clone().into(),
+                        )));
+                    }
+                }
+            }
         }
     }
 }
@@ -0,0 +1,214 @@
+use crate::proc_dir::DUMP_FILE_EXTENSION;
+use anyhow::{Context, Result};
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Signal installed by default by [`diagnostic_dump_install_signal_handler`].
+#[cfg(target_os = "linux")]
+pub const DEFAULT_DIAGNOSTIC_DUMP_SIGNAL: i32 = libc::SIGUSR1;
+
+/// Writes a dump of the running process into the run directory and returns its path.
+///
+/// The dump contains the stacks of all threads, a system snapshot, the open file descriptors
+/// and the memory maps. A run with a dump is kept as failed run by [`crate::proc_dir::cleanup`].
+pub fn diagnostic_dump() -> Result<PathBuf> {
+    if !crate::disk_quota::diagnostics_allowed() {
+        anyhow::bail!("Diagnostic writers are stopped by the disk quota");
+    }
+    write_dump(crate::proc_dir::proc_dir())
+}
+
+fn write_dump(run_dir: &Path) -> Result<PathBuf> {
+    let now = SystemTime::now();
+    let mut out = String::new();
+    writeln!(out, "Diagnostic dump")?;
+    writeln!(out, "Time: {}", humantime::format_rfc3339(now))?;
+    writeln!(out, "Process: {}", std::process::id())?;
+    if let Ok(exe) = std::env::current_exe() {
+        writeln!(out, "Executable: {}", exe.to_string_lossy())?;
+    }
+
+    writeln!(out, "\n=> threads:")?;
+    match crate::thread_stacks::capture_thread_stacks() {
+        Ok(stacks) => out.push_str(&crate::thread_stacks::format_thread_stacks(&stacks)),
+        Err(err) => writeln!(out, "Thread stacks are not available: {err:#}")?,
+    }
+
+    #[cfg(feature = "sysinfo")]
+    {
+        writeln!(out, "\n=> system snapshot:")?;
+        out.push_str(&crate::system_snapshot::system_snapshot().to_text());
+    }
+
+    writeln!(out, "\n=> open file descriptors:")?;
+    match open_file_descriptors() {
+        Ok(fds) => {
+            for (fd, target) in fds {
+                writeln!(out, "{fd} -> {target}")?;
+            }
+        }
+        Err(err) => writeln!(out, "Open file descriptors are not available: {err:#}")?,
+    }
+
+    writeln!(out, "\n=> memory maps:")?;
+    match std::fs::read_to_string("/proc/self/maps") {
+        Ok(maps) => out.push_str(&maps),
+        Err(err) => writeln!(out, "Memory maps are not available: {err}")?,
+    }
+
+    let path = run_dir.join(format!("{}.{DUMP_FILE_EXTENSION}", humantime::format_rfc3339(now)));
+    std::fs::write(&path, out).with_context(|| format!("Cannot write file '{}'", path.to_string_lossy()))?;
+    log::info!("diagnostic dump written to '{}'", path.to_string_lossy());
+    Ok(path)
+}
+
+fn open_file_descriptors() -> Result<Vec<(u32, String)>> {
+    let mut fds = std::fs::read_dir("/proc/self/fd")
+        .with_context(|| "Cannot list open file descriptors")?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let fd = entry.file_name().to_str()?.parse::<u32>().ok()?;
+            // The descriptor of the listed directory itself may already be closed
+            let target = std::fs::read_link(entry.path()).ok()?;
+            Some((fd, target.to_string_lossy().to_string()))
+        })
+        .collect::<Vec<_>>();
+    fds.sort();
+    Ok(fds)
+}
+
+/// Writes a [`diagnostic_dump`] whenever the process receives the signal, e.g. by `kill -USR1 <pid>`.
+///
+/// The dump is written by a background thread, the signal handler only wakes it up.
+/// Only one signal handler can be installed.
+#[cfg(target_os = "linux")]
+pub fn diagnostic_dump_install_signal_handler(signal: i32) -> Result<()> {
+    signal_handler::install(signal)
+}
+
+#[cfg(target_os = "linux")]
+mod signal_handler {
+    use anyhow::{Context, Result};
+    use once_cell::sync::OnceCell;
+    use std::{
+        io::Read,
+        os::fd::FromRawFd,
+        sync::atomic::{AtomicI32, Ordering},
+    };
+
+    static INSTALLED: OnceCell<i32> = OnceCell::new();
+    // Write end of the pipe waking up the dump thread
+    static PIPE_WRITE_FD: AtomicI32 = AtomicI32::new(-1);
+
+    extern "C" fn dump_handler(_signal: libc::c_int) {
+        let fd = PIPE_WRITE_FD.load(Ordering::SeqCst);
+        if fd >= 0 {
+            // SAFETY: write is async-signal-safe, a full pipe only drops the wake-up of an already pending dump.
+            unsafe {
+                libc::write(fd, [1u8].as_ptr().cast(), 1);
+            }
+        }
+    }
+
+    pub(super) fn install(signal: i32) -> Result<()> {
+        let installed = *INSTALLED.get_or_try_init(|| {
+            let mut fds = [0; 2];
+            // SAFETY: Creates a pipe, the descriptors are owned by the dump thread and the handler.
+            if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) } != 0 {
+                return Err(std::io::Error::last_os_error()).with_context(|| "Cannot create pipe");
+            }
+            // SAFETY: The read end is only used by this file, blocking reads are restored below.
+            let mut reader = unsafe {
+                libc::fcntl(fds[0], libc::F_SETFL, 0);
+                std::fs::File::from_raw_fd(fds[0])
+            };
+            PIPE_WRITE_FD.store(fds[1], Ordering::SeqCst);
+            std::thread::Builder::new()
+                .name("diagnostic-dump".to_string())
+                .spawn(move || {
+                    let mut buffer = [0u8; 64];
+                    while matches!(reader.read(&mut buffer), Ok(count) if count > 0) {
+                        if let Err(err) = super::diagnostic_dump() {
+                            log::warn!("Cannot write diagnostic dump: {err:?}");
+                        }
+                    }
+                })
+                .with_context(|| "Cannot start diagnostic dump thread")?;
+
+            // SAFETY: The handler only writes into the pipe.
+            let result = unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = dump_handler as extern "C" fn(libc::c_int) as libc::sighandler_t;
+                action.sa_flags = libc::SA_RESTART;
+                libc::sigemptyset(&mut action.sa_mask);
+                libc::sigaction(signal, &action, std::ptr::null_mut())
+            };
+            if result != 0 {
+                return Err(std::io::Error::last_os_error())
+                    .with_context(|| format!("Cannot install handler of signal {signal}"));
+            }
+            Ok::<_, anyhow::Error>(signal)
+        })?;
+        if installed != signal {
+            anyhow::bail!("The diagnostic dump handler is already installed for signal {installed}");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proc_dir::LOCK_FILE_NAME;
+
+    #[test]
+    fn dump_keeps_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let run_dir = dir.path().join("2024-01-01_10_00_00");
+        std::fs::create_dir(&run_dir).unwrap();
+        std::fs::write(run_dir.join(LOCK_FILE_NAME), "").unwrap();
+
+        let path = write_dump(&run_dir).unwrap();
+        assert_eq!(path.parent(), Some(run_dir.as_path()));
+        assert_eq!(path.extension().unwrap(), DUMP_FILE_EXTENSION);
+        let dump = std::fs::read_to_string(&path).unwrap();
+        assert!(dump.starts_with("Diagnostic dump\nTime: "), "{dump}");
+        assert!(dump.contains(&format!("\nProcess: {}\n", std::process::id())), "{dump}");
+        let mut sections = vec![
+            "\n=> threads:\n",
+            "\n=> open file descriptors:\n",
+            "\n=> memory maps:\n",
+        ];
+        if cfg!(feature = "sysinfo") {
+            sections.insert(1, "\n=> system snapshot:\n=> snapshot: ");
+        }
+        let positions = sections
+            .iter()
+            .map(|section| {
+                dump.find(section)
+                    .unwrap_or_else(|| panic!("{section:?} missing in {dump}"))
+            })
+            .collect::<Vec<_>>();
+        assert!(positions.is_sorted(), "{dump}");
+        #[cfg(target_os = "linux")]
+        {
+            // The stack of the dumping thread is included
+            assert!(dump.contains("diagnostic_dump::write_dump"), "{dump}");
+            assert!(dump.contains(" -> /"), "{dump}");
+        }
+
+        // A run with a dump is kept as failed run
+        crate::proc_dir::cleanup_run_dir(&run_dir).unwrap();
+        assert!(path.exists());
+        assert!(!run_dir.join(LOCK_FILE_NAME).exists());
+
+        // Without a dump the run is removed
+        std::fs::remove_file(&path).unwrap();
+        std::fs::write(run_dir.join(LOCK_FILE_NAME), "").unwrap();
+        crate::proc_dir::cleanup_run_dir(&run_dir).unwrap();
+        assert!(!run_dir.exists());
+    }
+}
@@ -0,0 +1,354 @@
+use crate::{
+    build_info::BUILD_INFO_FILE_NAME,
+    proc_dir::{DUMP_FILE_EXTENSION, HANG_FILE_EXTENSION, LOCK_FILE_NAME, PANIC_FILE_EXTENSION, REPORT_FILE_NAME},
+};
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        RwLock,
+    },
+    time::{Duration, SystemTime},
+};
+use walkdir::WalkDir;
+
+/// File in the run directory with the disk quota events, which are added to the exit report.
+pub(crate) const DISK_QUOTA_FILE_NAME: &str = "disk_quota.txt";
+/// Shortest interval of the background checks, shorter intervals are raised to it.
+pub const DISK_QUOTA_MIN_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+static DISK_QUOTA: Lazy<RwLock<DiskQuota>> = Lazy::new(|| RwLock::new(DiskQuota::default()));
+static DIAGNOSTICS_STOPPED: AtomicBool = AtomicBool::new(false);
+// Events of the free space check, which happens before the run directory exists
+static PENDING_EVENTS: Lazy<RwLock<Vec<String>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+/// Reaction if the free space is too low or the run directory exceeds its quota.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DiskQuotaReaction {
+    /// Only logs a warning.
+    #[default]
+    Warn,
+    /// Stops the diagnostic writers of this crate for the rest of the run, see [`diagnostics_allowed`].
+    StopWriters,
+    /// Removes the oldest files of the run directory until it is below its quota again.
+    ///
+    /// Stops the diagnostic writers instead if the free space is too low while the quota is not exceeded.
+    /// The lock file, the exit report, panic and hang files are never removed.
+    /// The space of files still opened by a writer may be released only when they are closed.
+    Rotate,
+}
+
+/// Limits of the disk usage by the run directory, checked by [`crate::proc_dir::proc_dir`] and in the background.
+#[derive(Debug, Clone)]
+pub struct DiskQuota {
+    /// Minimum free space in bytes of the file system of the proc directory.
+    pub min_free_space: Option<u64>,
+    /// Maximum size of the run directory in bytes.
+    pub max_run_size: Option<u64>,
+    pub reaction: DiskQuotaReaction,
+    /// Interval of the background checks, at least [`DISK_QUOTA_MIN_CHECK_INTERVAL`].
+    pub check_interval: Duration,
+}
+
+impl Default for DiskQuota {
+    fn default() -> Self {
+        Self {
+            min_free_space: None,
+            max_run_size: None,
+            reaction: DiskQuotaReaction::default(),
+            check_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+impl DiskQuota {
+    fn is_enabled(&self) -> bool {
+        self.min_free_space.is_some() || self.max_run_size.is_some()
+    }
+
+    // A zero interval would check without pause
+    fn effective_check_interval(&self) -> Duration {
+        self.check_interval.max(DISK_QUOTA_MIN_CHECK_INTERVAL)
+    }
+}
+
+/// Sets the disk quota, must be called before [`crate::proc_dir::proc_dir`] is called the first time.
+pub fn disk_quota_set_config(quota: DiskQuota) {
+    *DISK_QUOTA.write().unwrap() = quota;
+}
+
+/// Returns `false` if diagnostic writers are stopped by [`DiskQuotaReaction::StopWriters`].
+///
+/// Writers of the host into the run directory, e.g. loggers, should check it before writing.
+pub fn diagnostics_allowed() -> bool {
+    !DIAGNOSTICS_STOPPED.load(Ordering::Relaxed)
+}
+
+/// Checks the free space before the run directory is created, a failing check only triggers the reaction.
+pub(crate) fn check_free_space(proc_dir: &Path) {
+    let quota = DISK_QUOTA.read().unwrap().clone();
+    let Some(min_free_space) = quota.min_free_space else {
+        return;
+    };
+    match fs4::available_space(proc_dir) {
+        Ok(available) if available < min_free_space => {
+            let event = format!("Free space {available} bytes is below the minimum of {min_free_space} bytes");
+            log::warn!("{event}");
+            // Nothing can be rotated yet, so the writers are stopped instead
+            if quota.reaction != DiskQuotaReaction::Warn {
+                DIAGNOSTICS_STOPPED.store(true, Ordering::Relaxed);
+            }
+            PENDING_EVENTS.write().unwrap().push(event);
+        }
+        Ok(_) => {}
+        Err(err) => log::warn!("Cannot get free space of '{}': {err:?}", proc_dir.to_string_lossy()),
+    }
+}
+
+/// Records the events of the free space check and starts the background checks of the run directory.
+pub(crate) fn start_monitor(run_dir: &Path) {
+    for event in std::mem::take(&mut *PENDING_EVENTS.write().unwrap()) {
+        record_event(run_dir, &event);
+    }
+    let quota = DISK_QUOTA.read().unwrap().clone();
+    if !quota.is_enabled() {
+        return;
+    }
+    let run_dir = run_dir.to_path_buf();
+    let spawned = std::thread::Builder::new()
+        .name("disk-quota".to_string())
+        .spawn(move || monitor(&run_dir, &quota));
+    if let Err(err) = spawned {
+        log::warn!("Cannot start disk quota monitor: {err:?}");
+    }
+}
+
+/// Limit exceeded by a check with its description.
+enum Exceeded {
+    RunSize(String),
+    FreeSpace(String),
+}
+
+fn monitor(run_dir: &Path, quota: &DiskQuota) {
+    let mut exceeded = false;
+    // The run directory is removed by cleanup at the end of the run
+    while run_dir.is_dir() {
+        std::thread::sleep(quota.effective_check_interval());
+        match check(run_dir, quota) {
+            Ok(Some(limit)) => {
+                // Only rotation is repeated, other reactions are recorded once while the limit is exceeded
+                let rotate = quota.reaction == DiskQuotaReaction::Rotate && matches!(limit, Exceeded::RunSize(_));
+                if !exceeded || rotate {
+                    react(run_dir, quota, limit);
+                }
+                exceeded = true;
+            }
+            Ok(None) => exceeded = false,
+            Err(err) => log::warn!("Cannot check disk quota: {err:?}"),
+        }
+    }
+}
+
+fn check(run_dir: &Path, quota: &DiskQuota) -> Result<Option<Exceeded>> {
+    if let Some(max_run_size) = quota.max_run_size {
+        let size = dir_size(run_dir);
+        if size > max_run_size {
+            return Ok(Some(Exceeded::RunSize(format!(
+                "Run directory size {size} bytes exceeds the quota of {max_run_size} bytes"
+            ))));
+        }
+    }
+    if let Some(min_free_space) = quota.min_free_space {
+        let available = fs4::available_space(run_dir)
+            .with_context(|| format!("Cannot get free space of '{}'", run_dir.to_string_lossy()))?;
+        if available < min_free_space {
+            return Ok(Some(Exceeded::FreeSpace(format!(
+                "Free space {available} bytes is below the minimum of {min_free_space} bytes"
+            ))));
+        }
+    }
+    Ok(None)
+}
+
+fn react(run_dir: &Path, quota: &DiskQuota, limit: Exceeded) {
+    let event = match &limit {
+        Exceeded::RunSize(event) | Exceeded::FreeSpace(event) => event,
+    };
+    log::warn!("{event}");
+    match (quota.reaction, quota.max_run_size) {
+        (DiskQuotaReaction::Warn, _) => record_event(run_dir, &format!("{event}, warned")),
+        (DiskQuotaReaction::Rotate, Some(max_run_size)) if matches!(limit, Exceeded::RunSize(_)) => {
+            match rotate(run_dir, max_run_size) {
+                Ok(removed) => record_event(run_dir, &format!("{event}, removed {removed} files")),
+                Err(err) => {
+                    log::warn!("Cannot rotate run directory: {err:?}");
+                    record_event(run_dir, &format!("{event}, rotation failed: {err:#}"));
+                }
+            }
+        }
+        // Low free space is not caused by the run directory alone, removing its files would not help
+        (DiskQuotaReaction::StopWriters | DiskQuotaReaction::Rotate, _) => stop_writers(run_dir, event),
+    }
+}
+
+fn stop_writers(run_dir: &Path, event: &str) {
+    DIAGNOSTICS_STOPPED.store(true, Ordering::Relaxed);
+    record_event(run_dir, &format!("{event}, diagnostic writers stopped"));
+}
+
+/// Returns the size of all files in the directory.
+pub(crate) fn dir_size(dir: &Path) -> u64 {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+fn is_protected(run_dir: &Path, path: &Path) -> bool {
+    let Ok(relative) = path.strip_prefix(run_dir) else {
+        return true;
+    };
+    relative == Path::new(LOCK_FILE_NAME)
+        || relative == Path::new(REPORT_FILE_NAME)
+        || relative == Path::new(DISK_QUOTA_FILE_NAME)
+        || relative == Path::new(BUILD_INFO_FILE_NAME)
+        || is_resource_samples(relative)
+        || path
+            .extension()
+            .is_some_and(|ext| ext == PANIC_FILE_EXTENSION || ext == HANG_FILE_EXTENSION || ext == DUMP_FILE_EXTENSION)
+        || path
+            .to_string_lossy()
+            .ends_with(crate::panic_record::PANIC_DETAILS_FILE_SUFFIX)
+}
+
+// The samples show how the run grew until it exceeded the quota, including the rotated files of the sampler
+fn is_resource_samples(relative: &Path) -> bool {
+    #[cfg(feature = "sysinfo")]
+    return relative
+        .to_str()
+        .and_then(|name| name.strip_prefix(crate::resource_sampler::RESOURCES_FILE_STEM))
+        .is_some_and(|rest| rest.starts_with('.'));
+    #[cfg(not(feature = "sysinfo"))]
+    {
+        _ = relative;
+        false
+    }
+}
+
+/// Removes the oldest files until the run directory is not larger than the target size.
+///
+/// Returns the number of removed files.
+fn rotate(run_dir: &Path, target_size: u64) -> Result<usize> {
+    let mut files: Vec<(SystemTime, u64, PathBuf)> = WalkDir::new(run_dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file() && !is_protected(run_dir, entry.path()))
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some((metadata.modified().ok()?, metadata.len(), entry.into_path()))
+        })
+        .collect();
+    files.sort();
+
+    let mut size = dir_size(run_dir);
+    let mut removed = 0;
+    for (_, len, path) in files {
+        if size <= target_size {
+            break;
+        }
+        std::fs::remove_file(&path).with_context(|| format!("Cannot remove file '{}'", path.to_string_lossy()))?;
+        size = size.saturating_sub(len);
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+fn record_event(run_dir: &Path, event: &str) {
+    let path = run_dir.join(DISK_QUOTA_FILE_NAME);
+    let time = humantime::format_rfc3339_seconds(SystemTime::now());
+    let result = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| writeln!(file, "{time}: {event}"))
+        .with_context(|| format!("Cannot write file '{}'", path.to_string_lossy()));
+    if let Err(err) = result {
+        log::warn!("{err:?}");
+    }
+}
+
+/// Returns the recorded disk quota events of a run directory as section of the exit report.
+pub(crate) fn exit_report_section(run_dir: &Path) -> Option<String> {
+    let events = std::fs::read_to_string(run_dir.join(DISK_QUOTA_FILE_NAME)).ok()?;
+    Some(format!("\nDisk quota events:\n{events}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_file(path: &Path, len: usize, age_secs: u64) {
+        std::fs::write(path, vec![b'x'; len]).unwrap();
+        let modified = SystemTime::now() - Duration::from_secs(age_secs);
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+
+    #[test]
+    fn minimum_check_interval() {
+        let quota = DiskQuota {
+            check_interval: Duration::ZERO,
+            ..Default::default()
+        };
+        assert_eq!(quota.effective_check_interval(), DISK_QUOTA_MIN_CHECK_INTERVAL);
+        assert_eq!(DiskQuota::default().effective_check_interval(), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn rotate_oldest_unprotected_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let run_dir = dir.path();
+        let mut protected = vec![
+            LOCK_FILE_NAME.to_string(),
+            REPORT_FILE_NAME.to_string(),
+            BUILD_INFO_FILE_NAME.to_string(),
+            format!("main.{PANIC_FILE_EXTENSION}"),
+            format!("main{}", crate::panic_record::PANIC_DETAILS_FILE_SUFFIX),
+            format!("main.{HANG_FILE_EXTENSION}"),
+            format!("process.{DUMP_FILE_EXTENSION}"),
+        ];
+        if cfg!(feature = "sysinfo") {
+            protected.extend(["resources.csv".to_string(), "resources.1.csv".to_string()]);
+        }
+        for name in protected.iter() {
+            write_file(&run_dir.join(name), 100, 300);
+        }
+        write_file(&run_dir.join("oldest.log"), 1000, 200);
+        write_file(&run_dir.join("older.log"), 1000, 100);
+        write_file(&run_dir.join("newest.log"), 1000, 0);
+
+        // Only the two oldest logs need to be removed to reach the target size
+        let protected_size = 100 * protected.len() as u64;
+        assert_eq!(rotate(run_dir, protected_size + 1000).unwrap(), 2);
+        assert!(!run_dir.join("oldest.log").exists());
+        assert!(!run_dir.join("older.log").exists());
+        assert!(run_dir.join("newest.log").exists());
+
+        // Protected files are kept even if the target size cannot be reached
+        assert_eq!(rotate(run_dir, 0).unwrap(), 1);
+        for name in protected.iter() {
+            assert!(run_dir.join(name).exists(), "{name}");
+        }
+        assert_eq!(dir_size(run_dir), protected_size);
+    }
+}
@@ -0,0 +1,101 @@
+#[cfg(feature = "encryption")]
+use anyhow::{Context, Result};
+#[cfg(feature = "encryption")]
+use std::path::Path;
+
+/// File extension appended to archives encrypted with a public key.
+pub const ENCRYPTED_ARCHIVE_FILE_EXTENSION: &str = "age";
+
+/// Encryption of a created archive.
+///
+/// Further variants are available with the `encryption` feature.
+#[derive(Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ArchiveEncryption {
+    /// Encrypts all files inside the zip archive with AES-256 and the given passphrase.
+    Passphrase(String),
+    /// Encrypts the whole zip archive with the given [age](https://age-encryption.org) public key,
+    /// e.g. a key of the support team embedded in the application.
+    #[cfg(feature = "encryption")]
+    PublicKey(String),
+}
+
+impl std::fmt::Debug for ArchiveEncryption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Passphrase(_) => f.write_str("Passphrase(..)"),
+            #[cfg(feature = "encryption")]
+            Self::PublicKey(key) => f.debug_tuple("PublicKey").field(key).finish(),
+        }
+    }
+}
+
+/// Secret to open an encrypted archive.
+///
+/// Further variants are available with the `encryption` feature.
+#[derive(Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ArchiveDecryption {
+    /// Passphrase of an archive created with [`ArchiveEncryption::Passphrase`].
+    Passphrase(String),
+    /// Secret age identity matching the public key of an archive created with [`ArchiveEncryption::PublicKey`].
+    #[cfg(feature = "encryption")]
+    Identity(String),
+}
+
+impl std::fmt::Debug for ArchiveDecryption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Passphrase(_) => f.write_str("Passphrase(..)"),
+            #[cfg(feature = "encryption")]
+            Self::Identity(_) => f.write_str("Identity(..)"),
+        }
+    }
+}
+
+#[cfg(feature = "encryption")]
+pub(crate) fn parse_recipient(public_key: &str) -> Result<age::x25519::Recipient> {
+    public_key
+        .parse::<age::x25519::Recipient>()
+        .map_err(|err| anyhow::anyhow!("Invalid public key '{public_key}': {err}"))
+}
+
+#[cfg(feature = "encryption")]
+pub(crate) fn parse_identity(identity: &str) -> Result<age::x25519::Identity> {
+    identity
+        .parse::<age::x25519::Identity>()
+        .map_err(|err| anyhow::anyhow!("Invalid identity: {err}"))
+}
+
+/// Encrypts the file `src` with the given public key into the file `dst`.
+#[cfg(feature = "encryption")]
+pub(crate) fn encrypt_file(src: &Path, dst: &Path, public_key: &str) -> Result<()> {
+    let recipient = parse_recipient(public_key)?;
+    let encryptor = age::Encryptor::with_recipients(std::iter::once(&recipient as &dyn age::Recipient))
+        .with_context(|| "Cannot create encryptor")?;
+
+    let mut input =
+        std::fs::File::open(src).with_context(|| format!("Cannot open file '{}'", src.to_string_lossy()))?;
+    let output =
+        std::fs::File::create(dst).with_context(|| format!("Cannot create file '{}'", dst.to_string_lossy()))?;
+    let mut writer = encryptor
+        .wrap_output(output)
+        .with_context(|| format!("Cannot write file '{}'", dst.to_string_lossy()))?;
+    std::io::copy(&mut input, &mut writer)
+        .with_context(|| format!("Cannot encrypt file '{}'", src.to_string_lossy()))?;
+    writer
+        .finish()
+        .with_context(|| format!("Cannot finish file '{}'", dst.to_string_lossy()))?;
+    Ok(())
+}
+
+/// Decrypts an archive encrypted with [`ArchiveEncryption::PublicKey`] into a plain zip archive.
+#[cfg(feature = "encryption")]
+pub fn decrypt_archive(encrypted_path: &Path, identity: &str, archive_path: &Path) -> Result<()> {
+    let mut reader = crate::report::open_encrypted(encrypted_path, identity)?;
+    let mut output = std::fs::File::create(archive_path)
+        .with_context(|| format!("Cannot create file '{}'", archive_path.to_string_lossy()))?;
+    std::io::copy(&mut reader, &mut output)
+        .with_context(|| format!("Cannot decrypt file '{}'", encrypted_path.to_string_lossy()))?;
+    Ok(())
+}
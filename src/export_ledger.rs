@@ -0,0 +1,187 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+pub(crate) const EXPORT_LEDGER_FILE_NAME: &str = "export_ledger.json";
+// The oldest records are removed if the ledger grows beyond this number of records
+const KEEP_NUMBER_OF_EXPORT_RECORDS: usize = 200;
+
+/// Where the runs of an archive were exported to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ExportDestination {
+    /// Written to the archive files.
+    File,
+    /// Uploaded to the URL.
+    Upload { url: String },
+    /// Sent by mail to the recipients.
+    Mail { recipients: Vec<String> },
+}
+
+/// Export of runs, the runs are identified by the names of their run directories.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportRecord {
+    /// Time of the export as RFC 3339 timestamp.
+    pub time: String,
+    pub runs: Vec<String>,
+    /// Written archive file or its volumes.
+    pub archive: Vec<PathBuf>,
+    pub destination: ExportDestination,
+}
+
+fn ledger_file_path() -> PathBuf {
+    crate::misc::get_data_dir().join(EXPORT_LEDGER_FILE_NAME)
+}
+
+/// Returns all recorded exports, the oldest first.
+pub fn export_ledger() -> Result<Vec<ExportRecord>> {
+    read_ledger(&ledger_file_path())
+}
+
+fn read_ledger(path: &Path) -> Result<Vec<ExportRecord>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = std::fs::read(path).with_context(|| format!("Cannot read file '{}'", path.to_string_lossy()))?;
+    serde_json::from_slice(&content).with_context(|| format!("Cannot parse file '{}'", path.to_string_lossy()))
+}
+
+fn write_ledger(path: &Path, records: &[ExportRecord]) -> Result<()> {
+    let content = serde_json::to_vec_pretty(records)?;
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, content)
+        .with_context(|| format!("Cannot write file '{}'", tmp_path.to_string_lossy()))?;
+    std::fs::rename(&tmp_path, path).with_context(|| format!("Cannot write file '{}'", path.to_string_lossy()))
+}
+
+fn add_record(path: &Path, record: ExportRecord) -> Result<()> {
+    let mut records = read_ledger(path)?;
+    records.push(record);
+    let excess = records.len().saturating_sub(KEEP_NUMBER_OF_EXPORT_RECORDS);
+    records.drain(..excess);
+    write_ledger(path, &records)
+}
+
+/// Returns the names of all runs that were exported at least once.
+pub fn export_ledger_exported_runs() -> Result<HashSet<String>> {
+    Ok(export_ledger()?
+        .into_iter()
+        .flat_map(|record| record.runs.into_iter())
+        .collect())
+}
+
+pub fn export_ledger_is_exported(run: &str) -> Result<bool> {
+    Ok(export_ledger()?
+        .iter()
+        .any(|record| record.runs.iter().any(|exported_run| exported_run == run)))
+}
+
+/// Records the runs written to an archive.
+pub(crate) fn export_ledger_add_archive(runs: Vec<String>, archive: &[PathBuf]) {
+    if runs.is_empty() {
+        return;
+    }
+    let record = ExportRecord {
+        time: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        runs,
+        archive: archive.to_vec(),
+        destination: ExportDestination::File,
+    };
+    // A failing ledger must not fail the export itself
+    if let Err(err) = add_record(&ledger_file_path(), record) {
+        log::warn!("Cannot record export: {err:?}");
+    }
+}
+
+/// Records that a previously written archive was sent, e.g. uploaded or sent by mail.
+///
+/// The runs are taken from the record of the archive, nothing is recorded for unknown archives.
+pub fn export_ledger_record_sent(archive_file_path: &Path, destination: ExportDestination) -> Result<()> {
+    record_sent(&ledger_file_path(), archive_file_path, destination)
+}
+
+fn record_sent(path: &Path, archive_file_path: &Path, destination: ExportDestination) -> Result<()> {
+    let runs = read_ledger(path)?
+        .into_iter()
+        .rev()
+        .find(|record| record.archive.iter().any(|file| file == archive_file_path))
+        .map(|record| record.runs)
+        .unwrap_or_default();
+    if runs.is_empty() {
+        return Ok(());
+    }
+    add_record(
+        path,
+        ExportRecord {
+            time: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            runs,
+            archive: vec![archive_file_path.to_path_buf()],
+            destination,
+        },
+    )
+}
+
+/// Removes all records, all runs are exported again by the "only not yet exported" mode.
+pub fn export_ledger_clear() -> Result<()> {
+    let path = ledger_file_path();
+    if path.exists() {
+        std::fs::remove_file(&path).with_context(|| format!("Cannot remove file '{}'", path.to_string_lossy()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(runs: &[&str], archive: &str) -> ExportRecord {
+        ExportRecord {
+            time: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            runs: runs.iter().map(|run| run.to_string()).collect(),
+            archive: vec![PathBuf::from(archive)],
+            destination: ExportDestination::File,
+        }
+    }
+
+    #[test]
+    fn prune_oldest_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(EXPORT_LEDGER_FILE_NAME);
+        for index in 0..KEEP_NUMBER_OF_EXPORT_RECORDS + 5 {
+            add_record(&path, record(&[&format!("run-{index}")], "report.zip")).unwrap();
+        }
+        let records = read_ledger(&path).unwrap();
+        assert_eq!(records.len(), KEEP_NUMBER_OF_EXPORT_RECORDS);
+        assert_eq!(records[0].runs, ["run-5"]);
+        assert_eq!(
+            records.last().unwrap().runs,
+            [format!("run-{}", KEEP_NUMBER_OF_EXPORT_RECORDS + 4)]
+        );
+    }
+
+    #[test]
+    fn record_sent_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(EXPORT_LEDGER_FILE_NAME);
+        add_record(&path, record(&["run-1", "run-2"], "first.zip")).unwrap();
+        add_record(&path, record(&["run-3"], "second.zip")).unwrap();
+
+        let upload = ExportDestination::Upload {
+            url: "https://example.com/reports".to_string(),
+        };
+        record_sent(&path, Path::new("first.zip"), upload.clone()).unwrap();
+        let records = read_ledger(&path).unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[2].runs, ["run-1", "run-2"]);
+        assert_eq!(records[2].archive, [PathBuf::from("first.zip")]);
+        assert_eq!(records[2].destination, upload);
+
+        // Archives without a record are not recorded
+        record_sent(&path, Path::new("unknown.zip"), upload).unwrap();
+        assert_eq!(read_ledger(&path).unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,238 @@
+use crate::proc_dir::HANG_FILE_EXTENSION;
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
+};
+
+static WATCHDOG_RUNNING: AtomicBool = AtomicBool::new(false);
+static WATCHDOG_THREAD: Lazy<Mutex<Option<JoinHandle<()>>>> = Lazy::new(|| Mutex::new(None));
+static EPOCH: Lazy<Instant> = Lazy::new(Instant::now);
+// Milliseconds since the epoch of the last heartbeat
+static LAST_HEARTBEAT: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone)]
+pub struct HangWatchdogConfig {
+    /// Time without heartbeat after which the application is considered hanging.
+    pub threshold: Duration,
+    /// Interval the heartbeats are checked in.
+    pub check_interval: Duration,
+    /// Captures the stacks of all threads into the hang record, see [`crate::thread_stacks`].
+    pub capture_stacks: bool,
+}
+
+impl Default for HangWatchdogConfig {
+    fn default() -> Self {
+        Self {
+            threshold: Duration::from_secs(10),
+            check_interval: Duration::from_secs(1),
+            capture_stacks: true,
+        }
+    }
+}
+
+fn now_millis() -> u64 {
+    EPOCH.elapsed().as_millis() as u64
+}
+
+/// Signals that the application is responsive, e.g. called by a timer of the GTK main loop.
+pub fn hang_watchdog_heartbeat() {
+    LAST_HEARTBEAT.store(now_millis(), Ordering::SeqCst);
+}
+
+/// Starts the watchdog, which writes a `.hang` record into the run directory if no heartbeat arrives
+/// within the threshold.
+///
+/// A run with a hang record is kept as failed run by [`crate::proc_dir::cleanup`].
+/// Does nothing if the watchdog is already running.
+pub fn hang_watchdog_start(config: HangWatchdogConfig) -> Result<()> {
+    if WATCHDOG_RUNNING.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+    let run_dir = crate::proc_dir::proc_dir().clone();
+    hang_watchdog_heartbeat();
+    let spawned = std::thread::Builder::new()
+        .name("hang-watchdog".to_string())
+        .spawn(move || watch(&run_dir, &config));
+    match spawned {
+        Ok(handle) => {
+            *WATCHDOG_THREAD.lock().unwrap() = Some(handle);
+            Ok(())
+        }
+        Err(err) => {
+            WATCHDOG_RUNNING.store(false, Ordering::SeqCst);
+            Err(err).with_context(|| "Cannot start hang watchdog")
+        }
+    }
+}
+
+/// Stops the watchdog and waits until its thread has finished.
+pub fn hang_watchdog_stop() {
+    WATCHDOG_RUNNING.store(false, Ordering::SeqCst);
+    if let Some(handle) = WATCHDOG_THREAD.lock().unwrap().take() {
+        handle.thread().unpark();
+        if handle.join().is_err() {
+            log::warn!("Hang watchdog thread panicked");
+        }
+    }
+}
+
+pub fn hang_watchdog_is_running() -> bool {
+    WATCHDOG_RUNNING.load(Ordering::SeqCst)
+}
+
+fn watch(run_dir: &Path, config: &HangWatchdogConfig) {
+    // Record of the current hang, which is completed when the heartbeats arrive again
+    let mut hang: Option<(PathBuf, u64)> = None;
+    while WATCHDOG_RUNNING.load(Ordering::SeqCst) && run_dir.is_dir() {
+        std::thread::park_timeout(config.check_interval);
+        if !WATCHDOG_RUNNING.load(Ordering::SeqCst) {
+            break;
+        }
+        let last_heartbeat = LAST_HEARTBEAT.load(Ordering::SeqCst);
+        let elapsed = Duration::from_millis(now_millis().saturating_sub(last_heartbeat));
+        match hang.as_ref() {
+            None if elapsed > config.threshold => {
+                log::warn!(
+                    "No heartbeat since {}, writing hang record",
+                    humantime::format_duration(elapsed)
+                );
+                match write_hang_record(run_dir, config, elapsed) {
+                    Ok(path) => hang = Some((path, last_heartbeat)),
+                    Err(err) => {
+                        log::warn!("Cannot write hang record: {err:?}");
+                        // Do not retry on every check
+                        hang = Some((PathBuf::new(), last_heartbeat));
+                    }
+                }
+            }
+            Some((path, hang_heartbeat)) if last_heartbeat != *hang_heartbeat => {
+                let duration = Duration::from_millis(last_heartbeat.saturating_sub(*hang_heartbeat));
+                log::info!(
+                    "Heartbeat received again after {}",
+                    humantime::format_duration(duration)
+                );
+                if !path.as_os_str().is_empty() {
+                    append_recovery(path, duration);
+                }
+                hang = None;
+            }
+            _ => {}
+        }
+    }
+    WATCHDOG_RUNNING.store(false, Ordering::SeqCst);
+}
+
+fn write_hang_record(run_dir: &Path, config: &HangWatchdogConfig, elapsed: Duration) -> Result<PathBuf> {
+    let now = SystemTime::now();
+    let mut out = String::new();
+    out.push_str("The application did not respond.\n");
+    out.push_str(&format!("Time: {}\n", humantime::format_rfc3339(now)));
+    out.push_str(&format!(
+        "Last heartbeat: {}\n",
+        humantime::format_rfc3339(now - elapsed)
+    ));
+    out.push_str(&format!("No heartbeat for: {}\n", humantime::format_duration(elapsed)));
+    out.push_str(&format!(
+        "Threshold: {}\n\n",
+        humantime::format_duration(config.threshold)
+    ));
+    if config.capture_stacks {
+        match crate::thread_stacks::capture_thread_stacks() {
+            Ok(stacks) => out.push_str(&crate::thread_stacks::format_thread_stacks(&stacks)),
+            Err(err) => out.push_str(&format!("Thread stacks are not available: {err:#}\n")),
+        }
+    }
+
+    let path = run_dir.join(format!("{}.{HANG_FILE_EXTENSION}", humantime::format_rfc3339(now)));
+    std::fs::write(&path, out).with_context(|| format!("Cannot write file '{}'", path.to_string_lossy()))?;
+    Ok(path)
+}
+
+fn append_recovery(path: &Path, duration: Duration) {
+    let result = std::fs::OpenOptions::new()
+        .append(true)
+        .open(path)
+        .and_then(|mut file| {
+            writeln!(
+                file,
+                "The application responded again after {} without heartbeat.",
+                humantime::format_duration(duration)
+            )
+        });
+    if let Err(err) = result {
+        log::warn!("Cannot write file '{}': {err:?}", path.to_string_lossy());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hang_records(run_dir: &Path) -> Vec<PathBuf> {
+        std::fs::read_dir(run_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == HANG_FILE_EXTENSION))
+            .collect()
+    }
+
+    fn wait_for(condition: impl Fn() -> bool) {
+        let start = Instant::now();
+        while !condition() {
+            assert!(start.elapsed() < Duration::from_secs(10), "timed out");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn heartbeat_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let run_dir = dir.path().join("run");
+        std::fs::create_dir(&run_dir).unwrap();
+        std::fs::write(run_dir.join(crate::proc_dir::LOCK_FILE_NAME), "").unwrap();
+        let config = HangWatchdogConfig {
+            threshold: Duration::from_millis(100),
+            check_interval: Duration::from_millis(10),
+            capture_stacks: true,
+        };
+
+        hang_watchdog_heartbeat();
+        WATCHDOG_RUNNING.store(true, Ordering::SeqCst);
+        let watchdog = {
+            let run_dir = run_dir.clone();
+            std::thread::spawn(move || watch(&run_dir, &config))
+        };
+        // No heartbeat arrives, so the watchdog writes a record with the stacks
+        wait_for(|| hang_records(&run_dir).len() == 1);
+        let path = hang_records(&run_dir).remove(0);
+        wait_for(|| {
+            std::fs::read_to_string(&path)
+                .unwrap()
+                .contains("hang_watchdog::tests::heartbeat_timeout")
+        });
+
+        hang_watchdog_heartbeat();
+        wait_for(|| {
+            std::fs::read_to_string(&path)
+                .unwrap()
+                .contains("The application responded again")
+        });
+        WATCHDOG_RUNNING.store(false, Ordering::SeqCst);
+        watchdog.thread().unpark();
+        watchdog.join().unwrap();
+        assert_eq!(hang_records(&run_dir).len(), 1);
+
+        // The run with the hang record is kept for the next start
+        crate::proc_dir::cleanup_run_dir(&run_dir).unwrap();
+        assert!(path.exists());
+        assert!(!run_dir.join(crate::proc_dir::LOCK_FILE_NAME).exists());
+    }
+}
@@ -1,6 +1,34 @@
+pub mod archive_filter;
+pub mod build_info;
+pub mod consent;
+pub mod diagnostic_dump;
+pub mod disk_quota;
+pub mod encryption;
+pub mod export_ledger;
+pub mod hang_watchdog;
 mod localization;
+pub mod manifest;
 pub mod misc;
+#[cfg(feature = "upload")]
+pub mod outbox;
+pub mod panic_record;
+pub mod problem_report;
 pub mod proc_dir;
+pub mod redaction;
+pub mod report;
+#[cfg(feature = "sysinfo")]
+pub mod resource_sampler;
+#[cfg(feature = "smtp")]
+pub mod smtp;
+mod summary;
+pub mod support_contact;
+#[cfg(feature = "symbolication")]
+pub mod symbolication;
+#[cfg(feature = "sysinfo")]
+pub mod system_snapshot;
+pub mod thread_stacks;
+#[cfg(feature = "upload")]
+pub mod upload;
 
 #[cfg(feature = "create_report_dialog")]
 pub mod create_report_dialog;
@@ -8,6 +36,9 @@ pub mod create_report_dialog;
 #[cfg(feature = "problem_report_dialog")]
 pub mod problem_report_dialog;
 
+#[cfg(any(feature = "create_report_dialog", feature = "problem_report_dialog"))]
+mod send_report_group;
+
 #[cfg(any(feature = "create_report_dialog", feature = "problem_report_dialog"))]
 pub use misc::init_gui;
 
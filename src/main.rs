@@ -0,0 +1,302 @@
+use anyhow::{Context, Result};
+use clap::{Args, Parser, Subcommand};
+use mxl_investigator::{
+    encryption::ArchiveDecryption,
+    proc_dir::{self, ArchiveOptions},
+    report::{self, Reader, Run, RunOrigin},
+};
+use std::path::{Path, PathBuf};
+
+/// Lists, inspects, exports and purges the runs recorded by MXL applications.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Data directory of the application that recorded the runs
+    #[arg(long, short, global = true)]
+    data_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the current and failed runs
+    List,
+    /// Show the details of a run
+    Show {
+        /// Name of the run, optionally with its directory, e.g. 'failed/default/<run>'
+        run: String,
+    },
+    /// Export the failed runs into a report archive and remove them afterwards
+    Export {
+        /// Export only the failed runs, the default
+        #[arg(long, conflicts_with = "all")]
+        failed: bool,
+        /// Export the current and the failed runs
+        #[arg(long)]
+        all: bool,
+        /// Export only runs that were not exported before
+        #[arg(long)]
+        only_new: bool,
+        /// File name of the archive
+        file: PathBuf,
+    },
+    /// Remove all failed runs
+    Purge {
+        /// Move the failed runs to the trash instead of removing them permanently
+        #[arg(long)]
+        trash: bool,
+    },
+    /// Check the integrity of a report archive
+    Verify {
+        archive: PathBuf,
+        #[command(flatten)]
+        decryption: DecryptionArgs,
+    },
+    /// Show the manifest and the runs of a report archive
+    Inspect {
+        archive: PathBuf,
+        #[command(flatten)]
+        decryption: DecryptionArgs,
+    },
+    /// Resolve the backtraces of a report archive with separate debug files
+    #[cfg(feature = "symbolication")]
+    Symbolicate {
+        /// Directory with the debug files of the application and its libraries
+        #[arg(long)]
+        debug_dir: PathBuf,
+        archive: PathBuf,
+        /// File name of the symbolicated archive
+        output: PathBuf,
+    },
+}
+
+#[derive(Args)]
+struct DecryptionArgs {
+    /// Passphrase of an encrypted archive
+    #[arg(long, conflicts_with = "identity")]
+    passphrase: Option<String>,
+    /// File with the age identity of an archive encrypted with a public key
+    #[arg(long)]
+    identity: Option<PathBuf>,
+}
+
+impl DecryptionArgs {
+    fn decryption(&self) -> Result<Option<ArchiveDecryption>> {
+        if let Some(passphrase) = self.passphrase.as_ref() {
+            return Ok(Some(ArchiveDecryption::Passphrase(passphrase.clone())));
+        }
+        match self.identity.as_ref() {
+            Some(identity_file) => identity_decryption(identity_file).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(feature = "encryption")]
+fn identity_decryption(identity_file: &Path) -> Result<ArchiveDecryption> {
+    let content = std::fs::read_to_string(identity_file)
+        .with_context(|| format!("Cannot read identity file '{}'", identity_file.to_string_lossy()))?;
+    let identity = content
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .with_context(|| format!("No identity found in '{}'", identity_file.to_string_lossy()))?;
+    Ok(ArchiveDecryption::Identity(identity.to_string()))
+}
+
+#[cfg(not(feature = "encryption"))]
+fn identity_decryption(_identity_file: &Path) -> Result<ArchiveDecryption> {
+    anyhow::bail!("Decryption with an identity requires the 'encryption' feature")
+}
+
+fn init(data_dir: Option<&PathBuf>) -> Result<()> {
+    let data_dir = data_dir.with_context(|| "The data directory is required, use '--data-dir <DIR>'")?;
+    if !data_dir.is_dir() {
+        anyhow::bail!("Data directory '{}' does not exist", data_dir.to_string_lossy());
+    }
+    mxl_investigator::init(data_dir.clone());
+    Ok(())
+}
+
+fn origin_name(origin: &RunOrigin) -> String {
+    match origin {
+        RunOrigin::Current => "current".to_string(),
+        RunOrigin::Failed { source } => format!("failed ({source})"),
+    }
+}
+
+fn print_runs(runs: &[Run]) {
+    if runs.is_empty() {
+        println!("No runs found");
+        return;
+    }
+    for run in runs {
+        println!(
+            "{:<40} {:<20} {:<10} {} panic(s), {} file(s)",
+            run.name,
+            origin_name(&run.origin),
+            format!("{:?}", run.outcome),
+            run.panics.len(),
+            run.files.len()
+        );
+    }
+}
+
+fn print_run(run: &Run) {
+    println!("Run:      {}", run.name);
+    println!("Path:     {}", run.path);
+    println!("Origin:   {}", origin_name(&run.origin));
+    println!("Outcome:  {:?}", run.outcome);
+    if let Some(build) = run.build.as_ref() {
+        println!("Build:    {} {}", build.package_name, build.version_description());
+    }
+    for panic in run.panics.iter() {
+        println!();
+        println!(
+            "Panic at {} in thread '{}': {}",
+            panic.time,
+            panic.thread.as_deref().unwrap_or("<unknown>"),
+            panic.message
+        );
+        if let Some(location) = panic.location.as_ref() {
+            println!("  Location: {location}");
+        }
+    }
+    if let Some(exit_report) = run.exit_report.as_ref() {
+        println!();
+        println!("Exit report:");
+        println!("{}", exit_report.trim_end());
+    }
+    if !run.command_outputs.is_empty() {
+        println!();
+        println!("Command outputs:");
+        for output in run.command_outputs.iter() {
+            println!("  {}", output.program);
+        }
+    }
+    println!();
+    println!("Files:");
+    for file in run.files.iter() {
+        println!("  {file}");
+    }
+}
+
+fn find_run<'a>(runs: &'a [Run], name: &str) -> Result<&'a Run> {
+    let mut matches = runs.iter().filter(|run| run.name == name || run.path == name);
+    let run = matches.next().with_context(|| format!("Run '{name}' not found"))?;
+    if matches.next().is_some() {
+        anyhow::bail!("Run name '{name}' is ambiguous, use the path shown by 'list' instead");
+    }
+    Ok(run)
+}
+
+fn inspect(archive: &Path, decryption: Option<ArchiveDecryption>) -> Result<()> {
+    let mut reader = Reader::open_with_decryption(archive, decryption)?;
+    let manifest = reader.manifest()?;
+    println!("Schema version: {}", manifest.schema_version);
+    if !manifest.crate_version.is_empty() {
+        println!("Created by:     mxl-investigator {}", manifest.crate_version);
+    }
+    if !manifest.created.is_empty() {
+        println!("Created:        {}", manifest.created);
+    }
+    println!("Partial:        {}", if manifest.is_partial() { "yes" } else { "no" });
+    for entry in manifest.excluded.iter() {
+        println!("Excluded:       {} ({})", entry.name, entry.reason);
+    }
+    for entry in manifest.redacted.iter() {
+        println!("Redacted:       {} ({} occurrence(s))", entry.name, entry.count);
+    }
+    for run in manifest.dropped_runs.iter() {
+        println!("Dropped run:    {run}");
+    }
+    for entry in manifest.truncated.iter() {
+        println!(
+            "Truncated:      {} (kept {} of {} bytes)",
+            entry.name, entry.kept, entry.size
+        );
+    }
+    println!();
+    print_runs(&reader.runs()?);
+    Ok(())
+}
+
+fn run(cli: Cli) -> Result<()> {
+    match cli.command {
+        Command::List => {
+            init(cli.data_dir.as_ref())?;
+            print_runs(&report::local_runs()?);
+        }
+        Command::Show { run } => {
+            init(cli.data_dir.as_ref())?;
+            let runs = report::local_runs()?;
+            print_run(find_run(&runs, &run)?);
+        }
+        Command::Export {
+            failed: _,
+            all,
+            only_new,
+            file,
+        } => {
+            init(cli.data_dir.as_ref())?;
+            let options = ArchiveOptions {
+                only_not_exported: only_new,
+                ..Default::default()
+            };
+            if all {
+                if report::local_runs()?.is_empty() {
+                    println!("No runs found, nothing exported");
+                    return Ok(());
+                }
+                let files = proc_dir::proc_dir_archive_with_options(&file, options)?;
+                if files.is_empty() {
+                    println!("All runs were exported before, nothing new to export");
+                }
+                for file in files {
+                    println!("Report written to '{}'", file.to_string_lossy());
+                }
+            } else {
+                proc_dir::failed_dir_archive_and_remove_with_options(&file, options)?;
+            }
+        }
+        Command::Purge { trash } => {
+            init(cli.data_dir.as_ref())?;
+            if trash {
+                proc_dir::failed_dir_move_to_trash()?;
+            } else {
+                proc_dir::failed_dir_remove()?;
+            }
+        }
+        Command::Verify { archive, decryption } => {
+            let mut reader = Reader::open_with_decryption(&archive, decryption.decryption()?)?;
+            let count = reader.verify()?;
+            println!(
+                "Archive '{}' is valid, {count} entries verified",
+                archive.to_string_lossy()
+            );
+        }
+        Command::Inspect { archive, decryption } => inspect(&archive, decryption.decryption()?)?,
+        #[cfg(feature = "symbolication")]
+        Command::Symbolicate {
+            debug_dir,
+            archive,
+            output,
+        } => {
+            let resolved = mxl_investigator::symbolication::symbolicate_archive(&archive, &debug_dir, &output)?;
+            println!(
+                "Resolved {resolved} frames, symbolicated archive written to '{}'",
+                output.to_string_lossy()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(error) = run(Cli::parse()) {
+        eprintln!("Error: {error:#}");
+        std::process::exit(1);
+    }
+}
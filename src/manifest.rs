@@ -0,0 +1,90 @@
+use crate::build_info::BuildInfo;
+use serde::{Deserialize, Serialize};
+
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// Version of the layout of the archives created by this crate.
+///
+/// Version 1 uses the following layout:
+/// - `manifest.json`: The [`Manifest`] of the archive
+/// - `index.html`: A self-contained summary of the runs, not present in archives of older crate versions
+/// - `current/<run>/…`: Runs in the process directory, including the run creating the archive
+/// - `failed/<source-name>/<run>/…`: Failed runs of each registered failed directory,
+///   the default failed directory has the source name `default`
+/// - `collected/<name>/…`: Additional files and directories registered by the application
+///
+/// Archives of older versions have no schema version and contain the runs below the last three
+/// directories of their absolute path.
+pub const ARCHIVE_SCHEMA_VERSION: u32 = 1;
+pub const ARCHIVE_CURRENT_DIR_NAME: &str = "current";
+pub const ARCHIVE_FAILED_DIR_NAME: &str = "failed";
+pub const ARCHIVE_COLLECTED_DIR_NAME: &str = "collected";
+pub const ARCHIVE_DEFAULT_FAILED_SOURCE_NAME: &str = "default";
+
+/// Description of the contents of an archive, stored as [`MANIFEST_FILE_NAME`] in the archive root.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Layout version of the archive, see [`ARCHIVE_SCHEMA_VERSION`], `0` for archives without manifest.
+    #[serde(default)]
+    pub schema_version: u32,
+    /// Version of this crate that created the archive.
+    #[serde(default)]
+    pub crate_version: String,
+    /// Creation time of the archive in RFC 3339 format.
+    #[serde(default)]
+    pub created: String,
+    /// Build of the application that created the archive, the build of each run is stored in its run directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build: Option<BuildInfo>,
+    /// Files of the run directories that were not added to the archive.
+    #[serde(default)]
+    pub excluded: Vec<ExcludedEntry>,
+    /// Text files in which personal data was redacted.
+    #[serde(default)]
+    pub redacted: Vec<RedactedEntry>,
+    /// Runs that were left out to fit the size limit of the archive.
+    #[serde(default)]
+    pub dropped_runs: Vec<String>,
+    /// Files of which only the end was added to fit the size limit of the archive.
+    #[serde(default)]
+    pub truncated: Vec<TruncatedEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExcludedEntry {
+    pub name: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedactedEntry {
+    pub name: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TruncatedEntry {
+    pub name: String,
+    /// Original size of the file in bytes.
+    pub size: u64,
+    /// Number of bytes at the end of the file that were kept.
+    pub kept: u64,
+}
+
+impl Manifest {
+    pub(crate) fn new() -> Self {
+        Self {
+            schema_version: ARCHIVE_SCHEMA_VERSION,
+            crate_version: env!("CARGO_PKG_VERSION").into(),
+            created: humantime::format_rfc3339(std::time::SystemTime::now()).to_string(),
+            build: crate::build_info::build_info().cloned(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns `true` if files were excluded or truncated or runs were dropped, i.e. the archive lacks some data
+    /// of its runs.
+    pub fn is_partial(&self) -> bool {
+        !self.excluded.is_empty() || !self.dropped_runs.is_empty() || !self.truncated.is_empty()
+    }
+}
@@ -2,8 +2,11 @@ use anyhow::{Context, Result};
 use once_cell::sync::OnceCell;
 use std::{fs::File, io::Write, path::PathBuf};
 
-#[allow(dead_code)]
-pub(crate) const SUPPORT_EMAIL: &str = "support@x-software.com";
+pub(crate) const SYSINFO_FILE_NAME: &str = "sysinfo.txt";
+#[cfg(feature = "sysinfo")]
+pub(crate) const SYSINFO_JSON_FILE_NAME: &str = "sysinfo.json";
+pub(crate) const STDOUT_FILE_SUFFIX: &str = "_stdout.txt";
+pub(crate) const STDERR_FILE_SUFFIX: &str = "_stderr.txt";
 
 static PROJECT_DATA_DIR: OnceCell<PathBuf> = OnceCell::new();
 
@@ -19,10 +22,117 @@ pub fn init_gui() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Returns `true` if report files can be uploaded from the dialogs.
+#[cfg(any(feature = "create_report_dialog", feature = "problem_report_dialog"))]
+pub(crate) fn upload_available() -> bool {
+    #[cfg(feature = "upload")]
+    return crate::upload::upload_is_configured();
+    #[cfg(not(feature = "upload"))]
+    false
+}
+
+/// Uploads a report file from the dialogs, `progress` is called with the uploaded percentage.
+///
+/// A report is queued in the outbox if the upload fails, but not if it is cancelled.
+#[cfg(any(feature = "create_report_dialog", feature = "problem_report_dialog"))]
+pub(crate) fn upload_report(
+    path: &std::path::Path,
+    cancellation: crate::proc_dir::CancellationToken,
+    mut progress: Box<dyn FnMut(u32) + Send>,
+) -> Result<()> {
+    #[cfg(feature = "upload")]
+    {
+        let mut last_percent = None;
+        let options = crate::upload::UploadOptions {
+            progress: Some(Box::new(move |upload_progress: &crate::upload::UploadProgress| {
+                // Limit the number of updates sent to the user interface
+                let percent = (upload_progress.fraction() * 100.0) as u32;
+                if last_percent != Some(percent) {
+                    last_percent = Some(percent);
+                    progress(percent);
+                }
+            })),
+            cancellation: Some(cancellation),
+        };
+        let url = crate::upload::upload_config()
+            .map(|config| config.url)
+            .unwrap_or_default();
+        if let Err(err) = crate::upload::upload_file(path, options) {
+            if err.is::<crate::upload::UploadCancelled>() {
+                return Err(err);
+            }
+            // Keep the report to retry the upload on a later start, e.g. if the network is not reachable
+            return match crate::outbox::outbox_enqueue(path) {
+                Ok(_) => Err(err.context("The report is queued for a later upload")),
+                Err(enqueue_err) => {
+                    log::warn!("Cannot queue report for upload: {enqueue_err:?}");
+                    Err(err)
+                }
+            };
+        }
+        record_sent(path, crate::export_ledger::ExportDestination::Upload { url });
+        Ok(())
+    }
+    #[cfg(not(feature = "upload"))]
+    {
+        _ = (path, cancellation, &mut progress);
+        anyhow::bail!("Uploading reports requires the 'upload' feature")
+    }
+}
+
+#[cfg(all(
+    any(feature = "create_report_dialog", feature = "problem_report_dialog"),
+    any(feature = "upload", feature = "smtp")
+))]
+fn record_sent(path: &std::path::Path, destination: crate::export_ledger::ExportDestination) {
+    if let Err(err) = crate::export_ledger::export_ledger_record_sent(path, destination) {
+        log::warn!("Cannot record sent report: {err:?}");
+    }
+}
+
+/// Returns `true` if report files can be sent by mail from the dialogs.
+#[cfg(any(feature = "create_report_dialog", feature = "problem_report_dialog"))]
+pub(crate) fn mail_available() -> bool {
+    #[cfg(feature = "smtp")]
+    return crate::smtp::smtp_is_configured();
+    #[cfg(not(feature = "smtp"))]
+    false
+}
+
+/// Sends a report file by mail from the dialogs.
+#[cfg(any(feature = "create_report_dialog", feature = "problem_report_dialog"))]
+pub(crate) fn send_report_mail(
+    subject: &str,
+    body: &str,
+    path: &std::path::Path,
+    cancellation: crate::proc_dir::CancellationToken,
+) -> Result<()> {
+    #[cfg(feature = "smtp")]
+    {
+        let recipients = crate::smtp::smtp_config().map(|config| config.to).unwrap_or_default();
+        let options = crate::smtp::SmtpOptions {
+            cancellation: Some(cancellation),
+        };
+        crate::smtp::smtp_send_report(subject, body, path, options)?;
+        record_sent(path, crate::export_ledger::ExportDestination::Mail { recipients });
+        Ok(())
+    }
+    #[cfg(not(feature = "smtp"))]
+    {
+        _ = (subject, body, path, cancellation);
+        anyhow::bail!("Sending reports by mail requires the 'smtp' feature")
+    }
+}
+
 pub(crate) fn get_data_dir() -> &'static PathBuf {
     PROJECT_DATA_DIR.get().expect("Need to be initialized")
 }
 
+#[cfg(feature = "upload")]
+pub(crate) fn data_dir_is_initialized() -> bool {
+    PROJECT_DATA_DIR.get().is_some()
+}
+
 #[cfg(feature = "with_test")]
 pub fn init_test() {
     use once_cell::sync::Lazy;
@@ -53,75 +163,27 @@ pub fn create_sysinfo_dump() {
     #[cfg(feature = "sysinfo")]
     {
         fn create_sysinfo() -> Result<()> {
-            use sysinfo::{Components, Disks, Networks, System};
+            let snapshot = crate::system_snapshot::system_snapshot();
 
-            let sysinfo_file_path = crate::proc_dir::proc_dir().join("sysinfo.txt");
+            let sysinfo_file_path = crate::proc_dir::proc_dir().join(SYSINFO_FILE_NAME);
             let mut file = File::options()
                 .create(true)
                 .append(true)
                 .open(&sysinfo_file_path)
                 .with_context(|| format!("Cannot create file '{}'", sysinfo_file_path.to_string_lossy()))?;
+            file.write_all(snapshot.to_text().as_bytes())?;
 
-            let mut sys = sysinfo::System::new_all();
-            sys.refresh_all();
-
-            let mut out = Vec::new();
-            writeln!(&mut out, "=> system:")?;
-            // RAM and swap information:
-            writeln!(&mut out, "total memory: {} bytes", sys.total_memory())?;
-            writeln!(&mut out, "used memory : {} bytes", sys.used_memory())?;
-            writeln!(&mut out, "total swap  : {} bytes", sys.total_swap())?;
-            writeln!(&mut out, "used swap   : {} bytes", sys.used_swap())?;
-
-            // Display system information:
-            writeln!(&mut out, "System name:             {:?}", System::name())?;
-            writeln!(&mut out, "System kernel version:   {:?}", System::kernel_version())?;
-            writeln!(&mut out, "System OS version:       {:?}", System::os_version())?;
-            writeln!(&mut out, "System long OS version:  {:?}", System::long_os_version())?;
-            writeln!(&mut out, "System host name:        {:?}", System::host_name())?;
-
-            // Number of CPUs:
-            writeln!(&mut out, "NB CPUs: {}", sys.cpus().len())?;
-
-            // Display processes ID, name na disk usage:
-            if false {
-                for (pid, process) in sys.processes() {
-                    writeln!(&mut out, "[{pid}] {:?} {:?}", process.name(), process.disk_usage())?;
-                }
-            }
-
-            // We display all disks' information:
-            writeln!(&mut out, "=> disks:")?;
-            let disks = Disks::new_with_refreshed_list();
-            for disk in &disks {
-                writeln!(&mut out, "{disk:?}")?;
-            }
-
-            // Network interfaces name, total data received and total data transmitted:
-            let networks = Networks::new_with_refreshed_list();
-            writeln!(&mut out, "=> networks:")?;
-            for (interface_name, data) in &networks {
-                writeln!(
-                    &mut out,
-                    "{interface_name}: {} B (down) / {} B (up)",
-                    data.total_received(),
-                    data.total_transmitted(),
-                )?;
-                // If you want the amount of data received/transmitted since last call
-                // to `Networks::refresh`, use `received`/`transmitted`.
-            }
-
-            // Components temperature:
-            let components = Components::new_with_refreshed_list();
-            writeln!(&mut out, "=> components:")?;
-            for component in &components {
-                writeln!(&mut out, "{component:?}")?;
-            }
-
-            file.write_all(out.as_slice())?;
+            // The JSON file contains the latest snapshot only
+            let json_file_path = crate::proc_dir::proc_dir().join(SYSINFO_JSON_FILE_NAME);
+            std::fs::write(&json_file_path, snapshot.to_json()?)
+                .with_context(|| format!("Cannot write file '{}'", json_file_path.to_string_lossy()))?;
             Ok(())
         }
 
+        if !crate::disk_quota::diagnostics_allowed() {
+            log::debug!("Skip system information, diagnostic writers are stopped");
+            return;
+        }
         if let Err(err) = create_sysinfo() {
             log::warn!("Cannot create system information: {:?}", err);
         }
@@ -133,7 +195,7 @@ pub fn exec_cmd_and_dump_pipes(command: std::process::Command) {
         let path = crate::proc_dir::proc_dir();
         let mut stdout_file_name = std::ffi::OsString::new();
         stdout_file_name.push(command.get_program());
-        stdout_file_name.push("_stdout.txt");
+        stdout_file_name.push(STDOUT_FILE_SUFFIX);
         let mut stdout_file = File::options()
             .create(true)
             .append(true)
@@ -141,7 +203,7 @@ pub fn exec_cmd_and_dump_pipes(command: std::process::Command) {
         writeln!(&mut stdout_file, "{command:?}")?;
         let mut stderr_file_name = std::ffi::OsString::new();
         stderr_file_name.push(command.get_program());
-        stderr_file_name.push("_stderr.txt");
+        stderr_file_name.push(STDERR_FILE_SUFFIX);
         let mut stderr_file = File::options()
             .create(true)
             .append(true)
@@ -157,6 +219,13 @@ pub fn exec_cmd_and_dump_pipes(command: std::process::Command) {
         Ok(())
     }
 
+    if !crate::disk_quota::diagnostics_allowed() {
+        log::debug!(
+            "Skip command {:?}, diagnostic writers are stopped",
+            command.get_program()
+        );
+        return;
+    }
     if let Err(err) = exec_cmd(command) {
         log::warn!("Cannot execute command: {:?}", err);
     }
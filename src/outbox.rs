@@ -0,0 +1,509 @@
+use crate::upload::{UploadConfig, UploadOptions};
+use anyhow::{Context, Result};
+use fs4::fs_std::FileExt;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        RwLock,
+    },
+    time::{Duration, SystemTime},
+};
+
+pub(crate) const OUTBOX_DIR_NAME: &str = "outbox";
+const OUTBOX_ENTRY_FILE_NAME: &str = "entry.json";
+// Locked while the report of an entry is uploaded or removed
+const OUTBOX_LOCK_FILE_NAME: &str = "entry.lock";
+
+static OUTBOX_RETENTION: Lazy<RwLock<OutboxRetention>> = Lazy::new(|| RwLock::new(OutboxRetention::default()));
+// The queued reports are uploaded automatically once per process
+static AUTOMATIC_FLUSH_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Limits of the reports kept in the outbox, older reports are removed first.
+#[derive(Debug, Clone)]
+pub struct OutboxRetention {
+    /// Maximum number of queued reports.
+    pub max_entries: usize,
+    /// Maximum age of a queued report.
+    pub max_age: Option<Duration>,
+    /// Maximum number of failed upload attempts of a report.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for OutboxRetention {
+    fn default() -> Self {
+        Self {
+            max_entries: 20,
+            max_age: Some(Duration::from_secs(30 * 24 * 60 * 60)),
+            max_attempts: None,
+        }
+    }
+}
+
+/// Report queued for upload, stored with its metadata in `outbox/<id>` under the data directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    pub id: String,
+    /// File name of the report in the entry directory.
+    pub file_name: String,
+    pub size: u64,
+    /// Time the report was queued as RFC 3339 timestamp.
+    pub created: String,
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(default)]
+    pub last_attempt: Option<String>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+impl OutboxEntry {
+    /// Path of the queued report file.
+    pub fn file_path(&self) -> PathBuf {
+        self.file_path_in(&outbox_dir())
+    }
+
+    fn file_path_in(&self, dir: &Path) -> PathBuf {
+        dir.join(&self.id).join(&self.file_name)
+    }
+
+    fn age(&self) -> Option<Duration> {
+        let created = humantime::parse_rfc3339(&self.created).ok()?;
+        SystemTime::now().duration_since(created).ok()
+    }
+
+    fn write(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(&self.id).join(OUTBOX_ENTRY_FILE_NAME);
+        let content = serde_json::to_vec_pretty(self)?;
+        std::fs::write(&path, content).with_context(|| format!("Cannot write file '{}'", path.to_string_lossy()))
+    }
+}
+
+/// Result of [`outbox_flush`].
+#[derive(Debug, Clone, Default)]
+pub struct OutboxFlush {
+    /// IDs of the uploaded and removed reports.
+    pub uploaded: Vec<String>,
+    /// IDs of the reports that are still queued with the error of the attempt.
+    pub failed: Vec<(String, String)>,
+    /// IDs of the reports skipped because they are uploaded by another flush at the same time.
+    pub skipped: Vec<String>,
+}
+
+pub fn outbox_dir() -> PathBuf {
+    crate::misc::get_data_dir().join(OUTBOX_DIR_NAME)
+}
+
+pub fn outbox_set_retention(retention: OutboxRetention) {
+    *OUTBOX_RETENTION.write().unwrap() = retention;
+}
+
+fn read_entry(dir: &Path) -> Result<OutboxEntry> {
+    let path = dir.join(OUTBOX_ENTRY_FILE_NAME);
+    let content = std::fs::read(&path).with_context(|| format!("Cannot read file '{}'", path.to_string_lossy()))?;
+    serde_json::from_slice(&content).with_context(|| format!("Cannot parse file '{}'", path.to_string_lossy()))
+}
+
+/// Locks an entry against concurrent uploads, also from other processes.
+///
+/// Returns `None` if the entry is locked by someone else or was removed in the meantime.
+fn lock_entry(dir: &Path, id: &str) -> Result<Option<File>> {
+    let path = dir.join(id).join(OUTBOX_LOCK_FILE_NAME);
+    let lock_file = match File::create(&path) {
+        Ok(lock_file) => lock_file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).with_context(|| format!("Cannot create file '{}'", path.to_string_lossy())),
+    };
+    if lock_file.try_lock_exclusive().is_err() {
+        return Ok(None);
+    }
+    Ok(Some(lock_file))
+}
+
+/// Removes a locked entry, the metadata is removed first so that the entry is no longer listed.
+fn remove_locked_entry(dir: &Path, id: &str, lock_file: File) -> Result<()> {
+    let path = dir.join(id).join(OUTBOX_ENTRY_FILE_NAME);
+    std::fs::remove_file(&path).with_context(|| format!("Cannot remove file '{}'", path.to_string_lossy()))?;
+    // The lock file cannot be removed while it is open on all platforms
+    drop(lock_file);
+    remove_entry(dir, id)
+}
+
+fn remove_entry(dir: &Path, id: &str) -> Result<()> {
+    let dir = dir.join(id);
+    std::fs::remove_dir_all(&dir).with_context(|| format!("Cannot remove directory '{}'", dir.to_string_lossy()))
+}
+
+/// Returns the queued reports, the oldest first.
+pub fn outbox_entries() -> Result<Vec<OutboxEntry>> {
+    entries(&outbox_dir())
+}
+
+fn entries(dir: &Path) -> Result<Vec<OutboxEntry>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Cannot read directory '{}'", dir.to_string_lossy()))? {
+        let path = entry?.path();
+        // Entries without metadata are still copied or already removed
+        if !path.join(OUTBOX_ENTRY_FILE_NAME).is_file() {
+            continue;
+        }
+        match read_entry(&path) {
+            Ok(entry) => entries.push(entry),
+            Err(err) => log::warn!("Skip invalid outbox entry: {err:?}"),
+        }
+    }
+    entries.sort_by(|a, b| (&a.created, &a.id).cmp(&(&b.created, &b.id)));
+    Ok(entries)
+}
+
+/// Copies a report file into the outbox to upload it later.
+pub fn outbox_enqueue(path: &Path) -> Result<OutboxEntry> {
+    let retention = OUTBOX_RETENTION.read().unwrap().clone();
+    enqueue(&outbox_dir(), path, &retention)
+}
+
+fn enqueue(dir: &Path, path: &Path, retention: &OutboxRetention) -> Result<OutboxEntry> {
+    let file_name = path
+        .file_name()
+        .with_context(|| format!("Invalid report file name '{}'", path.to_string_lossy()))?
+        .to_string_lossy()
+        .to_string();
+    std::fs::create_dir_all(dir).with_context(|| format!("Cannot create directory '{}'", dir.to_string_lossy()))?;
+
+    let name = chrono::Local::now()
+        .format(crate::proc_dir::CURRENT_DIR_FMT)
+        .to_string();
+    let mut id = name.clone();
+    let mut index = 1;
+    while dir.join(&id).exists() {
+        index += 1;
+        id = format!("{name}_{index}");
+    }
+    let entry_dir = dir.join(&id);
+    std::fs::create_dir(&entry_dir)
+        .with_context(|| format!("Cannot create directory '{}'", entry_dir.to_string_lossy()))?;
+
+    let entry = OutboxEntry {
+        id,
+        size: 0,
+        file_name,
+        created: humantime::format_rfc3339(SystemTime::now()).to_string(),
+        attempts: 0,
+        last_attempt: None,
+        last_error: None,
+    };
+    let result = std::fs::copy(path, entry.file_path_in(dir))
+        .with_context(|| format!("Cannot copy '{}' to the outbox", path.to_string_lossy()))
+        .and_then(|size| {
+            let entry = OutboxEntry { size, ..entry.clone() };
+            entry.write(dir)?;
+            Ok(entry)
+        });
+    let entry = match result {
+        Ok(entry) => entry,
+        Err(err) => {
+            _ = std::fs::remove_dir_all(&entry_dir);
+            return Err(err);
+        }
+    };
+    log::info!("queued '{}' for upload as '{}'", path.to_string_lossy(), entry.id);
+    prune(dir, retention)?;
+    Ok(entry)
+}
+
+/// Removes a queued report.
+///
+/// Returns an error if the report is uploaded at the moment.
+pub fn outbox_remove(id: &str) -> Result<()> {
+    remove(&outbox_dir(), id)
+}
+
+fn remove(dir: &Path, id: &str) -> Result<()> {
+    // The ID must name an entry directory, not a path outside of the outbox
+    if id.is_empty() || id == "." || id.contains("..") || id.contains(['/', '\\']) {
+        anyhow::bail!("Invalid outbox entry ID '{id}'");
+    }
+    if !dir.join(id).is_dir() {
+        anyhow::bail!("Outbox entry '{id}' not found");
+    }
+    let Some(lock_file) = lock_entry(dir, id)? else {
+        anyhow::bail!("Outbox entry '{id}' is uploaded at the moment");
+    };
+    remove_locked_entry(dir, id, lock_file)
+}
+
+/// Removes the queued reports exceeding the retention limits, reports that are uploaded at the moment are kept.
+///
+/// Returns the IDs of the removed reports.
+pub fn outbox_prune() -> Result<Vec<String>> {
+    let retention = OUTBOX_RETENTION.read().unwrap().clone();
+    prune(&outbox_dir(), &retention)
+}
+
+fn prune(dir: &Path, retention: &OutboxRetention) -> Result<Vec<String>> {
+    let entries = entries(dir)?;
+    let excess = entries.len().saturating_sub(retention.max_entries);
+    let mut removed = Vec::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let expired = retention
+            .max_age
+            .is_some_and(|max_age| entry.age().is_some_and(|age| age > max_age));
+        let exhausted = retention
+            .max_attempts
+            .is_some_and(|max_attempts| entry.attempts >= max_attempts);
+        if index < excess || expired || exhausted {
+            let Some(lock_file) = lock_entry(dir, &entry.id)? else {
+                continue;
+            };
+            remove_locked_entry(dir, &entry.id, lock_file)?;
+            log::info!("removed outbox entry '{}' by retention", entry.id);
+            removed.push(entry.id.clone());
+        }
+    }
+    Ok(removed)
+}
+
+/// Uploads the queued reports with the given configuration, the uploaded reports are removed.
+///
+/// Each report is locked while it is uploaded, reports uploaded by another flush at the same time are skipped.
+pub fn outbox_flush_with_config(config: &UploadConfig) -> Result<OutboxFlush> {
+    let retention = OUTBOX_RETENTION.read().unwrap().clone();
+    flush(&outbox_dir(), config, &retention)
+}
+
+fn flush(dir: &Path, config: &UploadConfig, retention: &OutboxRetention) -> Result<OutboxFlush> {
+    let mut flush = OutboxFlush::default();
+    for entry in entries(dir)? {
+        let Some(lock_file) = lock_entry(dir, &entry.id)? else {
+            flush.skipped.push(entry.id);
+            continue;
+        };
+        // Read the entry again, it may have been uploaded or updated before it was locked
+        let mut entry = match read_entry(&dir.join(&entry.id)) {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        match crate::upload::upload_file_with_config(&entry.file_path_in(dir), config, UploadOptions::default()) {
+            Ok(_) => {
+                remove_locked_entry(dir, &entry.id, lock_file)?;
+                flush.uploaded.push(entry.id);
+            }
+            Err(err) => {
+                log::warn!("Cannot upload outbox entry '{}': {err:#}", entry.id);
+                entry.attempts += 1;
+                entry.last_attempt = Some(humantime::format_rfc3339(SystemTime::now()).to_string());
+                entry.last_error = Some(format!("{err:#}"));
+                entry.write(dir)?;
+                drop(lock_file);
+                flush.failed.push((entry.id, format!("{err:#}")));
+            }
+        }
+    }
+    prune(dir, retention)?;
+    Ok(flush)
+}
+
+/// Uploads the queued reports to the endpoint set by [`crate::upload::upload_set_config`].
+pub fn outbox_flush() -> Result<OutboxFlush> {
+    let config = crate::upload::upload_config().with_context(|| "No upload endpoint configured")?;
+    outbox_flush_with_config(&config)
+}
+
+/// Uploads the queued reports to the endpoint set by [`crate::upload::upload_set_config`] in a background thread.
+///
+/// The result is logged, use [`outbox_flush`] to handle it.
+pub fn outbox_flush_in_background() -> Result<()> {
+    std::thread::Builder::new()
+        .name("outbox-flush".to_string())
+        .spawn(|| match outbox_flush() {
+            Ok(flush) if flush.uploaded.is_empty() && flush.failed.is_empty() => {}
+            Ok(flush) => log::info!(
+                "outbox flushed, {} uploaded, {} still queued",
+                flush.uploaded.len(),
+                flush.failed.len()
+            ),
+            Err(err) => log::warn!("Cannot flush outbox: {err:?}"),
+        })
+        .with_context(|| "Cannot start outbox flush")?;
+    Ok(())
+}
+
+/// Uploads the queued reports in a background thread if an upload endpoint is set, otherwise only removes the
+/// reports exceeding the retention limits.
+///
+/// Called by [`crate::upload::upload_set_config`] and [`crate::proc_dir::proc_dir`], the reports are uploaded at
+/// most once per process.
+pub(crate) fn outbox_flush_automatically() {
+    if crate::upload::upload_is_configured() {
+        if !AUTOMATIC_FLUSH_STARTED.swap(true, Ordering::SeqCst) {
+            if let Err(err) = outbox_flush_in_background() {
+                log::warn!("Cannot flush outbox: {err:?}");
+            }
+        }
+    } else if let Err(err) = outbox_prune() {
+        log::warn!("Cannot prune outbox: {err:?}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::upload::tests::{serve, test_config, TestResponse};
+
+    fn enqueue_file(dir: &Path, name: &str, content: &str, retention: &OutboxRetention) -> OutboxEntry {
+        let source = tempfile::tempdir().unwrap();
+        let path = source.path().join(name);
+        std::fs::write(&path, content).unwrap();
+        enqueue(dir, &path, retention).unwrap()
+    }
+
+    fn ids(entries: &[OutboxEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.id.as_str()).collect()
+    }
+
+    #[test]
+    fn enqueue_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let retention = OutboxRetention::default();
+        assert!(entries(dir.path()).unwrap().is_empty());
+
+        let first = enqueue_file(dir.path(), "first.zip", "first report", &retention);
+        let second = enqueue_file(dir.path(), "second.zip", "second", &retention);
+        assert_ne!(first.id, second.id);
+        assert_eq!(first.size, 12);
+        assert_eq!(first.attempts, 0);
+        assert_eq!(
+            std::fs::read_to_string(second.file_path_in(dir.path())).unwrap(),
+            "second"
+        );
+        assert_eq!(entries(dir.path()).unwrap(), [first, second]);
+
+        // Directories without metadata are not listed
+        std::fs::create_dir(dir.path().join("incomplete")).unwrap();
+        assert_eq!(entries(dir.path()).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn prune_by_retention() {
+        let dir = tempfile::tempdir().unwrap();
+        let retention = OutboxRetention {
+            max_entries: 2,
+            max_age: Some(Duration::from_secs(60 * 60)),
+            max_attempts: Some(3),
+        };
+        let first = enqueue_file(dir.path(), "first.zip", "1", &retention);
+        let second = enqueue_file(dir.path(), "second.zip", "2", &retention);
+        let third = enqueue_file(dir.path(), "third.zip", "3", &retention);
+        // The oldest entry exceeding the maximum number was removed when the third one was queued
+        assert_eq!(ids(&entries(dir.path()).unwrap()), [&second.id, &third.id]);
+        assert!(!dir.path().join(&first.id).exists());
+
+        let expired = OutboxEntry {
+            created: humantime::format_rfc3339(SystemTime::now() - Duration::from_secs(2 * 60 * 60)).to_string(),
+            ..second.clone()
+        };
+        expired.write(dir.path()).unwrap();
+        let exhausted = OutboxEntry {
+            attempts: 3,
+            ..third.clone()
+        };
+        exhausted.write(dir.path()).unwrap();
+        assert_eq!(prune(dir.path(), &retention).unwrap(), [second.id, third.id]);
+        assert!(entries(dir.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn prune_keeps_locked_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let retention = OutboxRetention::default();
+        let entry = enqueue_file(dir.path(), "report.zip", "report", &retention);
+        let lock_file = lock_entry(dir.path(), &entry.id).unwrap().unwrap();
+        assert!(lock_entry(dir.path(), &entry.id).unwrap().is_none());
+
+        let retention = OutboxRetention {
+            max_entries: 0,
+            ..retention
+        };
+        assert!(prune(dir.path(), &retention).unwrap().is_empty());
+        drop(lock_file);
+        assert_eq!(prune(dir.path(), &retention).unwrap(), [entry.id]);
+    }
+
+    #[test]
+    fn remove_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let outbox_dir = dir.path().join(OUTBOX_DIR_NAME);
+        let retention = OutboxRetention::default();
+        let entry = enqueue_file(&outbox_dir, "report.zip", "report", &retention);
+        for id in ["", ".", "..", "a..b", "../outbox", "a/b", "a\\b"] {
+            let err = remove(&outbox_dir, id).err().unwrap();
+            assert_eq!(err.to_string(), format!("Invalid outbox entry ID '{id}'"));
+        }
+        assert!(remove(&outbox_dir, "unknown").is_err());
+
+        // An entry uploaded at the moment is kept
+        let lock_file = lock_entry(&outbox_dir, &entry.id).unwrap().unwrap();
+        let err = remove(&outbox_dir, &entry.id).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!("Outbox entry '{}' is uploaded at the moment", entry.id)
+        );
+        drop(lock_file);
+        remove(&outbox_dir, &entry.id).unwrap();
+        assert!(entries(&outbox_dir).unwrap().is_empty());
+        assert!(outbox_dir.is_dir());
+    }
+
+    #[test]
+    fn flush_uploads_and_keeps_failed() {
+        let dir = tempfile::tempdir().unwrap();
+        let retention = OutboxRetention::default();
+        let uploaded = enqueue_file(dir.path(), "uploaded.zip", "uploaded", &retention);
+        let failed = enqueue_file(dir.path(), "failed.zip", "failed", &retention);
+        let (url, server) = serve(2, |request| match request.body.as_slice() {
+            b"uploaded" => TestResponse::new(200),
+            _ => TestResponse::new(500),
+        });
+
+        let config = UploadConfig {
+            max_retries: 0,
+            ..test_config(url)
+        };
+        let result = flush(dir.path(), &config, &retention).unwrap();
+        assert_eq!(server.join().unwrap().len(), 2);
+        assert_eq!(result.uploaded, std::slice::from_ref(&uploaded.id));
+        assert_eq!(result.failed.len(), 1);
+        assert_eq!(result.failed[0].0, failed.id);
+        assert!(result.skipped.is_empty());
+        assert!(!dir.path().join(&uploaded.id).exists());
+
+        let entries = entries(dir.path()).unwrap();
+        assert_eq!(ids(&entries), [&failed.id]);
+        assert_eq!(entries[0].attempts, 1);
+        assert!(entries[0].last_attempt.is_some());
+        assert!(entries[0].last_error.as_ref().unwrap().contains("status 500"));
+    }
+
+    #[test]
+    fn flush_skips_locked_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let retention = OutboxRetention::default();
+        let locked = enqueue_file(dir.path(), "locked.zip", "locked", &retention);
+        let other = enqueue_file(dir.path(), "other.zip", "other", &retention);
+        let lock_file = lock_entry(dir.path(), &locked.id).unwrap().unwrap();
+        let (url, server) = serve(1, |_| TestResponse::new(200));
+
+        let result = flush(dir.path(), &test_config(url), &retention).unwrap();
+        assert_eq!(server.join().unwrap()[0].body, b"other");
+        assert_eq!(result.uploaded, [other.id]);
+        assert_eq!(result.skipped, std::slice::from_ref(&locked.id));
+        drop(lock_file);
+        assert_eq!(ids(&entries(dir.path()).unwrap()), [&locked.id]);
+    }
+}
@@ -0,0 +1,390 @@
+use crate::build_info::{BuildInfo, BUILD_SECTION_HEADER};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// Suffix of the file with the [`PanicDetails`] written next to each `.panic` file.
+pub const PANIC_DETAILS_FILE_SUFFIX: &str = ".panic.json";
+
+// Symbols of the panic machinery and the runtime, which are hidden by `BacktraceFilter::hide_runtime_frames`
+const RUNTIME_SYMBOL_PREFIXES: &[&str] = &[
+    "backtrace::",
+    "mxl_investigator::proc_dir::setup_panic",
+    "std::panicking::",
+    "std::panic::",
+    "std::rt::",
+    "std::sys::",
+    "std::sys_common::",
+    "core::panicking::",
+    "__rustc::",
+    "__rust_",
+    "<alloc::boxed::Box<dyn for<'a, 'b> core::ops::function::Fn<(&'a std::panic::PanicHookInfo",
+];
+const RUNTIME_SYMBOL_NAMES: &[&str] = &["rust_begin_unwind", "main", "_start", "__libc_start_main"];
+
+/// Filter for the human-readable backtraces in `.panic` files.
+///
+/// The [`PanicDetails`] always contain the full backtrace.
+#[derive(Debug, Clone)]
+pub struct BacktraceFilter {
+    /// Hides the frames of the panic machinery and the runtime, e.g. `std::panicking` or `std::rt`.
+    pub hide_runtime_frames: bool,
+    /// Collapses consecutive frames outside of the application crates into a single line.
+    pub collapse_library_frames: bool,
+    /// Names of the crates of the application, their frames are marked with `*`.
+    ///
+    /// If empty, the package of the [`BuildInfo`] or the name of the executable is used as crate name.
+    pub application_crates: Vec<String>,
+}
+
+impl Default for BacktraceFilter {
+    fn default() -> Self {
+        Self {
+            hide_runtime_frames: true,
+            collapse_library_frames: true,
+            application_crates: Vec::new(),
+        }
+    }
+}
+
+impl BacktraceFilter {
+    /// Filter that keeps all frames.
+    pub fn full() -> Self {
+        Self {
+            hide_runtime_frames: false,
+            collapse_library_frames: false,
+            application_crates: Vec::new(),
+        }
+    }
+
+    fn is_runtime_symbol(name: &str) -> bool {
+        RUNTIME_SYMBOL_NAMES.contains(&name) || RUNTIME_SYMBOL_PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+    }
+
+    fn is_application_symbol(application_crates: &[String], name: &str) -> bool {
+        let name = name.trim_start_matches('<');
+        application_crates.iter().any(|crate_name| {
+            name.strip_prefix(crate_name.as_str())
+                .is_some_and(|rest| rest.starts_with("::"))
+        })
+    }
+}
+
+fn executable_crate_name() -> Option<String> {
+    let exe = std::env::current_exe().ok()?;
+    Some(exe.file_stem()?.to_string_lossy().replace('-', "_"))
+}
+
+/// Symbol of a frame, a frame has several symbols if functions were inlined.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameSymbol {
+    pub name: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PanicFrame {
+    /// Instruction pointer of the frame.
+    pub ip: u64,
+    #[serde(default)]
+    pub symbols: Vec<FrameSymbol>,
+}
+
+impl PanicFrame {
+    /// Returns `true` if no symbol of the frame has a name.
+    pub fn is_unresolved(&self) -> bool {
+        self.symbols.iter().all(|symbol| symbol.name.is_none())
+    }
+}
+
+/// Executable or shared library loaded by the process.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoadedModule {
+    pub path: String,
+    /// GNU build ID or UUID of the module as hex string.
+    pub build_id: Option<String>,
+    /// Start of the module in the address space of the process.
+    pub start: u64,
+    /// End of the module in the address space of the process.
+    pub end: u64,
+    /// Difference between the actual and the stated addresses of the module.
+    pub bias: u64,
+}
+
+/// Structured record of a panic, which allows to symbolicate backtraces of stripped binaries later.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PanicDetails {
+    pub thread: String,
+    pub message: String,
+    pub location: Option<String>,
+    #[serde(default)]
+    pub frames: Vec<PanicFrame>,
+    /// Modules loaded at the time of the panic, only recorded with the `symbolication` feature.
+    #[serde(default)]
+    pub modules: Vec<LoadedModule>,
+    /// Build of the application, if set by [`crate::build_info::build_info_set`].
+    #[serde(default)]
+    pub build: Option<BuildInfo>,
+}
+
+impl PanicDetails {
+    pub(crate) fn new(thread: &str, message: &str, location: Option<String>, backtrace: &backtrace::Backtrace) -> Self {
+        let frames = backtrace
+            .frames()
+            .iter()
+            .map(|frame| PanicFrame {
+                ip: frame.ip() as u64,
+                symbols: frame
+                    .symbols()
+                    .iter()
+                    .map(|symbol| FrameSymbol {
+                        name: symbol.name().map(|name| format!("{name:#}")),
+                        file: symbol.filename().map(|file| file.to_string_lossy().to_string()),
+                        line: symbol.lineno(),
+                    })
+                    .collect(),
+            })
+            .collect();
+        Self {
+            thread: thread.to_string(),
+            message: message.to_string(),
+            location,
+            frames,
+            modules: loaded_modules(),
+            build: crate::build_info::build_info().cloned(),
+        }
+    }
+
+    /// Formats the first line of a `.panic` file.
+    pub fn format_message(&self) -> String {
+        match self.location.as_ref() {
+            Some(location) => format!("Thread '{}' panicked at '{}': {location}", self.thread, self.message),
+            None => format!("Thread '{}' panicked at '{}'", self.thread, self.message),
+        }
+    }
+
+    /// Formats all frames similar to the backtrace of a `.panic` file.
+    pub fn format_backtrace(&self) -> String {
+        self.format_backtrace_filtered(&BacktraceFilter::full())
+    }
+
+    /// Formats the content of a `.panic` file, the backtrace with the given filter followed by the build.
+    pub(crate) fn format_panic_file(&self, filter: &BacktraceFilter) -> String {
+        let mut text = format!("{}\n{}", self.format_message(), self.format_backtrace_filtered(filter));
+        if let Some(build) = self.build.as_ref() {
+            _ = write!(text, "\n{BUILD_SECTION_HEADER}\n{}", build.to_text());
+        }
+        text
+    }
+
+    /// Formats the frames of the backtrace of a `.panic` file with the given filter.
+    pub fn format_backtrace_filtered(&self, filter: &BacktraceFilter) -> String {
+        let mut application_crates = filter.application_crates.clone();
+        if application_crates.is_empty() {
+            let build_crate_name = self.build.as_ref().map(|build| build.package_name.replace('-', "_"));
+            application_crates.extend(build_crate_name.or_else(executable_crate_name));
+        }
+
+        // Each symbol of a frame is shown as its own line, inlined functions have the index of their frame
+        let mut lines = Vec::new();
+        for (index, frame) in self.frames.iter().enumerate() {
+            if frame.symbols.is_empty() {
+                lines.push((index, frame.ip, None));
+            }
+            for (symbol_index, symbol) in frame.symbols.iter().enumerate() {
+                lines.push((index, frame.ip, Some((symbol_index, symbol))));
+            }
+        }
+        if filter.hide_runtime_frames {
+            lines.retain(|(_, _, symbol)| {
+                !symbol
+                    .and_then(|(_, symbol)| symbol.name.as_deref())
+                    .is_some_and(BacktraceFilter::is_runtime_symbol)
+            });
+        }
+        let is_application = |symbol: Option<(usize, &FrameSymbol)>| {
+            symbol
+                .and_then(|(_, symbol)| symbol.name.as_deref())
+                .is_some_and(|name| BacktraceFilter::is_application_symbol(&application_crates, name))
+        };
+        // Collapsing without any application frame would hide the complete backtrace
+        let collapse = filter.collapse_library_frames && lines.iter().any(|(_, _, symbol)| is_application(*symbol));
+
+        let mut text = String::new();
+        let mut position = 0;
+        while position < lines.len() {
+            let (index, ip, symbol) = lines[position];
+            let application = is_application(symbol);
+            if collapse && !application {
+                let library_frames = lines[position..]
+                    .iter()
+                    .take_while(|(_, _, symbol)| !is_application(*symbol))
+                    .count();
+                if library_frames > 1 {
+                    _ = writeln!(text, "      ... {library_frames} library frames ...");
+                    position += library_frames;
+                    continue;
+                }
+            }
+            position += 1;
+
+            let marker = if application { '*' } else { ' ' };
+            match symbol {
+                None => _ = writeln!(text, "{marker}{index:3}: {ip:#x} - <unknown>"),
+                Some((symbol_index, symbol)) => {
+                    let name = symbol.name.as_deref().unwrap_or("<unknown>");
+                    match symbol_index {
+                        0 => _ = writeln!(text, "{marker}{index:3}: {name}"),
+                        _ => _ = writeln!(text, "{marker}     {name}"),
+                    }
+                    if let Some(file) = symbol.file.as_ref() {
+                        match symbol.line {
+                            Some(line) => _ = writeln!(text, "             at {file}:{line}"),
+                            None => _ = writeln!(text, "             at {file}"),
+                        }
+                    }
+                }
+            }
+        }
+        text
+    }
+}
+
+#[cfg(feature = "symbolication")]
+fn loaded_modules() -> Vec<LoadedModule> {
+    use findshlibs::{SharedLibrary, TargetSharedLibrary};
+
+    let mut modules = Vec::new();
+    TargetSharedLibrary::each(|library| {
+        let start = usize::from(library.actual_load_addr()) as u64;
+        modules.push(LoadedModule {
+            path: library.name().to_string_lossy().to_string(),
+            build_id: library.id().map(|id| id.to_string()),
+            start,
+            end: start + library.len() as u64,
+            bias: usize::from(library.virtual_memory_bias()) as u64,
+        });
+    });
+    modules
+}
+
+#[cfg(not(feature = "symbolication"))]
+fn loaded_modules() -> Vec<LoadedModule> {
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(ip: u64, name: &str, file: Option<(&str, u32)>) -> PanicFrame {
+        PanicFrame {
+            ip,
+            symbols: vec![FrameSymbol {
+                name: Some(name.to_string()),
+                file: file.map(|(file, _)| file.to_string()),
+                line: file.map(|(_, line)| line),
+            }],
+        }
+    }
+
+    fn details(frames: Vec<PanicFrame>) -> PanicDetails {
+        PanicDetails {
+            thread: "main".to_string(),
+            message: "failure".to_string(),
+            frames,
+            ..Default::default()
+        }
+    }
+
+    fn filter(hide_runtime_frames: bool, collapse_library_frames: bool) -> BacktraceFilter {
+        BacktraceFilter {
+            hide_runtime_frames,
+            collapse_library_frames,
+            application_crates: vec!["app".to_string()],
+        }
+    }
+
+    #[test]
+    fn hide_runtime_frames() {
+        let details = details(vec![
+            frame(0x10, "backtrace::backtrace::trace", None),
+            frame(0x20, "std::panicking::begin_panic_handler", None),
+            frame(0x30, "core::panicking::panic_fmt", None),
+            frame(0x40, "app::run", Some(("src/main.rs", 10))),
+            frame(0x50, "std::rt::lang_start_internal", None),
+            frame(0x60, "main", None),
+        ]);
+        assert_eq!(
+            details.format_backtrace_filtered(&filter(true, false)),
+            "*  3: app::run\n             at src/main.rs:10\n"
+        );
+        assert_eq!(
+            details.format_backtrace(),
+            "   0: backtrace::backtrace::trace\n   1: std::panicking::begin_panic_handler\n   \
+             2: core::panicking::panic_fmt\n   3: app::run\n             at src/main.rs:10\n   \
+             4: std::rt::lang_start_internal\n   5: main\n"
+        );
+    }
+
+    #[test]
+    fn collapse_library_frames() {
+        let mut inlined = frame(0x40, "app::worker::run", Some(("src/worker.rs", 20)));
+        inlined.symbols.push(FrameSymbol {
+            name: Some("<app::Job as core::ops::Drop>::drop".to_string()),
+            file: None,
+            line: None,
+        });
+        let details = details(vec![
+            frame(0x10, "app::handler", Some(("src/handler.rs", 5))),
+            frame(0x20, "tokio::runtime::task::poll", None),
+            frame(0x30, "apple::run", None),
+            inlined,
+            frame(0x50, "serde::de::deserialize", None),
+            frame(0x60, "app::main", None),
+        ]);
+        assert_eq!(
+            details.format_backtrace_filtered(&filter(false, true)),
+            "*  0: app::handler\n             at src/handler.rs:5\n      ... 2 library frames ...\n\
+             *  3: app::worker::run\n             at src/worker.rs:20\n*     <app::Job as core::ops::Drop>::drop\n   \
+             4: serde::de::deserialize\n*  5: app::main\n"
+        );
+    }
+
+    #[test]
+    fn no_application_frame() {
+        let details = details(vec![
+            frame(0x10, "std::panicking::begin_panic_handler", None),
+            frame(0x20, "tokio::runtime::task::poll", None),
+            frame(0x30, "tokio::runtime::task::run", None),
+            PanicFrame {
+                ip: 0x1234,
+                symbols: Vec::new(),
+            },
+        ]);
+        // Nothing is collapsed, otherwise the complete backtrace would be hidden
+        assert_eq!(
+            details.format_backtrace_filtered(&filter(true, true)),
+            "   1: tokio::runtime::task::poll\n   2: tokio::runtime::task::run\n   3: 0x1234 - <unknown>\n"
+        );
+    }
+
+    #[test]
+    fn application_crate_of_build() {
+        let details = PanicDetails {
+            build: Some(BuildInfo {
+                package_name: "my-app".to_string(),
+                ..Default::default()
+            }),
+            ..details(vec![
+                frame(0x10, "my_app::run", None),
+                frame(0x20, "tokio::runtime::task::poll", None),
+                frame(0x30, "tokio::runtime::task::run", None),
+            ])
+        };
+        assert_eq!(
+            details.format_backtrace_filtered(&BacktraceFilter::default()),
+            "*  0: my_app::run\n      ... 2 library frames ...\n"
+        );
+    }
+}
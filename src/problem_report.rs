@@ -0,0 +1,214 @@
+use crate::{
+    consent::{ConsentState, ProblemReportAction, ProblemReportConsent},
+    localization::helper::fl,
+};
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+
+/// Reason of the decision of [`should_offer_problem_report`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProblemReportReason {
+    /// No previous run failed.
+    NoFailedRuns,
+    /// The user snoozed the problem report.
+    Snoozed,
+    /// The user does not want to be asked.
+    NeverAsk,
+    /// All failed runs were already exported.
+    AlreadyExported,
+    /// The failed runs are handled according to the consent of the user without asking.
+    Automatic,
+    /// At least one previous run panicked.
+    Panicked,
+    /// Previous runs failed without a panic, e.g. by an error or an abort.
+    Failed,
+}
+
+/// Failed runs found by [`should_offer_problem_report`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProblemReportSummary {
+    pub failed_runs: usize,
+    pub panicked_runs: usize,
+    /// Failed runs that were already exported according to the export ledger.
+    pub exported_runs: usize,
+    /// Name of the latest failed run, which starts with its start time.
+    pub latest_run: Option<String>,
+}
+
+impl ProblemReportSummary {
+    /// Localized text describing the failed runs, which the host can display.
+    pub fn description(&self) -> String {
+        fl!(
+            "problem-report-summary",
+            failed_runs = self.failed_runs,
+            panicked_runs = self.panicked_runs
+        )
+    }
+}
+
+/// Decision whether the problem report dialog is presented on startup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProblemReportOffer {
+    pub reason: ProblemReportReason,
+    /// Action according to the consent of the user.
+    pub action: ProblemReportAction,
+    pub summary: ProblemReportSummary,
+}
+
+impl ProblemReportOffer {
+    /// Returns `true` if the problem report dialog should be presented.
+    pub fn should_offer(&self) -> bool {
+        matches!(self.reason, ProblemReportReason::Panicked | ProblemReportReason::Failed)
+    }
+}
+
+fn failed_run_dirs() -> Result<Vec<PathBuf>> {
+    let mut run_dirs = Vec::new();
+    for (_, dir) in crate::proc_dir::failed_dirs() {
+        if !dir.is_dir() {
+            continue;
+        }
+        for entry in std::fs::read_dir(&dir)
+            .with_context(|| format!("Cannot list directories in '{}'", dir.to_string_lossy()))?
+        {
+            let path = entry?.path();
+            if path.is_dir() {
+                run_dirs.push(path);
+            }
+        }
+    }
+    Ok(run_dirs)
+}
+
+fn summary() -> Result<ProblemReportSummary> {
+    let mut summary = ProblemReportSummary::default();
+    if crate::proc_dir::failed_dir_is_empty()? {
+        return Ok(summary);
+    }
+    let exported_runs = crate::export_ledger::export_ledger_exported_runs()?;
+    for run_dir in failed_run_dirs()? {
+        summary.failed_runs += 1;
+        if crate::proc_dir::dir_has_panic(&run_dir)? {
+            summary.panicked_runs += 1;
+        }
+        let name = run_dir.file_name().unwrap_or_default().to_string_lossy().to_string();
+        if exported_runs.contains(&name) {
+            summary.exported_runs += 1;
+        }
+        if summary.latest_run.as_ref().is_none_or(|latest_run| *latest_run < name) {
+            summary.latest_run = Some(name);
+        }
+    }
+    Ok(summary)
+}
+
+fn reason(summary: &ProblemReportSummary, state: &ConsentState, action: &ProblemReportAction) -> ProblemReportReason {
+    if summary.failed_runs == 0 {
+        ProblemReportReason::NoFailedRuns
+    } else if state.is_snoozed() {
+        ProblemReportReason::Snoozed
+    } else if state.problem_report == ProblemReportConsent::NeverAsk {
+        ProblemReportReason::NeverAsk
+    } else if summary.exported_runs == summary.failed_runs {
+        ProblemReportReason::AlreadyExported
+    } else if *action != ProblemReportAction::Ask {
+        ProblemReportReason::Automatic
+    } else if summary.panicked_runs > 0 {
+        ProblemReportReason::Panicked
+    } else {
+        ProblemReportReason::Failed
+    }
+}
+
+/// Decides whether the problem report dialog should be presented on startup.
+///
+/// Combines the failed runs of previous runs, the runs already exported and the consent of the user, the host presents the dialog if
+/// [`ProblemReportOffer::should_offer`] returns `true` and handles [`ProblemReportReason::Automatic`] itself.
+pub fn should_offer_problem_report() -> Result<ProblemReportOffer> {
+    let summary = summary()?;
+    let state = crate::consent::consent_state()?;
+    let action = crate::consent::problem_report_action()?;
+    let reason = reason(&summary, &state, &action);
+    Ok(ProblemReportOffer {
+        reason,
+        action,
+        summary,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(failed_runs: usize, panicked_runs: usize, exported_runs: usize) -> ProblemReportSummary {
+        ProblemReportSummary {
+            failed_runs,
+            panicked_runs,
+            exported_runs,
+            latest_run: None,
+        }
+    }
+
+    fn state(problem_report: ProblemReportConsent) -> ConsentState {
+        ConsentState {
+            problem_report,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn already_exported_and_automatic() {
+        let export = state(ProblemReportConsent::AlwaysExport);
+        let action = ProblemReportAction::Export(PathBuf::from("/reports"));
+        // Runs exported before are not exported again
+        assert_eq!(
+            reason(&summary(2, 1, 2), &export, &action),
+            ProblemReportReason::AlreadyExported
+        );
+        assert_eq!(
+            reason(&summary(2, 1, 1), &export, &action),
+            ProblemReportReason::Automatic
+        );
+        assert_eq!(
+            reason(
+                &summary(2, 0, 2),
+                &state(ProblemReportConsent::AskEveryTime),
+                &ProblemReportAction::Ask
+            ),
+            ProblemReportReason::AlreadyExported
+        );
+    }
+
+    #[test]
+    fn reason_order() {
+        let ask = state(ProblemReportConsent::AskEveryTime);
+        assert_eq!(
+            reason(&summary(0, 0, 0), &ask, &ProblemReportAction::Ask),
+            ProblemReportReason::NoFailedRuns
+        );
+        assert_eq!(
+            reason(&summary(2, 1, 0), &ask, &ProblemReportAction::Ask),
+            ProblemReportReason::Panicked
+        );
+        assert_eq!(
+            reason(&summary(2, 0, 0), &ask, &ProblemReportAction::Ask),
+            ProblemReportReason::Failed
+        );
+        assert_eq!(
+            reason(
+                &summary(2, 1, 0),
+                &state(ProblemReportConsent::NeverAsk),
+                &ProblemReportAction::Ignore
+            ),
+            ProblemReportReason::NeverAsk
+        );
+        let snoozed = ConsentState {
+            snoozed_until: Some("2999-01-01T00:00:00Z".to_string()),
+            ..state(ProblemReportConsent::NeverAsk)
+        };
+        assert_eq!(
+            reason(&summary(2, 1, 2), &snoozed, &ProblemReportAction::Ignore),
+            ProblemReportReason::Snoozed
+        );
+    }
+}
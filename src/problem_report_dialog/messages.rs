@@ -1,3 +1,4 @@
+use crate::proc_dir::ArchiveProgress;
 use mxl_relm4_components::relm4::gtk;
 use std::path::PathBuf;
 
@@ -12,8 +13,22 @@ pub(super) mod internal {
         ShowBackwardToStartPage,
         OpenFileChooser,
         CreateReport(PathBuf),
+        CancelReport,
+        UploadReport,
+        SendReportMail,
         MoveToTrash,
         EscapePressed,
+        ConsentSelected(u32),
+        Snooze,
+    }
+
+    #[derive(Debug)]
+    pub enum CommandMsg {
+        Progress(ArchiveProgress),
+        Finished(anyhow::Result<()>),
+        UploadProgress(u32),
+        UploadFinished(anyhow::Result<()>),
+        MailFinished(anyhow::Result<()>),
     }
 }
 
@@ -1,17 +1,51 @@
+use crate::{
+    proc_dir::CancellationToken,
+    support_contact::{SupportContact, SupportMessageContext},
+};
 use mxl_relm4_components::{relm4::Controller, relm4_components::save_dialog::SaveDialog};
 
 #[derive(Debug)]
 pub struct ProblemReportDialogInit {
     pub app_name: &'static str,
+    pub app_version: Option<&'static str>,
     pub binary_name: &'static str,
+    /// Support contact of the dialog, the contact set by [`crate::support_contact::support_set_contact`] if `None`.
+    pub support_contact: Option<SupportContact>,
 }
 
 #[derive(Debug)]
 pub struct ProblemReportDialog {
     pub(super) app_name: &'static str,
+    pub(super) app_version: Option<&'static str>,
     pub(super) binary_name: &'static str,
+    pub(super) support_contact: Option<SupportContact>,
     pub(super) file_name: String,
     pub(super) file_chooser: Controller<SaveDialog>,
+    pub(super) cancellation: Option<CancellationToken>,
 }
 
-impl ProblemReportDialog {}
+impl ProblemReportDialog {
+    pub(super) fn support_contact(&self) -> SupportContact {
+        self.support_contact
+            .clone()
+            .unwrap_or_else(crate::support_contact::support_contact)
+    }
+
+    /// Uses the directory of the created report for automatic exports, if the user chose them.
+    pub(super) fn remember_export_dir(report_file_path: &std::path::Path) {
+        let result =
+            crate::consent::consent_state().and_then(|state| match (state.problem_report, report_file_path.parent()) {
+                (crate::consent::ProblemReportConsent::AlwaysExport, Some(dir)) => {
+                    crate::consent::consent_set_export_dir(Some(dir.to_path_buf()))
+                }
+                _ => Ok(()),
+            });
+        if let Err(err) = result {
+            log::warn!("Cannot store export directory: {err:?}");
+        }
+    }
+
+    pub(super) fn message_context(&self) -> SupportMessageContext {
+        SupportMessageContext::new(self.app_name, self.app_version, std::path::Path::new(&self.file_name))
+    }
+}
@@ -1,8 +1,16 @@
 use super::{
-    messages::{internal::PrivateMsg, ProblemReportDialogInput, ProblemReportDialogOutput},
+    messages::{
+        internal::{CommandMsg, PrivateMsg},
+        ProblemReportDialogInput, ProblemReportDialogOutput,
+    },
     model::{ProblemReportDialog, ProblemReportDialogInit},
 };
-use crate::localization::helper::fl;
+use crate::{
+    consent::{ProblemReportConsent, DEFAULT_SNOOZE_DAYS},
+    localization::helper::fl,
+    proc_dir::{ArchiveCancelled, ArchiveOptions, ArchiveProgress, CancellationToken},
+    send_report_group::{messages::SendReportGroupInput, model::SendReportGroup},
+};
 use mxl_relm4_components::{
     relm4::{
         self,
@@ -15,39 +23,12 @@ use mxl_relm4_components::{
 };
 use relm4_icons::icon_names;
 
-macro_rules! report_subject_fmt {
-    () => {
-        "Problem report file for {app_name}"
-    };
-}
-macro_rules! report_body_fmt {
-    () => {
-        "Hello X-Software Support,\n\
-\n\
-\n\
-I would like get assistance for {app_name}.\n\
-\n\
-Thanks!"
-    };
-}
-
-fn create_report_email_link(app_name: &str) -> String {
-    use urlencoding::encode;
-
-    format!(
-        "<a href=\"mailto:{email}?subject={subject}&amp;body={body}\">{email}</a>",
-        email = crate::misc::SUPPORT_EMAIL,
-        subject = encode(&format!(report_subject_fmt!(), app_name = app_name)),
-        body = encode(&format!(report_body_fmt!(), app_name = app_name))
-    )
-}
-
 #[relm4::component(pub)]
 impl Component for ProblemReportDialog {
     type Init = ProblemReportDialogInit;
     type Input = ProblemReportDialogInput;
     type Output = ProblemReportDialogOutput;
-    type CommandOutput = ();
+    type CommandOutput = CommandMsg;
 
     view! {
         adw::Window {
@@ -98,6 +79,49 @@ impl Component for ProblemReportDialog {
                                         connect_activated => ProblemReportDialogInput::PrivateMessage(PrivateMsg::MoveToTrash),
                                     },
                                 },
+
+                                adw::PreferencesGroup {
+                                    #[name(consent_row)]
+                                    adw::ComboRow {
+                                        set_title: &fl!("problem-report-dialog", "consent-title"),
+                                        set_model: Some(&gtk::StringList::new(&[
+                                            fl!("problem-report-dialog", "consent-ask-every-time").as_str(),
+                                            fl!("problem-report-dialog", "consent-always-export").as_str(),
+                                            fl!("problem-report-dialog", "consent-always-upload").as_str(),
+                                            fl!("problem-report-dialog", "consent-never-ask").as_str(),
+                                        ])),
+                                        connect_selected_notify[sender] => move |row| {
+                                            sender.input(ProblemReportDialogInput::PrivateMessage(PrivateMsg::ConsentSelected(row.selected())));
+                                        },
+                                    },
+                                    adw::ActionRow {
+                                        set_title: &fl!("problem-report-dialog", "btn-snooze", days = DEFAULT_SNOOZE_DAYS),
+                                        set_activatable: true,
+                                        connect_activated => ProblemReportDialogInput::PrivateMessage(PrivateMsg::Snooze),
+                                    },
+                                },
+                            },
+                        },
+
+                        #[name(progress_page)]
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Vertical,
+                            set_spacing: 8,
+                            set_valign: gtk::Align::Center,
+                            set_margin_all: 32,
+
+                            gtk::Label {
+                                add_css_class: "title-2",
+                                set_label: &fl!("problem-report-dialog", "progress-description"),
+                            },
+                            #[name(progress_bar)]
+                            gtk::ProgressBar {
+                                set_show_text: true,
+                            },
+                            gtk::Button {
+                                set_label: &fl!("problem-report-dialog", "btn-cancel"),
+                                set_halign: gtk::Align::Center,
+                                connect_clicked => ProblemReportDialogInput::PrivateMessage(PrivateMsg::CancelReport),
                             },
                         },
 
@@ -106,7 +130,10 @@ impl Component for ProblemReportDialog {
                             set_title: &fl!("problem-report-dialog", "success-title"),
                             add_css_class: "success",
                             #[watch]
-                            set_description: Some(&fl!("problem-report-dialog", "success-description", file_name = model.file_name.clone(), support_mail = create_report_email_link(model.app_name))),
+                            set_description: Some(&fl!("problem-report-dialog", "success-description", file_name = model.file_name.clone(), support_mail = model.support_contact().markup(&model.message_context()))),
+
+                            #[local_ref]
+                            send_group -> adw::PreferencesGroup {},
                         },
 
                         #[name(error_page)]
@@ -137,8 +164,12 @@ impl Component for ProblemReportDialog {
     fn init(init: Self::Init, root: Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self> {
         let model = ProblemReportDialog {
             app_name: init.app_name,
+            app_version: init.app_version,
             binary_name: init.binary_name,
+            support_contact: init.support_contact,
             file_name: String::default(),
+            cancellation: None,
+            send_group: SendReportGroup::builder().launch(()).detach(),
             file_chooser: {
                 let builder = SaveDialog::builder();
                 let widget = builder.widget();
@@ -172,15 +203,19 @@ impl Component for ProblemReportDialog {
             },
         };
 
+        let send_group_sender = model.send_group.sender().clone();
         root.upcast_ref::<gtk::Window>().connect_close_request(glib::clone!(
             #[strong]
             sender,
             move |_| {
+                // A running upload or mail is cancelled with the dialog
+                send_group_sender.emit(SendReportGroupInput::Cancel);
                 sender.output(ProblemReportDialogOutput::Closed).unwrap_or_default();
                 glib::Propagation::Proceed
             }
         ));
 
+        let send_group = model.send_group.widget();
         let widgets = view_output!();
         mxl_relm4_components::gtk::do_closure_on_escape(&root, move || {
             sender.input(ProblemReportDialogInput::PrivateMessage(PrivateMsg::EscapePressed))
@@ -221,23 +256,46 @@ impl Component for ProblemReportDialog {
                 }
                 PrivateMsg::CreateReport(path) => {
                     self.file_name = path.to_string_lossy().to_string();
-                    if let Err(err) = crate::proc_dir::failed_dir_archive_and_remove(&path) {
-                        widgets
-                            .error_page
-                            .set_title(&fl!("problem-report-dialog", "error-create-title"));
-                        widgets
-                            .error_page
-                            .set_description(Some(glib::markup_escape_text(&format!("{:?}", err)).as_str()));
-                        sender.input(ProblemReportDialogInput::PrivateMessage(PrivateMsg::SwitchForwardTo(
-                            widgets.error_page.clone().into(),
-                        )));
-                    } else {
-                        sender.input(ProblemReportDialogInput::PrivateMessage(PrivateMsg::SwitchForwardTo(
-                            widgets.success_page.clone().into(),
-                        )));
-                    }
+                    Self::remember_export_dir(&path);
+                    widgets.stack_view.set_transition_type(gtk::StackTransitionType::None);
+                    widgets.stack_view.set_visible_child(&widgets.progress_page);
+                    widgets.progress_bar.set_fraction(0.0);
+                    widgets.progress_bar.set_text(None);
+                    let contact = self.support_contact();
+                    let context = self.message_context();
+                    self.send_group.emit(SendReportGroupInput::SetReport {
+                        path: path.clone(),
+                        subject: contact.subject(&context),
+                        body: contact.body(&context),
+                    });
+                    let cancellation = CancellationToken::new();
+                    self.cancellation = Some(cancellation.clone());
+                    sender.spawn_command(move |out| {
+                        let mut last_percent = None;
+                        let progress_out = out.clone();
+                        let options = ArchiveOptions {
+                            progress: Some(Box::new(move |progress: &ArchiveProgress| {
+                                // Limit the number of updates sent to the user interface
+                                let percent = (progress.fraction() * 100.0) as u32;
+                                if last_percent != Some(percent) || progress.files_done == progress.files_total {
+                                    last_percent = Some(percent);
+                                    progress_out.send(CommandMsg::Progress(*progress)).unwrap_or_default();
+                                }
+                            })),
+                            cancellation: Some(cancellation),
+                            ..Default::default()
+                        };
+                        let result =
+                            crate::proc_dir::failed_dir_archive_and_remove_with_options(&path, options).map(|_| ());
+                        out.send(CommandMsg::Finished(result)).unwrap_or_default();
+                    });
                     self.update_view(widgets, sender);
                 }
+                PrivateMsg::CancelReport => {
+                    if let Some(cancellation) = &self.cancellation {
+                        cancellation.cancel();
+                    }
+                }
                 PrivateMsg::MoveToTrash => {
                     if let Err(err) = crate::proc_dir::failed_dir_move_to_trash() {
                         widgets
@@ -253,8 +311,23 @@ impl Component for ProblemReportDialog {
                         root.close()
                     }
                 }
+                PrivateMsg::ConsentSelected(index) => {
+                    if let Some(consent) = ProblemReportConsent::ALL.get(index as usize) {
+                        if let Err(err) = crate::consent::consent_set_problem_report(*consent) {
+                            log::warn!("Cannot store problem report consent: {err:?}");
+                        }
+                    }
+                }
+                PrivateMsg::Snooze => {
+                    if let Err(err) = crate::consent::consent_snooze(DEFAULT_SNOOZE_DAYS) {
+                        log::warn!("Cannot snooze problem report: {err:?}");
+                    }
+                    root.close();
+                }
                 PrivateMsg::EscapePressed => {
-                    if widgets
+                    if let Some(cancellation) = &self.cancellation {
+                        cancellation.cancel();
+                    } else if widgets
                         .stack_view
                         .visible_child()
                         .map_or(false, |child| child == widgets.error_page)
@@ -271,6 +344,15 @@ impl Component for ProblemReportDialog {
                 widgets.stack_view.set_transition_type(gtk::StackTransitionType::None);
                 widgets.stack_view.set_visible_child(&widgets.start_page);
                 self.file_name = crate::proc_dir::create_problem_report_file_name(self.binary_name);
+                let consent = crate::consent::consent_state().unwrap_or_else(|err| {
+                    log::warn!("Cannot read problem report consent: {err:?}");
+                    Default::default()
+                });
+                let position = ProblemReportConsent::ALL
+                    .iter()
+                    .position(|consent_choice| *consent_choice == consent.problem_report)
+                    .unwrap_or_default();
+                widgets.consent_row.set_selected(position as u32);
                 let top_level = transient_for.toplevel_window();
                 root.set_transient_for(top_level.as_ref());
                 self.file_chooser.widget().set_transient_for(top_level.as_ref());
@@ -278,4 +360,49 @@ impl Component for ProblemReportDialog {
             }
         }
     }
+
+    fn update_cmd_with_view(
+        &mut self,
+        widgets: &mut Self::Widgets,
+        message: Self::CommandOutput,
+        sender: ComponentSender<Self>,
+        _root: &Self::Root,
+    ) {
+        match message {
+            CommandMsg::Progress(progress) => {
+                widgets.progress_bar.set_fraction(progress.fraction());
+                widgets.progress_bar.set_text(Some(&fl!(
+                    "problem-report-dialog",
+                    "progress-files",
+                    files_done = progress.files_done,
+                    files_total = progress.files_total
+                )));
+            }
+            CommandMsg::Finished(result) => {
+                self.cancellation = None;
+                match result {
+                    Err(err) if err.is::<ArchiveCancelled>() => {
+                        widgets.stack_view.set_transition_type(gtk::StackTransitionType::None);
+                        widgets.stack_view.set_visible_child(&widgets.start_page);
+                    }
+                    Err(err) => {
+                        widgets
+                            .error_page
+                            .set_title(&fl!("problem-report-dialog", "error-create-title"));
+                        widgets
+                            .error_page
+                            .set_description(Some(glib::markup_escape_text(&format!("{:?}", err)).as_str()));
+                        sender.input(ProblemReportDialogInput::PrivateMessage(PrivateMsg::SwitchForwardTo(
+                            widgets.error_page.clone().into(),
+                        )));
+                    }
+                    Ok(()) => {
+                        sender.input(ProblemReportDialogInput::PrivateMessage(PrivateMsg::SwitchForwardTo(
+                            widgets.success_page.clone().into(),
+                        )));
+                    }
+                }
+            }
+        }
+    }
 }
@@ -1,13 +1,29 @@
-use crate::localization::helper::fl;
+use crate::{
+    archive_filter::ArchiveFilter,
+    encryption::ArchiveEncryption,
+    localization::helper::fl,
+    manifest::{
+        ExcludedEntry, Manifest, RedactedEntry, TruncatedEntry, ARCHIVE_COLLECTED_DIR_NAME, ARCHIVE_CURRENT_DIR_NAME,
+        ARCHIVE_DEFAULT_FAILED_SOURCE_NAME, ARCHIVE_FAILED_DIR_NAME, MANIFEST_FILE_NAME,
+    },
+    panic_record::{BacktraceFilter, PanicDetails, PANIC_DETAILS_FILE_SUFFIX},
+    redaction::{RedactionConfig, Redactor},
+    report::Run,
+    summary::SUMMARY_FILE_NAME,
+};
 use anyhow::{Context, Result};
 use fs4::fs_std::FileExt;
 use once_cell::sync::{Lazy, OnceCell};
 use std::{
+    collections::HashSet,
     fs::File,
-    io::{Read, Write},
+    io::{BufRead, BufReader, Read, Seek, SeekFrom, Write},
     panic,
     path::{Path, PathBuf},
-    sync::RwLock,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
 };
 use walkdir::WalkDir;
 use zip::{write::SimpleFileOptions, ZipWriter};
@@ -15,17 +31,177 @@ use zip::{write::SimpleFileOptions, ZipWriter};
 pub const ARCHIVE_DEFAULT_FILE_EXTENSION: &str = "zip";
 pub const ARCHIVE_MIME_TYPE: &str = "application/x-zip";
 
-const CURRENT_DIR_FMT: &str = "%Y-%m-%d_%H_%M_%S";
-const PROC_DIR_NAME: &str = "proc";
-const PROC_FAILED_DIR_NAME: &str = "proc_failed";
-const LOCK_FILE_NAME: &str = "run.lock";
-const REPORT_FILE_NAME: &str = "exit_report.txt";
+pub(crate) const CURRENT_DIR_FMT: &str = "%Y-%m-%d_%H_%M_%S";
+pub(crate) const PROC_DIR_NAME: &str = "proc";
+pub(crate) const PROC_FAILED_DIR_NAME: &str = "proc_failed";
+pub(crate) const LOCK_FILE_NAME: &str = "run.lock";
+pub(crate) const REPORT_FILE_NAME: &str = "exit_report.txt";
+pub(crate) const REPORT_ABORTED_UNEXPECTED: &str = "The program run was aborted unexpectedly.\n\
+    This behavior is typically caused by a SIGKILL, but it can \
+    also be the result of a program crash or immediate termination.";
 const KEEP_NUMBER_OF_FAILED_RUNS: usize = 20;
-const PANIC_FILE_EXTENSION: &str = "panic";
+pub(crate) const PANIC_FILE_EXTENSION: &str = "panic";
+pub(crate) const HANG_FILE_EXTENSION: &str = "hang";
+pub(crate) const DUMP_FILE_EXTENSION: &str = "dump";
+const ARCHIVE_COPY_BUFFER_SIZE: usize = 64 * 1024;
+// Estimated size of the zip headers of an entry without its name
+const ARCHIVE_ENTRY_OVERHEAD: u64 = 128;
+// Additional space kept free in size limited archives for the end of the archive and the encryption
+const ARCHIVE_MANIFEST_RESERVE: u64 = 4 * 1024;
+// Estimated size of a redacted entry in the manifest without its name
+const ARCHIVE_REDACTED_ENTRY_SIZE: u64 = 64;
+// Maximum number of truncations to fit the measured summary and manifest into size limited archives
+const ARCHIVE_TRUNCATE_ATTEMPTS: usize = 4;
+// Name of the plain archive in the temporary directory before it is encrypted
+#[cfg(feature = "encryption")]
+const ARCHIVE_TEMP_FILE_NAME: &str = "archive.zip";
 
 static RUN_DIR_HOLDER: OnceCell<PathBuf> = OnceCell::new();
 pub type ProcDirArchiveCallback = fn();
 static PROC_DIR_ARCHIVE_CREATE_CALLBACK: OnceCell<ProcDirArchiveCallback> = OnceCell::new();
+static ARCHIVE_FILTER: Lazy<RwLock<ArchiveFilter>> = Lazy::new(|| RwLock::new(ArchiveFilter::default()));
+static ARCHIVE_REDACTION: Lazy<RwLock<RedactionConfig>> = Lazy::new(|| RwLock::new(RedactionConfig::default()));
+static PANIC_BACKTRACE_FILTER: Lazy<RwLock<BacktraceFilter>> = Lazy::new(|| RwLock::new(BacktraceFilter::default()));
+static AUTO_EXPORT_POLICY: Lazy<RwLock<Option<AutoExportPolicy>>> = Lazy::new(|| RwLock::new(None));
+
+/// Progress of an archive creation, reported after each chunk written to the archive.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ArchiveProgress {
+    pub files_done: u64,
+    pub files_total: u64,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+}
+
+impl ArchiveProgress {
+    /// Returns the progress as a fraction between 0.0 and 1.0 based on the number of bytes.
+    pub fn fraction(&self) -> f64 {
+        if self.bytes_total == 0 {
+            if self.files_total == 0 {
+                return 1.0;
+            }
+            return self.files_done as f64 / self.files_total as f64;
+        }
+        self.bytes_done as f64 / self.bytes_total as f64
+    }
+}
+
+pub type ArchiveProgressCallback = Box<dyn FnMut(&ArchiveProgress) + Send>;
+
+/// Token to cancel a running archive creation from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Error returned if an archive creation was cancelled by a [`CancellationToken`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArchiveCancelled;
+
+impl std::fmt::Display for ArchiveCancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Archive creation was cancelled")
+    }
+}
+
+impl std::error::Error for ArchiveCancelled {}
+
+/// What to do if an archive would exceed [`ArchiveSizeLimit::max_size`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveSizeStrategy {
+    /// Splits the created archive into numbered volumes `<file>.001`, `<file>.002`, ... of at most the maximum size.
+    ///
+    /// The volumes can be joined with [`archive_join_volumes`] or by concatenating them in order.
+    Split,
+    /// Drops the oldest runs first and then keeps only the tail of the largest files.
+    ///
+    /// The limit is applied to the uncompressed file sizes, so the resulting archive is usually much smaller.
+    /// Everything that was cut is recorded in the manifest of the archive.
+    Truncate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArchiveSizeLimit {
+    /// Maximum size of a created file in bytes.
+    pub max_size: u64,
+    pub strategy: ArchiveSizeStrategy,
+}
+
+/// Policy to export the runs of previous processes that failed, when [`proc_dir`] is initialized.
+#[derive(Debug, Clone)]
+pub struct AutoExportPolicy {
+    /// Directory the archives are written to, e.g. a shared mount.
+    pub dir: PathBuf,
+    /// Name of the application used in the file names `<host>_<app>_<time>.zip`,
+    /// or `<host>_<app>_<time>.zip.age` if the archives are encrypted with a public key.
+    pub app_name: String,
+    pub encryption: Option<ArchiveEncryption>,
+    /// Removes the exported runs after the archive was written successfully.
+    pub remove_exported: bool,
+}
+
+impl AutoExportPolicy {
+    pub fn new(dir: PathBuf, app_name: impl Into<String>) -> Self {
+        Self {
+            dir,
+            app_name: app_name.into(),
+            encryption: None,
+            remove_exported: true,
+        }
+    }
+}
+
+/// Options for a single archive creation.
+#[derive(Default)]
+pub struct ArchiveOptions {
+    pub progress: Option<ArchiveProgressCallback>,
+    pub cancellation: Option<CancellationToken>,
+    pub encryption: Option<ArchiveEncryption>,
+    pub size_limit: Option<ArchiveSizeLimit>,
+    /// Adds only runs that were not exported before according to the export ledger.
+    ///
+    /// The run of the current process is always added, because it may have changed since its last export.
+    pub only_not_exported: bool,
+}
+
+impl std::fmt::Debug for ArchiveOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ArchiveOptions")
+            .field("progress", &self.progress.is_some())
+            .field("cancellation", &self.cancellation)
+            .field("encryption", &self.encryption)
+            .field("size_limit", &self.size_limit)
+            .field("only_not_exported", &self.only_not_exported)
+            .finish()
+    }
+}
+
+impl ArchiveOptions {
+    fn check_cancelled(&self) -> Result<()> {
+        if self.cancellation.as_ref().is_some_and(|token| token.is_cancelled()) {
+            return Err(ArchiveCancelled.into());
+        }
+        Ok(())
+    }
+
+    fn report_progress(&mut self, progress: &ArchiveProgress) {
+        if let Some(callback) = self.progress.as_mut() {
+            callback(progress);
+        }
+    }
+}
 
 fn create_dir_all_with_panic<P: AsRef<Path> + std::fmt::Debug>(path: P) {
     std::fs::create_dir_all(&path).unwrap_or_else(|error| panic!("Cannot create directory {:?}: {:?}", path, error));
@@ -34,6 +210,12 @@ fn create_dir_all_with_panic<P: AsRef<Path> + std::fmt::Debug>(path: P) {
 pub fn set_proc_dir(path: PathBuf) {
     RUN_DIR_HOLDER.set(path).expect("Proc directory already set");
     create_dir_all_with_panic(RUN_DIR_HOLDER.get().unwrap());
+    crate::build_info::write_to_run_dir(RUN_DIR_HOLDER.get().unwrap());
+}
+
+/// Returns the run directory if it was already created by [`proc_dir`] or set by [`set_proc_dir`].
+pub(crate) fn proc_dir_if_initialized() -> Option<&'static PathBuf> {
+    RUN_DIR_HOLDER.get()
 }
 
 pub fn default_proc_dir() -> &'static PathBuf {
@@ -50,21 +232,62 @@ pub fn default_failed_dir() -> &'static PathBuf {
     })
 }
 
-static FAILED_DIRS: Lazy<RwLock<Vec<PathBuf>>> = Lazy::new(|| RwLock::new(vec![default_failed_dir().clone()]));
+// Failed directories with their source names used in the archive layout
+static FAILED_DIRS: Lazy<RwLock<Vec<(String, PathBuf)>>> = Lazy::new(|| {
+    RwLock::new(vec![(
+        ARCHIVE_DEFAULT_FAILED_SOURCE_NAME.to_string(),
+        default_failed_dir().clone(),
+    )])
+});
+// Additional files and directories added to every archive
+static COLLECTED_DIRS: Lazy<RwLock<Vec<(String, PathBuf)>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+fn unique_source_name(sources: &[(String, PathBuf)], name: &str) -> String {
+    let mut unique_name = name.to_string();
+    let mut index = 1;
+    while sources.iter().any(|(source_name, _)| *source_name == unique_name) {
+        index += 1;
+        unique_name = format!("{name}_{index}");
+    }
+    unique_name
+}
 
+pub(crate) fn failed_dirs() -> Vec<(String, PathBuf)> {
+    FAILED_DIRS.read().unwrap().clone()
+}
+
+/// Adds a directory with failed runs, its source name in archives is the name of the directory.
 pub fn failed_dir_add(path: PathBuf) {
-    FAILED_DIRS.write().unwrap().push(path)
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| ARCHIVE_FAILED_DIR_NAME.to_string());
+    failed_dir_add_with_name(&name, path)
+}
+
+/// Adds a directory with failed runs, which are stored as `failed/<name>/<run>` in archives.
+///
+/// If the name is already used a number is appended.
+pub fn failed_dir_add_with_name(name: &str, path: PathBuf) {
+    let mut failed_dirs = FAILED_DIRS.write().unwrap();
+    let name = unique_source_name(&failed_dirs, name);
+    failed_dirs.push((name, path))
+}
+
+/// Adds a file or directory that is stored as `collected/<name>` in every archive and is never removed.
+///
+/// If the name is already used a number is appended.
+pub fn collected_dir_add(name: &str, path: PathBuf) {
+    let mut collected_dirs = COLLECTED_DIRS.write().unwrap();
+    let name = unique_source_name(&collected_dirs, name);
+    collected_dirs.push((name, path))
 }
 
 fn write_report_aborted_unexpected(path: &Path) -> Result<()> {
     let report_file_path = path.join(REPORT_FILE_NAME);
     if !report_file_path.try_exists()? {
-        std::fs::write(
-            report_file_path,
-            "The program run was aborted unexpectedly.\n\
-            This behavior is typically caused by a SIGKILL, but it can \
-            also be the result of a program crash or immediate termination.",
-        )?;
+        let quota_section = crate::disk_quota::exit_report_section(path).unwrap_or_default();
+        std::fs::write(report_file_path, format!("{REPORT_ABORTED_UNEXPECTED}{quota_section}"))?;
     }
     Ok(())
 }
@@ -78,7 +301,8 @@ pub fn write_report_error(err: &anyhow::Error) {
         .with_context(|| "Cannot open report file")
     {
         Ok(mut file) => {
-            if let Err(err) = writeln!(file, "The program run exited with error:\n{:?}", err)
+            let quota_section = crate::disk_quota::exit_report_section(proc_dir()).unwrap_or_default();
+            if let Err(err) = writeln!(file, "The program run exited with error:\n{:?}{quota_section}", err)
                 .with_context(|| "Cannot write report file")
             {
                 log::warn!("{:?}", err)
@@ -88,12 +312,14 @@ pub fn write_report_error(err: &anyhow::Error) {
     }
 }
 
-fn move_to_failed_dir() -> Result<()> {
+/// Moves the runs of previous processes to the failed directory and returns their new directories.
+fn move_to_failed_dir() -> Result<Vec<PathBuf>> {
     // Move failed runs out of the run directory:
     let failed_dir = default_failed_dir();
     let proc_dir = default_proc_dir();
+    let mut moved_dirs = Vec::new();
 
-    let preserve_dir = |from: &Path| {
+    let mut preserve_dir = |from: &Path| {
         let from_dir_name = from
             .file_name()
             .unwrap_or_else(|| panic!("Cannot get name of path '{:?}'", from));
@@ -104,7 +330,9 @@ fn move_to_failed_dir() -> Result<()> {
                 from.to_string_lossy(),
                 to.to_string_lossy()
             )
-        })
+        })?;
+        moved_dirs.push(to);
+        Ok::<_, anyhow::Error>(())
     };
 
     if let Ok(entry) = std::fs::read_dir(proc_dir) {
@@ -147,7 +375,21 @@ fn move_to_failed_dir() -> Result<()> {
             }
         }
     }
-    Ok(())
+    Ok(moved_dirs)
+}
+
+/// Returns `true` if the lock file of the run directory is locked by a running process.
+pub(crate) fn run_dir_in_use(path: &Path) -> bool {
+    match File::open(path.join(LOCK_FILE_NAME)) {
+        Ok(lock_file) => match lock_file.try_lock_exclusive() {
+            Ok(()) => {
+                _ = FileExt::unlock(&lock_file);
+                false
+            }
+            Err(_error) => true,
+        },
+        Err(_error) => false,
+    }
 }
 
 #[allow(dead_code)] // clippy warning: field `0` is never read
@@ -181,63 +423,656 @@ pub fn proc_dir() -> &'static PathBuf {
         let data_dir = chrono::Local::now().format(CURRENT_DIR_FMT).to_string();
         let data_dir = default_proc_dir().join(std::path::Path::new(&data_dir));
 
+        create_dir_all_with_panic(default_proc_dir());
+        crate::disk_quota::check_free_space(default_proc_dir());
         create_dir_all_with_panic(&data_dir);
         if let Err(err) = create_lock_file(&data_dir) {
             panic!("Cannot lock directory: {:?}", err);
         }
-        move_to_failed_dir().unwrap_or_else(|error| panic!("Cannot move failed runs: {:?}", error));
+        crate::disk_quota::start_monitor(&data_dir);
+        crate::build_info::write_to_run_dir(&data_dir);
+        let failed_run_dirs =
+            move_to_failed_dir().unwrap_or_else(|error| panic!("Cannot move failed runs: {:?}", error));
+        auto_export_failed_runs(&failed_run_dirs);
         cleanup_dir(default_failed_dir()).unwrap_or_else(|error| panic!("Cannot cleanup failed runs: {:?}", error));
+        #[cfg(feature = "upload")]
+        crate::outbox::outbox_flush_automatically();
         data_dir
     })
 }
 
 pub fn cleanup() -> Result<()> {
+    // A hang record written at the moment must be complete before the run is checked for it
+    crate::hang_watchdog::hang_watchdog_stop();
+    // The sampler must not write into the run directory while it is removed
+    #[cfg(feature = "sysinfo")]
+    crate::resource_sampler::resource_sampler_stop();
     if let Some(data_dir) = RUN_DIR_HOLDER.get() {
-        // Remove the current run directory
-        std::fs::remove_dir_all(data_dir)?;
+        cleanup_run_dir(data_dir)?;
     }
 
     // Cleanup other failed runs
     cleanup_dir(default_failed_dir())
-    // for dir in FAILED_DIRS.read().unwrap().iter() {
+    // for (_, dir) in FAILED_DIRS.read().unwrap().iter() {
     //     cleanup_dir(&dir)?;
     // }
     // Ok(())
 }
 
-fn create_archive(src_dirs: &[PathBuf], archive_file_path: &Path) -> Result<()> {
-    if src_dirs.is_empty() {
+/// Removes the run directory at the end of the run, a run with a hang record or a dump is kept as failed run.
+pub(crate) fn cleanup_run_dir(data_dir: &Path) -> Result<()> {
+    if dir_has_file_with_extension(data_dir, HANG_FILE_EXTENSION)?
+        || dir_has_file_with_extension(data_dir, DUMP_FILE_EXTENSION)?
+    {
+        // Keep the run, without lock file it is moved to the failed runs by the next process
+        let lock_file_path = data_dir.join(LOCK_FILE_NAME);
+        std::fs::remove_file(&lock_file_path)
+            .with_context(|| format!("Cannot remove file '{}'", lock_file_path.to_string_lossy()))?;
+    } else {
+        // Remove the current run directory
+        std::fs::remove_dir_all(data_dir)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchiveSourceKind {
+    Run,
+    Collected,
+}
+
+/// File or directory added to an archive with the given name.
+struct ArchiveSource {
+    path: PathBuf,
+    name: PathBuf,
+    kind: ArchiveSourceKind,
+}
+
+/// Returns all runs in a directory as sources named `<name>/<run>`, none if the directory does not exist.
+fn run_sources(dir: &Path, name: &Path) -> Result<Vec<ArchiveSource>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    Ok(std::fs::read_dir(dir)?
+        .map(|entry| {
+            let path = entry?.path();
+            Ok(ArchiveSource {
+                name: name.join(path.file_name().unwrap_or_default()),
+                path,
+                kind: ArchiveSourceKind::Run,
+            })
+        })
+        .collect::<std::io::Result<Vec<_>>>()?)
+}
+
+fn current_run_sources() -> Result<Vec<ArchiveSource>> {
+    run_sources(default_proc_dir(), Path::new(ARCHIVE_CURRENT_DIR_NAME))
+}
+
+fn failed_run_sources() -> Result<Vec<ArchiveSource>> {
+    let mut sources = Vec::new();
+    for (name, dir) in FAILED_DIRS.read().unwrap().iter() {
+        sources.append(&mut run_sources(dir, &Path::new(ARCHIVE_FAILED_DIR_NAME).join(name))?);
+    }
+    Ok(sources)
+}
+
+fn collected_sources() -> Vec<ArchiveSource> {
+    COLLECTED_DIRS
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|(_, path)| path.exists())
+        .map(|(name, path)| ArchiveSource {
+            path: path.clone(),
+            name: Path::new(ARCHIVE_COLLECTED_DIR_NAME).join(name),
+            kind: ArchiveSourceKind::Collected,
+        })
+        .collect()
+}
+
+/// Removes the runs that were exported before, except the run of the current process.
+fn retain_not_exported(sources: &mut Vec<ArchiveSource>, exported_runs: &HashSet<String>) {
+    let running_dir = RUN_DIR_HOLDER.get();
+    sources.retain(|source| {
+        source.kind != ArchiveSourceKind::Run
+            || Some(&source.path) == running_dir
+            || !exported_runs.contains(source.path.file_name().unwrap_or_default().to_string_lossy().as_ref())
+    });
+}
+
+/// Records the runs written to the archive in the export ledger.
+fn record_export(sources: &[ArchiveSource], archive: &CreatedArchive) {
+    let runs = sources
+        .iter()
+        .filter(|source| source.kind == ArchiveSourceKind::Run && !archive.dropped_dirs.contains(&source.path))
+        .map(|source| {
+            source
+                .path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string()
+        })
+        .collect();
+    crate::export_ledger::export_ledger_add_archive(runs, &archive.files);
+}
+
+struct CreatedArchive {
+    /// Written archive file or its volumes.
+    files: Vec<PathBuf>,
+    /// Runs that were not added to fit the size limit.
+    dropped_dirs: Vec<PathBuf>,
+}
+
+fn create_archive(
+    sources: &[ArchiveSource],
+    archive_file_path: &Path,
+    options: &mut ArchiveOptions,
+) -> Result<CreatedArchive> {
+    if sources.is_empty() {
         anyhow::bail!("Cannot archive empty list of directories");
     }
+    if options.size_limit.is_some_and(|size_limit| size_limit.max_size == 0) {
+        anyhow::bail!("Cannot create archive with a maximum size of zero bytes");
+    }
 
-    let archive_file = File::create(archive_file_path)
-        .with_context(|| format!("Cannot create archive '{}'", archive_file_path.to_string_lossy()))?;
+    let result = match options.encryption.clone() {
+        #[cfg(feature = "encryption")]
+        Some(ArchiveEncryption::PublicKey(public_key)) => {
+            // Validate the key before the time consuming archive creation
+            crate::encryption::parse_recipient(&public_key)?;
+            // The plain archive is written into a private directory, the destination may be a shared location
+            let temp_dir = tempfile::Builder::new()
+                .prefix("mxl-investigator-")
+                .tempdir()
+                .with_context(|| "Cannot create temporary directory")?;
+            let zip_file_path = temp_dir.path().join(ARCHIVE_TEMP_FILE_NAME);
+            write_archive(sources, &zip_file_path, options).and_then(|dropped_dirs| {
+                crate::encryption::encrypt_file(&zip_file_path, archive_file_path, &public_key)?;
+                Ok(dropped_dirs)
+            })
+        }
+        _ => write_archive(sources, archive_file_path, options),
+    };
+    let result = result.and_then(|dropped_dirs| {
+        let files = match options.size_limit {
+            Some(ArchiveSizeLimit {
+                max_size,
+                strategy: ArchiveSizeStrategy::Split,
+            }) => split_into_volumes(archive_file_path, max_size)?,
+            Some(ArchiveSizeLimit {
+                max_size,
+                strategy: ArchiveSizeStrategy::Truncate,
+            }) => {
+                let size = archive_file_path.metadata()?.len();
+                if size > max_size {
+                    log::warn!(
+                        "Archive '{}' exceeds the size limit of {max_size} bytes with {size} bytes",
+                        archive_file_path.to_string_lossy()
+                    );
+                }
+                vec![archive_file_path.to_path_buf()]
+            }
+            None => vec![archive_file_path.to_path_buf()],
+        };
+        Ok(CreatedArchive { files, dropped_dirs })
+    });
+    if result.is_err() {
+        // Do not leave a partially written archive behind
+        _ = std::fs::remove_file(archive_file_path);
+    }
+    result
+}
 
-    let mut zip = ZipWriter::new(archive_file);
-    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Bzip2);
-
-    for src_dir in src_dirs {
-        let parent_dir = src_dir
-            .parent()
-            .unwrap_or_else(|| src_dir)
-            .parent()
-            .unwrap_or_else(|| src_dir)
-            .parent()
-            .unwrap_or_else(|| src_dir);
-        let walk_dir = WalkDir::new(src_dir);
+fn volume_file_path(archive_file_path: &Path, index: usize) -> PathBuf {
+    let mut volume_file_path = archive_file_path.as_os_str().to_owned();
+    volume_file_path.push(format!(".{index:03}"));
+    PathBuf::from(volume_file_path)
+}
+
+fn split_into_volumes(archive_file_path: &Path, max_size: u64) -> Result<Vec<PathBuf>> {
+    if archive_file_path.metadata()?.len() <= max_size {
+        return Ok(vec![archive_file_path.to_path_buf()]);
+    }
+
+    let mut input = File::open(archive_file_path)
+        .with_context(|| format!("Cannot open archive '{}'", archive_file_path.to_string_lossy()))?;
+    let mut volumes = Vec::new();
+    let result = (|| loop {
+        let volume_path = volume_file_path(archive_file_path, volumes.len() + 1);
+        let mut output = File::create(&volume_path)
+            .with_context(|| format!("Cannot create volume '{}'", volume_path.to_string_lossy()))?;
+        volumes.push(volume_path.clone());
+        let len = std::io::copy(&mut (&mut input).take(max_size), &mut output)
+            .with_context(|| format!("Cannot write volume '{}'", volume_path.to_string_lossy()))?;
+        if len == 0 {
+            volumes.pop();
+            drop(output);
+            std::fs::remove_file(&volume_path)?;
+            return Ok(());
+        }
+    })();
+    if let Err(err) = result {
+        rm_dirs(&volumes)?;
+        return Err(err);
+    }
+    std::fs::remove_file(archive_file_path)
+        .with_context(|| format!("Cannot remove file '{}'", archive_file_path.to_string_lossy()))?;
+    Ok(volumes)
+}
+
+/// Joins the volumes of an archive created with [`ArchiveSizeStrategy::Split`] into a single archive.
+///
+/// The given path is the path of the archive that was passed to the archive creation, without volume number.
+pub fn archive_join_volumes(archive_file_path: &Path, output_file_path: &Path) -> Result<()> {
+    let mut output = File::create(output_file_path)
+        .with_context(|| format!("Cannot create archive '{}'", output_file_path.to_string_lossy()))?;
+    let mut index = 1;
+    loop {
+        let volume_path = volume_file_path(archive_file_path, index);
+        if !volume_path.try_exists()? {
+            break;
+        }
+        let mut input = File::open(&volume_path)
+            .with_context(|| format!("Cannot open volume '{}'", volume_path.to_string_lossy()))?;
+        std::io::copy(&mut input, &mut output)
+            .with_context(|| format!("Cannot read volume '{}'", volume_path.to_string_lossy()))?;
+        index += 1;
+    }
+    if index == 1 {
+        anyhow::bail!("No volumes found for archive '{}'", archive_file_path.to_string_lossy());
+    }
+    Ok(())
+}
+
+#[derive(Clone)]
+enum ArchiveEntryKind {
+    File,
+    Directory,
+}
+
+#[derive(Clone)]
+struct ArchiveEntry {
+    path: PathBuf,
+    name: PathBuf,
+    kind: ArchiveEntryKind,
+    /// Index of the source, `None` for directories shared by several sources
+    source: Option<usize>,
+    size: u64,
+    /// Number of bytes at the end of the file to keep if it is truncated
+    keep: Option<u64>,
+}
+
+fn collect_archive_entries(sources: &[ArchiveSource], manifest: &mut Manifest) -> Result<Vec<ArchiveEntry>> {
+    let filter = ARCHIVE_FILTER.read().unwrap().compile()?;
+    let mut entries = Vec::new();
+    let mut shared_dirs = std::collections::BTreeSet::new();
+
+    for (index, source) in sources.iter().enumerate() {
+        // Write parent directories like `failed/default` explicitly, once for all sources
+        let mut parents = source
+            .name
+            .ancestors()
+            .skip(1)
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .collect::<Vec<_>>();
+        parents.reverse();
+        for parent in parents {
+            if shared_dirs.insert(parent.to_path_buf()) {
+                entries.push(ArchiveEntry {
+                    path: source.path.clone(),
+                    name: parent.to_path_buf(),
+                    kind: ArchiveEntryKind::Directory,
+                    source: None,
+                    size: 0,
+                    keep: None,
+                });
+            }
+        }
+
+        let walk_dir = WalkDir::new(&source.path);
         let it = walk_dir.into_iter().filter_map(|e| e.ok());
-        let mut buffer = Vec::new();
 
         for entry in it {
             let path = entry.path();
-            let name = path.strip_prefix(parent_dir).unwrap();
+            let relative_path = path.strip_prefix(&source.path).unwrap();
+            let name = if relative_path.as_os_str().is_empty() {
+                source.name.clone()
+            } else {
+                source.name.join(relative_path)
+            };
+            let name = name.as_path();
 
-            // Write file or directory explicitly
-            // Some unzip tools unzip files with directory paths correctly, some do not!
             if path.is_file() {
+                let size = entry.metadata().map(|metadata| metadata.len()).unwrap_or_default();
+                if let Some(reason) = filter.exclude_reason(name, size) {
+                    log::trace!("excluding file {path:?}: {reason}");
+                    manifest.excluded.push(ExcludedEntry {
+                        name: name.to_string_lossy().to_string(),
+                        reason,
+                    });
+                    continue;
+                }
+                entries.push(ArchiveEntry {
+                    path: path.to_path_buf(),
+                    name: name.to_path_buf(),
+                    kind: ArchiveEntryKind::File,
+                    source: Some(index),
+                    size,
+                    keep: None,
+                });
+            } else if path.is_dir() {
+                entries.push(ArchiveEntry {
+                    path: path.to_path_buf(),
+                    name: name.to_path_buf(),
+                    kind: ArchiveEntryKind::Directory,
+                    source: Some(index),
+                    size: 0,
+                    keep: None,
+                });
+            }
+        }
+    }
+    Ok(entries)
+}
+
+fn estimate_archive_size(entries: &[ArchiveEntry]) -> u64 {
+    entries
+        .iter()
+        .map(|entry| {
+            ARCHIVE_ENTRY_OVERHEAD + 2 * entry.name.as_os_str().len() as u64 + entry.keep.unwrap_or(entry.size)
+        })
+        .sum()
+}
+
+/// Drops the oldest runs and truncates the largest files until the entries, the summary and the manifest
+/// fit into the maximum size.
+///
+/// Returns the indices of the dropped sources.
+fn truncate_archive_entries(
+    sources: &[ArchiveSource],
+    entries: &mut Vec<ArchiveEntry>,
+    max_size: u64,
+    manifest: &mut Manifest,
+    redactor: Option<&Redactor>,
+) -> Result<Vec<usize>> {
+    // The summary and the manifest depend on what is dropped and truncated, so the truncation is repeated
+    // with their measured size reserved until they fit
+    let mut reserve = ARCHIVE_MANIFEST_RESERVE;
+    let mut attempt = 1;
+    loop {
+        let mut truncated_entries = entries.clone();
+        let mut truncated_manifest = manifest.clone();
+        let budget = max_size.saturating_sub(reserve);
+        let dropped = truncate_to_budget(sources, &mut truncated_entries, budget, &mut truncated_manifest);
+        let required = ARCHIVE_MANIFEST_RESERVE
+            + archive_metadata_size(sources, &truncated_entries, &truncated_manifest, redactor)?;
+        if required <= reserve || attempt == ARCHIVE_TRUNCATE_ATTEMPTS {
+            *entries = truncated_entries;
+            *manifest = truncated_manifest;
+            return Ok(dropped);
+        }
+        reserve = required;
+        attempt += 1;
+    }
+}
+
+/// Returns the size of the summary and the manifest written after the entries of an archive.
+fn archive_metadata_size(
+    sources: &[ArchiveSource],
+    entries: &[ArchiveEntry],
+    manifest: &Manifest,
+    redactor: Option<&Redactor>,
+) -> Result<u64> {
+    let runs = archive_runs(sources, entries, redactor)?;
+    let summary_size = crate::summary::render(&runs, manifest).len() as u64;
+    let mut manifest_size = serde_json::to_vec_pretty(manifest)?.len() as u64;
+    if redactor.is_some() {
+        // Redactions are only known while the files are written, each file may get an entry
+        manifest_size += entries
+            .iter()
+            .filter(|entry| matches!(entry.kind, ArchiveEntryKind::File))
+            .map(|entry| ARCHIVE_REDACTED_ENTRY_SIZE + entry.name.as_os_str().len() as u64)
+            .sum::<u64>();
+    }
+    let headers_size = 2 * ARCHIVE_ENTRY_OVERHEAD + 2 * (SUMMARY_FILE_NAME.len() + MANIFEST_FILE_NAME.len()) as u64;
+    Ok(summary_size + manifest_size + headers_size)
+}
+
+/// Drops the oldest runs and truncates the largest files until the entries fit into the budget.
+fn truncate_to_budget(
+    sources: &[ArchiveSource],
+    entries: &mut Vec<ArchiveEntry>,
+    budget: u64,
+    manifest: &mut Manifest,
+) -> Vec<usize> {
+    // The run directory names start with their creation time, the newest run is always kept
+    let mut runs = (0..sources.len())
+        .filter(|index| sources[*index].kind == ArchiveSourceKind::Run)
+        .collect::<Vec<_>>();
+    runs.sort_by_key(|index| sources[*index].path.file_name().map(|name| name.to_os_string()));
+    runs.pop();
+    let mut dropped = Vec::new();
+    for run in runs {
+        if estimate_archive_size(entries) <= budget {
+            break;
+        }
+        entries.retain(|entry| entry.source != Some(run));
+        manifest
+            .dropped_runs
+            .push(sources[run].name.to_string_lossy().to_string());
+        dropped.push(run);
+    }
+
+    let mut excess = estimate_archive_size(entries).saturating_sub(budget);
+    let mut files = entries
+        .iter_mut()
+        .filter(|entry| matches!(entry.kind, ArchiveEntryKind::File))
+        .collect::<Vec<_>>();
+    files.sort_by_key(|entry| std::cmp::Reverse(entry.size));
+    for entry in files {
+        if excess == 0 {
+            break;
+        }
+        let cut = excess.min(entry.size);
+        entry.keep = Some(entry.size - cut);
+        excess -= cut;
+        manifest.truncated.push(TruncatedEntry {
+            name: entry.name.to_string_lossy().to_string(),
+            size: entry.size,
+            kept: entry.size - cut,
+        });
+    }
+    dropped
+}
+
+/// Copies a file into the current archive entry and returns the number of redactions.
+///
+/// Text files are redacted line by line if a redactor is given, other files are copied unchanged.
+fn write_archive_file(
+    file: impl Read,
+    path: &Path,
+    zip: &mut ZipWriter<File>,
+    redactor: Option<&Redactor>,
+    progress: &mut ArchiveProgress,
+    options: &mut ArchiveOptions,
+) -> Result<usize> {
+    let read_context = || {
+        format!(
+            "Cannot read from file '{}' to add it to the archive.",
+            path.to_string_lossy()
+        )
+    };
+    let write_context = || format!("Cannot write file buffer '{}' to the archive.", path.to_string_lossy());
+
+    let mut reader = BufReader::with_capacity(ARCHIVE_COPY_BUFFER_SIZE, file);
+    let redactor = match redactor {
+        Some(redactor) if !reader.fill_buf().with_context(read_context)?.contains(&0) => Some(redactor),
+        _ => None,
+    };
+    let mut count = 0;
+    let mut buffer = Vec::with_capacity(ARCHIVE_COPY_BUFFER_SIZE);
+    loop {
+        buffer.clear();
+        let len = match redactor {
+            Some(_) => reader.read_until(b'\n', &mut buffer).with_context(read_context)?,
+            None => {
+                buffer.resize(ARCHIVE_COPY_BUFFER_SIZE, 0);
+                let len = reader.read(&mut buffer).with_context(read_context)?;
+                buffer.truncate(len);
+                len
+            }
+        };
+        if len == 0 {
+            break;
+        }
+        match redactor {
+            Some(redactor) => {
+                let (line, line_count) = redactor.redact(&buffer);
+                count += line_count;
+                zip.write_all(&line).with_context(write_context)?;
+            }
+            None => zip.write_all(&buffer).with_context(write_context)?,
+        }
+        // Files may grow while they are archived, never report more than the total
+        progress.bytes_done = (progress.bytes_done + len as u64).min(progress.bytes_total);
+        options.report_progress(progress);
+        options.check_cancelled()?;
+    }
+    Ok(count)
+}
+
+/// Builds the runs of the archive from the entries, with the content as it is written to the archive.
+fn archive_runs(sources: &[ArchiveSource], entries: &[ArchiveEntry], redactor: Option<&Redactor>) -> Result<Vec<Run>> {
+    let mut runs = Vec::new();
+    for (index, source) in sources.iter().enumerate() {
+        if source.kind != ArchiveSourceKind::Run {
+            continue;
+        }
+        if !entries.iter().any(|entry| entry.source == Some(index)) {
+            // Dropped run
+            continue;
+        }
+        let files = entries
+            .iter()
+            .filter(|entry| entry.source == Some(index) && matches!(entry.kind, ArchiveEntryKind::File))
+            .filter_map(|entry| Some((archive_entry_relative_name(&entry.name, &source.name)?, entry)))
+            .collect::<Vec<_>>();
+
+        let mut read = |file: &str| -> Result<String> {
+            let (_, entry) = files
+                .iter()
+                .find(|(name, _)| name == file)
+                .with_context(|| format!("File '{file}' not found in run"))?;
+            let mut f = File::open(&entry.path)
+                .with_context(|| format!("Cannot open file '{}'", entry.path.to_string_lossy()))?;
+            let mut content = Vec::new();
+            match entry.keep {
+                Some(keep) => {
+                    f.seek(SeekFrom::Start(entry.size - keep))
+                        .with_context(|| format!("Cannot seek in file '{}'", entry.path.to_string_lossy()))?;
+                    f.take(keep).read_to_end(&mut content)
+                }
+                None => f.read_to_end(&mut content),
+            }
+            .with_context(|| format!("Cannot read file '{}'", entry.path.to_string_lossy()))?;
+            Ok(match redactor {
+                Some(redactor) => String::from_utf8_lossy(&redactor.redact(&content).0).to_string(),
+                None => String::from_utf8_lossy(&content).to_string(),
+            })
+        };
+        let modified = |file: &str| {
+            let (_, entry) = files.iter().find(|(name, _)| name == file)?;
+            Some(crate::report::local_time(entry.path.metadata().ok()?.modified().ok()?))
+        };
+        let path = archive_entry_relative_name(&source.name, Path::new("")).unwrap_or_default();
+        let running = source.name.starts_with(ARCHIVE_CURRENT_DIR_NAME) && run_dir_in_use(&source.path);
+        let names = files.iter().map(|(name, _)| name.clone()).collect();
+        if let Some(run) = crate::report::archive_run(&path, names, running, &mut read, &modified)? {
+            runs.push(run);
+        }
+    }
+    Ok(runs)
+}
+
+/// Returns the name of an entry relative to `base` with `/` as separator.
+fn archive_entry_relative_name(name: &Path, base: &Path) -> Option<String> {
+    Some(
+        name.strip_prefix(base)
+            .ok()?
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+    )
+}
+
+/// Writes the archive and returns the source directories that were dropped to fit the size limit.
+fn write_archive(
+    sources: &[ArchiveSource],
+    archive_file_path: &Path,
+    options: &mut ArchiveOptions,
+) -> Result<Vec<PathBuf>> {
+    let mut manifest = Manifest::new();
+    let mut entries = collect_archive_entries(sources, &mut manifest)?;
+    let redactor = ARCHIVE_REDACTION.read().unwrap().compile()?;
+    let dropped_dirs = match options.size_limit {
+        Some(ArchiveSizeLimit {
+            max_size,
+            strategy: ArchiveSizeStrategy::Truncate,
+        }) => truncate_archive_entries(sources, &mut entries, max_size, &mut manifest, redactor.as_ref())?
+            .into_iter()
+            .map(|run| sources[run].path.clone())
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    let mut progress = ArchiveProgress::default();
+    for entry in entries.iter() {
+        if let ArchiveEntryKind::File = entry.kind {
+            progress.files_total += 1;
+            progress.bytes_total += entry.keep.unwrap_or(entry.size);
+        }
+    }
+    options.report_progress(&progress);
+
+    let archive_file = File::create(archive_file_path)
+        .with_context(|| format!("Cannot create archive '{}'", archive_file_path.to_string_lossy()))?;
+
+    let passphrase = match options.encryption.as_ref() {
+        Some(ArchiveEncryption::Passphrase(passphrase)) => Some(passphrase.clone()),
+        _ => None,
+    };
+    let mut zip = ZipWriter::new(archive_file);
+    let zip_options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Bzip2);
+    let zip_file_options = match passphrase.as_ref() {
+        Some(passphrase) => zip_options.with_aes_encryption(zip::AesMode::Aes256, passphrase),
+        None => zip_options,
+    };
+
+    for entry in entries.iter() {
+        options.check_cancelled()?;
+        let path = entry.path.as_path();
+        let name = entry.name.as_path();
+
+        // Write file or directory explicitly
+        // Some unzip tools unzip files with directory paths correctly, some do not!
+        match entry.kind {
+            ArchiveEntryKind::File => {
                 log::trace!("adding file {path:?} as {name:?} ...");
+                // Keep the modification time, the summary and the report reader derive the end of runs from it
+                let modified = path
+                    .metadata()
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+                    .and_then(|time| zip::DateTime::try_from(crate::report::local_time(time)).ok());
+                let file_options = match modified {
+                    Some(modified) => zip_file_options.last_modified_time(modified),
+                    None => zip_file_options,
+                };
                 #[allow(deprecated)]
-                zip.start_file_from_path(name, options)
+                zip.start_file_from_path(name, file_options)
                     .with_context(|| format!("Cannot add file '{}' to archive", name.to_string_lossy()))?;
                 let mut f = File::open(path).with_context(|| {
                     format!(
@@ -245,35 +1080,50 @@ fn create_archive(src_dirs: &[PathBuf], archive_file_path: &Path) -> Result<()>
                         path.to_string_lossy()
                     )
                 })?;
-
-                f.read_to_end(&mut buffer).with_context(|| {
-                    format!(
-                        "Cannot read from file '{}' to add it to the archive.",
-                        path.to_string_lossy()
-                    )
-                })?;
-                zip.write_all(&buffer).with_context(|| {
-                    format!("Cannot write file buffer '{}' to the archive.", path.to_string_lossy())
-                })?;
-                buffer.clear();
-            } else if path.is_dir() && !name.as_os_str().is_empty() {
-                // Only if not root! Avoids path spec / warning
-                // and mapname conversion failed error on unzip
+                let count = match entry.keep {
+                    Some(keep) => {
+                        f.seek(SeekFrom::Start(entry.size - keep))
+                            .with_context(|| format!("Cannot seek in file '{}'", path.to_string_lossy()))?;
+                        write_archive_file(f.take(keep), path, &mut zip, redactor.as_ref(), &mut progress, options)?
+                    }
+                    None => write_archive_file(f, path, &mut zip, redactor.as_ref(), &mut progress, options)?,
+                };
+                if count > 0 {
+                    manifest.redacted.push(RedactedEntry {
+                        name: name.to_string_lossy().to_string(),
+                        count,
+                    });
+                }
+                progress.files_done += 1;
+                options.report_progress(&progress);
+            }
+            ArchiveEntryKind::Directory => {
                 log::trace!("adding dir {path:?} as {name:?} ...");
-                zip.add_directory(name.to_string_lossy(), options)
+                zip.add_directory(name.to_string_lossy(), zip_options)
                     .with_context(|| format!("Cannot add directory '{}' to the archive", name.to_string_lossy()))?;
             }
         }
     }
 
+    options.check_cancelled()?;
+    let runs = archive_runs(sources, &entries, redactor.as_ref())?;
+    zip.start_file(SUMMARY_FILE_NAME, zip_file_options)
+        .with_context(|| format!("Cannot add file '{SUMMARY_FILE_NAME}' to archive"))?;
+    zip.write_all(crate::summary::render(&runs, &manifest).as_bytes())
+        .with_context(|| format!("Cannot write file '{SUMMARY_FILE_NAME}' to the archive"))?;
+
+    zip.start_file(MANIFEST_FILE_NAME, zip_file_options)
+        .with_context(|| format!("Cannot add file '{MANIFEST_FILE_NAME}' to archive"))?;
+    serde_json::to_writer_pretty(&mut zip, &manifest)
+        .with_context(|| format!("Cannot write file '{MANIFEST_FILE_NAME}' to the archive"))?;
     zip.finish()
         .with_context(|| format!("Cannot finish archive '{}'", archive_file_path.to_string_lossy()))?;
 
-    Ok(())
+    Ok(dropped_dirs)
 }
 
 pub fn failed_dir_is_empty() -> Result<bool> {
-    for dir in FAILED_DIRS.read().unwrap().iter() {
+    for (_, dir) in FAILED_DIRS.read().unwrap().iter() {
         let is_empty = dir.read_dir()?.next().is_none();
         if !is_empty {
             return Ok(false);
@@ -282,11 +1132,15 @@ pub fn failed_dir_is_empty() -> Result<bool> {
     Ok(true)
 }
 
-fn dir_has_panic(path: &Path) -> Result<bool> {
+pub(crate) fn dir_has_panic(path: &Path) -> Result<bool> {
+    dir_has_file_with_extension(path, PANIC_FILE_EXTENSION)
+}
+
+fn dir_has_file_with_extension(path: &Path, extension: &str) -> Result<bool> {
     if !path.is_dir() {
         return Ok(false);
     }
-    let panic_extension = std::ffi::OsString::from(PANIC_FILE_EXTENSION);
+    let file_extension = std::ffi::OsString::from(extension);
     Ok(!std::fs::read_dir(path)?
         .filter(|entry| {
             if let Ok(entry) = entry {
@@ -295,7 +1149,7 @@ fn dir_has_panic(path: &Path) -> Result<bool> {
                     return false;
                 }
                 if let Some(extension) = path.extension() {
-                    return extension == panic_extension.as_os_str();
+                    return extension == file_extension.as_os_str();
                 }
             }
             true
@@ -305,7 +1159,7 @@ fn dir_has_panic(path: &Path) -> Result<bool> {
 }
 
 pub fn failed_dir_any_panic() -> Result<bool> {
-    for dir in FAILED_DIRS.read().unwrap().iter() {
+    for (_, dir) in FAILED_DIRS.read().unwrap().iter() {
         let is_any = std::fs::read_dir(dir)?
             .map(|entry| match entry {
                 Ok(entry) => dir_has_panic(entry.path().as_path()),
@@ -369,28 +1223,41 @@ fn rm_dirs(dirs: &[PathBuf]) -> Result<()> {
 }
 
 pub fn failed_dir_archive_and_remove(archive_file_path: &Path) -> Result<()> {
-    let mut directories = Vec::new();
-    for dir in FAILED_DIRS.read().unwrap().iter() {
-        let mut paths = std::fs::read_dir(dir)?
-            .map(|entry| Ok(entry?.path()))
-            .collect::<std::io::Result<Vec<_>>>()?;
-        directories.append(&mut paths);
+    failed_dir_archive_and_remove_with_options(archive_file_path, ArchiveOptions::default()).map(|_| ())
+}
+
+/// Archives all failed runs like [`failed_dir_archive_and_remove`] with progress reporting and cancellation.
+///
+/// The failed runs are only removed if the archive was created completely.
+/// If the creation was cancelled an [`ArchiveCancelled`] error is returned.
+/// Returns the written archive file or its volumes.
+pub fn failed_dir_archive_and_remove_with_options(
+    archive_file_path: &Path,
+    options: ArchiveOptions,
+) -> Result<Vec<PathBuf>> {
+    let mut options = options;
+    let mut sources = failed_run_sources()?;
+    if options.only_not_exported {
+        retain_not_exported(&mut sources, &crate::export_ledger::export_ledger_exported_runs()?);
     }
-    if directories.is_empty() {
+    if sources.is_empty() {
         println!("{}", fl!("no-bug-reports"));
-        return Ok(());
+        return Ok(Vec::new());
     }
-    create_archive(&directories, archive_file_path)?;
+    let mut directories = sources.iter().map(|source| source.path.clone()).collect::<Vec<_>>();
+    sources.append(&mut collected_sources());
+    let archive = create_archive(&sources, archive_file_path, &mut options)?;
+    record_export(&sources, &archive);
+    directories.retain(|dir| !archive.dropped_dirs.contains(dir));
     rm_dirs(&directories)?;
-    println!(
-        "{}",
-        fl!("bug-report-written-to", file_name = archive_file_path.to_string_lossy())
-    );
-    Ok(())
+    for file in archive.files.iter() {
+        println!("{}", fl!("bug-report-written-to", file_name = file.to_string_lossy()));
+    }
+    Ok(archive.files)
 }
 
 pub fn failed_dir_move_to_trash() -> Result<()> {
-    for dir in FAILED_DIRS.read().unwrap().iter() {
+    for (_, dir) in FAILED_DIRS.read().unwrap().iter() {
         let directories = std::fs::read_dir(dir)?
             .map(|entry| Ok(entry?.path()))
             .collect::<std::io::Result<Vec<_>>>()?;
@@ -399,32 +1266,234 @@ pub fn failed_dir_move_to_trash() -> Result<()> {
     Ok(())
 }
 
+/// Removes all failed runs permanently.
+pub fn failed_dir_remove() -> Result<()> {
+    for (_, dir) in FAILED_DIRS.read().unwrap().iter() {
+        let directories = std::fs::read_dir(dir)?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        rm_dirs(&directories)?;
+    }
+    Ok(())
+}
+
 #[cfg(feature = "create_report_dialog")]
 pub(crate) fn create_report_file_name(binary_name: &str) -> String {
     format!("{}_report.{}", binary_name, ARCHIVE_DEFAULT_FILE_EXTENSION)
 }
 
+/// Sets the filter deciding which files are added to created archives.
+///
+/// Returns an error if one of the glob patterns is invalid.
+pub fn archive_set_filter(filter: ArchiveFilter) -> Result<()> {
+    filter.compile()?;
+    *ARCHIVE_FILTER.write().unwrap() = filter;
+    Ok(())
+}
+
+/// Sets the redaction of personal data applied to text files in created archives.
+///
+/// Returns an error if one of the regular expressions is invalid.
+pub fn archive_set_redaction(config: RedactionConfig) -> Result<()> {
+    config.compile()?;
+    *ARCHIVE_REDACTION.write().unwrap() = config;
+    Ok(())
+}
+
 pub fn proc_dir_archive_set_callback(callback: ProcDirArchiveCallback) {
     PROC_DIR_ARCHIVE_CREATE_CALLBACK.set(callback).unwrap();
 }
 
 pub fn proc_dir_archive(archive_file_path: &Path) -> Result<()> {
+    proc_dir_archive_with_options(archive_file_path, ArchiveOptions::default()).map(|_| ())
+}
+
+/// Archives the current and failed runs like [`proc_dir_archive`] with progress reporting and cancellation.
+///
+/// The failed runs are only removed if the archive was created completely.
+/// If the creation was cancelled an [`ArchiveCancelled`] error is returned.
+/// Returns the written archive file or its volumes, nothing if
+/// [`only_not_exported`](ArchiveOptions::only_not_exported) is set and all runs were exported before.
+pub fn proc_dir_archive_with_options(archive_file_path: &Path, options: ArchiveOptions) -> Result<Vec<PathBuf>> {
+    let mut options = options;
     if let Some(callback) = PROC_DIR_ARCHIVE_CREATE_CALLBACK.get() {
         callback();
     }
-    let mut directories = std::fs::read_dir(default_proc_dir())?
-        .map(|entry| Ok(entry?.path()))
-        .collect::<std::io::Result<Vec<_>>>()?;
-    let mut failed_dirs = Vec::new();
-    for dir in FAILED_DIRS.read().unwrap().iter() {
-        let mut paths = std::fs::read_dir(dir)?
-            .map(|entry| Ok(entry?.path()))
-            .collect::<std::io::Result<Vec<_>>>()?;
-        failed_dirs.append(&mut paths);
+    let mut sources = current_run_sources()?;
+    let mut failed_sources = failed_run_sources()?;
+    if options.only_not_exported {
+        let exported_runs = crate::export_ledger::export_ledger_exported_runs()?;
+        retain_not_exported(&mut sources, &exported_runs);
+        retain_not_exported(&mut failed_sources, &exported_runs);
+        if sources.is_empty() && failed_sources.is_empty() {
+            return Ok(Vec::new());
+        }
+    }
+    let mut failed_dirs = failed_sources
+        .iter()
+        .map(|source| source.path.clone())
+        .collect::<Vec<_>>();
+    sources.append(&mut failed_sources);
+    sources.append(&mut collected_sources());
+    let archive = create_archive(&sources, archive_file_path, &mut options)?;
+    record_export(&sources, &archive);
+    failed_dirs.retain(|dir| !archive.dropped_dirs.contains(dir));
+    rm_dirs(&failed_dirs)?;
+    Ok(archive.files)
+}
+
+/// Sets the policy to export failed runs without user interaction, `None` disables it.
+///
+/// Must be set before [`proc_dir`] is called the first time.
+pub fn auto_export_set_policy(policy: Option<AutoExportPolicy>) {
+    *AUTO_EXPORT_POLICY.write().unwrap() = policy;
+}
+
+fn host_name() -> String {
+    #[cfg(feature = "sysinfo")]
+    let host_name = sysinfo::System::host_name();
+    #[cfg(not(feature = "sysinfo"))]
+    let host_name = None;
+    host_name
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Replaces characters that are not allowed in file names on all platforms.
+fn file_name_component(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            'A'..='Z' | 'a'..='z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+fn auto_export_file_path(policy: &AutoExportPolicy) -> PathBuf {
+    let name = format!(
+        "{}_{}_{}",
+        file_name_component(&host_name()),
+        file_name_component(&policy.app_name),
+        chrono::Local::now().format(CURRENT_DIR_FMT)
+    );
+    let extension = match policy.encryption {
+        // The archive is encrypted as a whole, it cannot be opened as zip file
+        #[cfg(feature = "encryption")]
+        Some(ArchiveEncryption::PublicKey(_)) => format!(
+            "{ARCHIVE_DEFAULT_FILE_EXTENSION}.{}",
+            crate::encryption::ENCRYPTED_ARCHIVE_FILE_EXTENSION
+        ),
+        _ => ARCHIVE_DEFAULT_FILE_EXTENSION.to_string(),
+    };
+    unique_file_path(&policy.dir, &name, &extension)
+}
+
+/// Returns `<dir>/<name>.<extension>`, with a suffix `_2`, `_3` and so on if the file exists.
+fn unique_file_path(dir: &Path, name: &str, extension: &str) -> PathBuf {
+    let mut file_path = dir.join(format!("{name}.{extension}"));
+    let mut index = 1;
+    while file_path.exists() {
+        index += 1;
+        file_path = dir.join(format!("{name}_{index}.{extension}"));
+    }
+    file_path
+}
+
+fn auto_export(policy: &AutoExportPolicy, run_dirs: &[PathBuf]) -> Result<PathBuf> {
+    let mut sources = run_dirs
+        .iter()
+        .map(|run_dir| ArchiveSource {
+            path: run_dir.clone(),
+            name: Path::new(ARCHIVE_FAILED_DIR_NAME)
+                .join(ARCHIVE_DEFAULT_FAILED_SOURCE_NAME)
+                .join(run_dir.file_name().unwrap_or_default()),
+            kind: ArchiveSourceKind::Run,
+        })
+        .collect::<Vec<_>>();
+    sources.append(&mut collected_sources());
+    let (archive_file_path, archive) = write_auto_export_archive(policy, &sources)?;
+    record_export(&sources, &archive);
+    if policy.remove_exported {
+        remove_exported_runs(run_dirs, &archive)?;
+    }
+    Ok(archive_file_path)
+}
+
+/// Writes the archive of the automatic export into the directory of the policy.
+fn write_auto_export_archive(
+    policy: &AutoExportPolicy,
+    sources: &[ArchiveSource],
+) -> Result<(PathBuf, CreatedArchive)> {
+    std::fs::create_dir_all(&policy.dir)
+        .with_context(|| format!("Cannot create directory '{}'", policy.dir.to_string_lossy()))?;
+    // Write a temporary file first, so that nobody picks up an incomplete archive from the directory
+    let archive_file_path = auto_export_file_path(policy);
+    let mut tmp_file_path = archive_file_path.as_os_str().to_owned();
+    tmp_file_path.push(".part");
+    let tmp_file_path = PathBuf::from(tmp_file_path);
+    let mut options = ArchiveOptions {
+        encryption: policy.encryption.clone(),
+        ..Default::default()
+    };
+    let archive = create_archive(sources, &tmp_file_path, &mut options).and_then(|archive| {
+        std::fs::rename(&tmp_file_path, &archive_file_path).with_context(|| {
+            format!(
+                "Cannot rename '{}' to '{}'",
+                tmp_file_path.to_string_lossy(),
+                archive_file_path.to_string_lossy()
+            )
+        })?;
+        Ok(CreatedArchive {
+            files: vec![archive_file_path.clone()],
+            ..archive
+        })
+    });
+    match archive {
+        Ok(archive) => Ok((archive_file_path, archive)),
+        Err(err) => {
+            _ = std::fs::remove_file(&tmp_file_path);
+            Err(err)
+        }
     }
-    directories.append(&mut failed_dirs.clone());
-    create_archive(&directories, archive_file_path)?;
-    rm_dirs(&failed_dirs)
+}
+
+/// Removes the run directories written to the archive, runs dropped from the archive are kept.
+fn remove_exported_runs(run_dirs: &[PathBuf], archive: &CreatedArchive) -> Result<()> {
+    let mut exported_dirs = run_dirs.to_vec();
+    exported_dirs.retain(|dir| !archive.dropped_dirs.contains(dir));
+    rm_dirs(&exported_dirs)
+}
+
+/// Exports the newly failed runs according to the policy set by [`auto_export_set_policy`].
+fn auto_export_failed_runs(run_dirs: &[PathBuf]) {
+    let Some(policy) = AUTO_EXPORT_POLICY.read().unwrap().clone() else {
+        return;
+    };
+    if run_dirs.is_empty() {
+        return;
+    }
+    // The failed runs are kept if the export fails, the program must start anyway
+    match auto_export(&policy, run_dirs) {
+        Ok(file) => log::info!(
+            "exported {} failed runs to '{}'",
+            run_dirs.len(),
+            file.to_string_lossy()
+        ),
+        Err(err) => log::warn!("Cannot export failed runs automatically: {err:?}"),
+    }
+}
+
+/// Sets the filter for the backtraces in `.panic` files, the panic details always contain the full backtrace.
+pub fn panic_set_backtrace_filter(filter: BacktraceFilter) {
+    *PANIC_BACKTRACE_FILTER.write().unwrap() = filter;
+}
+
+pub(crate) fn panic_backtrace_filter() -> BacktraceFilter {
+    PANIC_BACKTRACE_FILTER
+        .read()
+        .map(|filter| filter.clone())
+        .unwrap_or_default()
 }
 
 pub fn setup_panic() {
@@ -442,23 +1511,20 @@ pub fn setup_panic() {
                 },
             };
 
-            let dump = match info.location() {
-                Some(location) => {
-                    format!(
-                        "Thread '{thread_name}' panicked at '{cause}': {file_name}:{line}:{column}\n{backtrace:?}",
-                        file_name = location.file(),
-                        line = location.line(),
-                        column = location.column()
-                    )
-                }
-                None => format!("Thread '{thread_name}' panicked at '{cause}'\n{backtrace:?}"),
-            };
-            std::eprint!("{dump}");
-            let file_name = format!(
-                "{}.{}",
-                humantime::format_rfc3339(std::time::SystemTime::now()),
-                PANIC_FILE_EXTENSION
+            let location = info
+                .location()
+                .map(|location| format!("{}:{}:{}", location.file(), location.line(), location.column()));
+            let details = PanicDetails::new(thread_name, cause, location, &backtrace);
+
+            let filter = panic_backtrace_filter();
+            std::eprint!(
+                "{}\n{}",
+                details.format_message(),
+                details.format_backtrace_filtered(&filter)
             );
+            let dump = details.format_panic_file(&filter);
+            let time = humantime::format_rfc3339(std::time::SystemTime::now());
+            let file_name = format!("{}.{}", time, PANIC_FILE_EXTENSION);
             let panic_file = log_dir.join(file_name);
             if let Err(err) = std::fs::write(&panic_file, dump) {
                 std::eprint!(
@@ -467,6 +1533,431 @@ pub fn setup_panic() {
                     err
                 );
             }
+            let details_file = log_dir.join(format!("{time}{PANIC_DETAILS_FILE_SUFFIX}"));
+            if let Err(err) = serde_json::to_vec_pretty(&details)
+                .map_err(std::io::Error::from)
+                .and_then(|content| std::fs::write(&details_file, content))
+            {
+                std::eprint!(
+                    "Cannot write panic details into file '{}': {:?}",
+                    details_file.to_string_lossy(),
+                    err
+                );
+            }
         }
     }));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::ArchiveDecryption;
+    #[cfg(feature = "encryption")]
+    use crate::encryption::ENCRYPTED_ARCHIVE_FILE_EXTENSION;
+
+    // Incompressible content, so the archive size is close to the file sizes
+    fn random_bytes(len: usize) -> Vec<u8> {
+        let mut state: u32 = 1;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    fn write_random_file(path: &Path, len: usize) {
+        std::fs::write(path, random_bytes(len)).unwrap();
+    }
+
+    /// Returns a failed run with an exit report as source of an archive.
+    fn exit_report_source(dir: &Path, run: &str, exit_report: &str) -> ArchiveSource {
+        let run_dir = dir.join("failed").join(run);
+        std::fs::create_dir_all(&run_dir).unwrap();
+        std::fs::write(run_dir.join(REPORT_FILE_NAME), exit_report).unwrap();
+        ArchiveSource {
+            path: run_dir,
+            name: Path::new(ARCHIVE_FAILED_DIR_NAME)
+                .join(ARCHIVE_DEFAULT_FAILED_SOURCE_NAME)
+                .join(run),
+            kind: ArchiveSourceKind::Run,
+        }
+    }
+
+    #[test]
+    fn split_and_join_volumes() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("report.zip");
+        write_random_file(&archive, 2500);
+        let content = std::fs::read(&archive).unwrap();
+
+        let volumes = split_into_volumes(&archive, 1000).unwrap();
+        assert_eq!(
+            volumes,
+            (1..=3)
+                .map(|index| volume_file_path(&archive, index))
+                .collect::<Vec<_>>()
+        );
+        assert!(!archive.exists());
+        let sizes = volumes
+            .iter()
+            .map(|volume| volume.metadata().unwrap().len())
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, [1000, 1000, 500]);
+
+        let joined = dir.path().join("joined.zip");
+        archive_join_volumes(&archive, &joined).unwrap();
+        assert_eq!(std::fs::read(&joined).unwrap(), content);
+    }
+
+    #[test]
+    fn split_exact_multiple_and_small_archives() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("report.zip");
+        write_random_file(&archive, 2000);
+        assert_eq!(split_into_volumes(&archive, 1000).unwrap().len(), 2);
+        assert!(!volume_file_path(&archive, 3).exists());
+
+        let small = dir.path().join("small.zip");
+        write_random_file(&small, 1000);
+        assert_eq!(split_into_volumes(&small, 1000).unwrap(), std::slice::from_ref(&small));
+        assert!(!volume_file_path(&small, 1).exists());
+    }
+
+    #[test]
+    fn join_without_volumes() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(archive_join_volumes(&dir.path().join("missing.zip"), &dir.path().join("joined.zip")).is_err());
+    }
+
+    #[test]
+    fn truncate_to_size_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let failed = dir.path().join("failed");
+        let mut sources = Vec::new();
+        for run in ["2024-01-01_10_00_00", "2024-01-02_10_00_00"] {
+            let run_dir = failed.join(run);
+            std::fs::create_dir_all(&run_dir).unwrap();
+            write_random_file(&run_dir.join("data.bin"), 100_000);
+            // The exit report is shown in the summary, so it is stored twice in the archive
+            let exit_report = random_bytes(90_000)
+                .chunks(76)
+                .map(|line| line.iter().map(|byte| (b'a' + byte % 26) as char).collect::<String>() + "\n")
+                .collect::<String>();
+            std::fs::write(run_dir.join(REPORT_FILE_NAME), exit_report).unwrap();
+            sources.push(ArchiveSource {
+                path: run_dir,
+                name: Path::new(ARCHIVE_FAILED_DIR_NAME)
+                    .join(ARCHIVE_DEFAULT_FAILED_SOURCE_NAME)
+                    .join(run),
+                kind: ArchiveSourceKind::Run,
+            });
+        }
+
+        let max_size = 150_000;
+        let archive = dir.path().join("report.zip");
+        let mut options = ArchiveOptions {
+            size_limit: Some(ArchiveSizeLimit {
+                max_size,
+                strategy: ArchiveSizeStrategy::Truncate,
+            }),
+            ..Default::default()
+        };
+        let created = create_archive(&sources, &archive, &mut options).unwrap();
+        assert_eq!(created.files, std::slice::from_ref(&archive));
+        assert_eq!(created.dropped_dirs, [sources[0].path.clone()]);
+        let size = archive.metadata().unwrap().len();
+        assert!(size <= max_size, "archive size {size} exceeds {max_size}");
+
+        let manifest = crate::report::Reader::open(&archive).unwrap().manifest().unwrap();
+        assert!(manifest.is_partial());
+        assert_eq!(manifest.dropped_runs, ["failed/default/2024-01-01_10_00_00"]);
+        assert!(!manifest.truncated.is_empty());
+    }
+
+    #[test]
+    fn run_end_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let run_dir = dir.path().join("2024-01-01_10_00_00");
+        std::fs::create_dir_all(&run_dir).unwrap();
+        let report_file = File::create(run_dir.join(REPORT_FILE_NAME)).unwrap();
+        writeln!(&report_file, "The program run exited with error:\nfailure").unwrap();
+        let ended = chrono::NaiveDate::from_ymd_opt(2024, 1, 1)
+            .unwrap()
+            .and_hms_opt(10, 30, 2)
+            .unwrap();
+        let ended_time = ended.and_local_timezone(chrono::Local).unwrap();
+        report_file.set_modified(ended_time.into()).unwrap();
+        std::fs::write(run_dir.join(format!("ls{}", crate::misc::STDOUT_FILE_SUFFIX)), "output").unwrap();
+        let sources = [ArchiveSource {
+            path: run_dir,
+            name: Path::new(ARCHIVE_FAILED_DIR_NAME)
+                .join(ARCHIVE_DEFAULT_FAILED_SOURCE_NAME)
+                .join("2024-01-01_10_00_00"),
+            kind: ArchiveSourceKind::Run,
+        }];
+
+        let runs = archive_runs(
+            &sources,
+            &collect_archive_entries(&sources, &mut Manifest::new()).unwrap(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(runs[0].ended, Some(ended));
+        assert!(runs[0].command_outputs.is_empty());
+
+        let archive = dir.path().join("report.zip");
+        create_archive(&sources, &archive, &mut ArchiveOptions::default()).unwrap();
+        let mut reader = crate::report::Reader::open(&archive).unwrap();
+        let runs = reader.runs().unwrap();
+        assert_eq!(runs[0].ended, Some(ended));
+        assert_eq!(runs[0].command_outputs[0].stdout.as_deref(), Some("output"));
+        let summary = String::from_utf8(reader.read(SUMMARY_FILE_NAME).unwrap()).unwrap();
+        assert!(summary.contains("<tr><th>Ended</th><td>2024-01-01 10:30:02</td></tr>"));
+    }
+
+    #[test]
+    fn passphrase_encrypted_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let sources = [exit_report_source(dir.path(), "2024-01-01_10_00_00", "secret failure")];
+        let archive = dir.path().join("report.zip");
+        let mut options = ArchiveOptions {
+            encryption: Some(ArchiveEncryption::Passphrase("correct horse".to_string())),
+            ..Default::default()
+        };
+        create_archive(&sources, &archive, &mut options).unwrap();
+        // The content is encrypted, only the names of the entries are readable
+        assert!(!String::from_utf8_lossy(&std::fs::read(&archive).unwrap()).contains("secret failure"));
+
+        let mut reader = crate::report::Reader::open_with_decryption(
+            &archive,
+            Some(ArchiveDecryption::Passphrase("correct horse".to_string())),
+        )
+        .unwrap();
+        assert!(reader.verify().unwrap() > 0);
+        let runs = reader.runs().unwrap();
+        assert_eq!(runs[0].exit_report.as_deref(), Some("secret failure"));
+
+        let mut reader = crate::report::Reader::open_with_decryption(
+            &archive,
+            Some(ArchiveDecryption::Passphrase("wrong".to_string())),
+        )
+        .unwrap();
+        assert!(reader.verify().is_err());
+        assert!(reader.runs().is_err());
+        assert!(crate::report::Reader::open(&archive).unwrap().verify().is_err());
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn public_key_encrypted_archive() {
+        use age::secrecy::ExposeSecret;
+
+        let dir = tempfile::tempdir().unwrap();
+        let sources = [exit_report_source(dir.path(), "2024-01-01_10_00_00", "secret failure")];
+        let identity = age::x25519::Identity::generate();
+        let archive = dir
+            .path()
+            .join(format!("report.zip.{ENCRYPTED_ARCHIVE_FILE_EXTENSION}"));
+        let mut options = ArchiveOptions {
+            encryption: Some(ArchiveEncryption::PublicKey(identity.to_public().to_string())),
+            ..Default::default()
+        };
+        create_archive(&sources, &archive, &mut options).unwrap();
+        assert!(crate::report::Reader::open(&archive).is_err());
+
+        let secret = identity.to_string().expose_secret().to_string();
+        let mut reader =
+            crate::report::Reader::open_with_decryption(&archive, Some(ArchiveDecryption::Identity(secret.clone())))
+                .unwrap();
+        assert!(reader.verify().unwrap() > 0);
+        assert_eq!(reader.runs().unwrap()[0].exit_report.as_deref(), Some("secret failure"));
+
+        let plain = dir.path().join("plain.zip");
+        crate::encryption::decrypt_archive(&archive, &secret, &plain).unwrap();
+        let runs = crate::report::Reader::open(&plain).unwrap().runs().unwrap();
+        assert_eq!(runs[0].exit_report.as_deref(), Some("secret failure"));
+
+        let other = age::x25519::Identity::generate()
+            .to_string()
+            .expose_secret()
+            .to_string();
+        let err = crate::report::Reader::open_with_decryption(&archive, Some(ArchiveDecryption::Identity(other)))
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("Cannot decrypt archive"), "{err:#}");
+        let err = crate::report::Reader::open_with_decryption(
+            &archive,
+            Some(ArchiveDecryption::Identity("invalid".to_string())),
+        )
+        .unwrap_err();
+        assert!(err.to_string().starts_with("Invalid identity"), "{err}");
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn invalid_public_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let sources = [exit_report_source(dir.path(), "2024-01-01_10_00_00", "failure")];
+        let archive = dir.path().join("report.zip.age");
+        let mut options = ArchiveOptions {
+            encryption: Some(ArchiveEncryption::PublicKey("age1invalid".to_string())),
+            ..Default::default()
+        };
+        let err = create_archive(&sources, &archive, &mut options).err().unwrap();
+        assert!(err.to_string().starts_with("Invalid public key"), "{err}");
+        assert!(!archive.exists());
+    }
+
+    #[test]
+    fn only_not_exported_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sources = vec![
+            exit_report_source(dir.path(), "2024-01-01_10-00-00", "exported"),
+            exit_report_source(dir.path(), "2024-01-02_10-00-00", "new"),
+            ArchiveSource {
+                path: dir.path().join("collected.txt"),
+                name: PathBuf::from("collected.txt"),
+                kind: ArchiveSourceKind::Collected,
+            },
+        ];
+        let exported_runs = HashSet::from(["2024-01-01_10-00-00".to_string(), "2023-12-31_10-00-00".to_string()]);
+        retain_not_exported(&mut sources, &exported_runs);
+        // Collected files are always added
+        assert_eq!(
+            sources.iter().map(|source| source.name.clone()).collect::<Vec<_>>(),
+            [
+                Path::new(ARCHIVE_FAILED_DIR_NAME)
+                    .join(ARCHIVE_DEFAULT_FAILED_SOURCE_NAME)
+                    .join("2024-01-02_10-00-00"),
+                PathBuf::from("collected.txt"),
+            ]
+        );
+    }
+
+    fn auto_export_policy(dir: &Path) -> AutoExportPolicy {
+        AutoExportPolicy::new(dir.join("export"), "My App")
+    }
+
+    fn dir_names(dir: &Path) -> Vec<String> {
+        let mut names = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn unique_auto_export_file_path() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            unique_file_path(dir.path(), "host_app", "zip"),
+            dir.path().join("host_app.zip")
+        );
+        std::fs::write(dir.path().join("host_app.zip"), "").unwrap();
+        assert_eq!(
+            unique_file_path(dir.path(), "host_app", "zip"),
+            dir.path().join("host_app_2.zip")
+        );
+        std::fs::write(dir.path().join("host_app_2.zip"), "").unwrap();
+        assert_eq!(
+            unique_file_path(dir.path(), "host_app", "zip"),
+            dir.path().join("host_app_3.zip")
+        );
+
+        let policy = auto_export_policy(dir.path());
+        let file_name = auto_export_file_path(&policy)
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        assert!(file_name.contains("_My_App_"), "{file_name}");
+        assert!(file_name.ends_with(".zip"), "{file_name}");
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn auto_export_file_path_encrypted() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = AutoExportPolicy {
+            encryption: Some(ArchiveEncryption::PublicKey("age1key".to_string())),
+            ..auto_export_policy(dir.path())
+        };
+        let file_name = auto_export_file_path(&policy)
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        assert!(file_name.ends_with(".zip.age"), "{file_name}");
+        let policy = AutoExportPolicy {
+            encryption: Some(ArchiveEncryption::Passphrase("secret".to_string())),
+            ..policy
+        };
+        let file_name = auto_export_file_path(&policy)
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        assert!(file_name.ends_with(".zip"), "{file_name}");
+    }
+
+    #[test]
+    fn auto_export_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = auto_export_policy(dir.path());
+        let sources = [
+            exit_report_source(dir.path(), "2024-01-01_10_00_00", "first"),
+            exit_report_source(dir.path(), "2024-01-02_10_00_00", "second"),
+        ];
+        let kept_dir = dir.path().join("failed").join("2024-01-03_10_00_00");
+        std::fs::create_dir_all(&kept_dir).unwrap();
+
+        let (archive_file_path, archive) = write_auto_export_archive(&policy, &sources).unwrap();
+        assert_eq!(archive.files, std::slice::from_ref(&archive_file_path));
+        // The temporary file was renamed
+        assert_eq!(
+            dir_names(&policy.dir),
+            [archive_file_path.file_name().unwrap().to_string_lossy()]
+        );
+        let runs = crate::report::Reader::open(&archive_file_path).unwrap().runs().unwrap();
+        assert_eq!(runs.len(), 2);
+
+        let run_dirs = sources.iter().map(|source| source.path.clone()).collect::<Vec<_>>();
+        remove_exported_runs(&run_dirs, &archive).unwrap();
+        assert_eq!(dir_names(&dir.path().join("failed")), ["2024-01-03_10_00_00"]);
+    }
+
+    #[test]
+    fn auto_export_keeps_dropped_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let sources = [
+            exit_report_source(dir.path(), "2024-01-01_10_00_00", "first"),
+            exit_report_source(dir.path(), "2024-01-02_10_00_00", "second"),
+        ];
+        let run_dirs = sources.iter().map(|source| source.path.clone()).collect::<Vec<_>>();
+        let archive = CreatedArchive {
+            files: Vec::new(),
+            dropped_dirs: vec![run_dirs[1].clone()],
+        };
+        remove_exported_runs(&run_dirs, &archive).unwrap();
+        assert_eq!(dir_names(&dir.path().join("failed")), ["2024-01-02_10_00_00"]);
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn auto_export_failure_keeps_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = AutoExportPolicy {
+            encryption: Some(ArchiveEncryption::PublicKey("age1invalid".to_string())),
+            ..auto_export_policy(dir.path())
+        };
+        let run_dir = dir.path().join("failed").join("2024-01-01_10_00_00");
+        std::fs::create_dir_all(&run_dir).unwrap();
+        std::fs::write(run_dir.join(REPORT_FILE_NAME), "failure").unwrap();
+
+        assert!(auto_export(&policy, std::slice::from_ref(&run_dir)).is_err());
+        // Neither the temporary file nor an archive is left and the run is kept
+        assert!(dir_names(&policy.dir).is_empty());
+        assert!(run_dir.join(REPORT_FILE_NAME).exists());
+    }
+}
@@ -0,0 +1,369 @@
+use anyhow::{Context, Result};
+use regex::bytes::Regex;
+use std::{borrow::Cow, ops::Range};
+
+const EMAIL_PATTERN: &str = r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}";
+const MAC_PATTERN: &str = r"\b[0-9A-Fa-f]{2}(?:[:-][0-9A-Fa-f]{2}){5}\b";
+// Full, IPv4-suffixed and compressed addresses; the start of compressed addresses like `::1` cannot be anchored with
+// `\b` because it may start with a colon, so the preceding byte is checked by `is_ipv6`
+const IPV6_PATTERN: &str = concat!(
+    r"\b(?:[0-9A-Fa-f]{1,4}:){7}[0-9A-Fa-f]{1,4}\b",
+    r"|\b(?:[0-9A-Fa-f]{1,4}:){6}(?:[0-9]{1,3}\.){3}[0-9]{1,3}\b",
+    r"|(?:\b[0-9A-Fa-f]{1,4}(?::[0-9A-Fa-f]{1,4}){0,6})?::",
+    r"(?:(?:[0-9A-Fa-f]{1,4}:){0,5}(?:[0-9]{1,3}\.){3}[0-9]{1,3}\b|[0-9A-Fa-f]{1,4}(?::[0-9A-Fa-f]{1,4}){0,6}\b)?",
+);
+const IPV4_PATTERN: &str = r"\b(?:[0-9]{1,3}\.){3}[0-9]{1,3}\b";
+
+/// Host supplied rule replacing every match of a regular expression in text files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedactionRule {
+    /// Regular expression in the syntax of the `regex` crate.
+    pub pattern: String,
+    /// Replacement text, may refer to capture groups like `$1`.
+    pub replacement: String,
+}
+
+/// Configuration of the redaction of personal data in text files before they are added to an archive.
+///
+/// Files are treated as text if their first bytes do not contain a NUL byte, they are redacted line by line.
+/// The built-in rules are disabled by default, use [`RedactionConfig::builtin`] to enable all of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RedactionConfig {
+    pub home_dir: bool,
+    /// Replaces the host name only as a whole word, e.g. `myhost` but not `myhost2`, `my-myhost` or `myhost.lan`.
+    ///
+    /// Every whole word occurrence is replaced, so a host called `localhost` also redacts `localhost` in URLs.
+    pub hostname: bool,
+    /// Replaces the user name only as a whole word, like [`RedactionConfig::hostname`].
+    pub username: bool,
+    pub email: bool,
+    pub mac: bool,
+    pub ipv6: bool,
+    pub ipv4: bool,
+    pub rules: Vec<RedactionRule>,
+}
+
+impl RedactionConfig {
+    /// Returns a configuration with all built-in rules enabled.
+    pub fn builtin() -> Self {
+        Self {
+            home_dir: true,
+            hostname: true,
+            username: true,
+            email: true,
+            mac: true,
+            ipv6: true,
+            ipv4: true,
+            rules: Vec::new(),
+        }
+    }
+
+    /// Returns `None` if no rule is enabled.
+    pub(crate) fn compile(&self) -> Result<Option<Redactor>> {
+        let mut rules = Vec::new();
+        // Order matters: The home directory contains the user name and e-mail addresses may contain the host name
+        if self.home_dir {
+            rules.extend(CompiledRule::path(home_dir(), "<home>")?);
+        }
+        if self.email {
+            rules.push(CompiledRule::new(EMAIL_PATTERN, "<email>", None)?);
+        }
+        if self.hostname {
+            rules.extend(CompiledRule::literal(hostname(), "<hostname>", true)?);
+        }
+        if self.username {
+            rules.extend(CompiledRule::literal(username(), "<username>", true)?);
+        }
+        if self.mac {
+            rules.push(CompiledRule::new(MAC_PATTERN, "<mac>", None)?);
+        }
+        if self.ipv6 {
+            rules.push(CompiledRule::new(IPV6_PATTERN, "<ipv6>", Some(is_ipv6))?);
+        }
+        if self.ipv4 {
+            rules.push(CompiledRule::new(IPV4_PATTERN, "<ipv4>", Some(is_ipv4))?);
+        }
+        for rule in self.rules.iter() {
+            rules.push(CompiledRule::new(&rule.pattern, &rule.replacement, None)?);
+        }
+
+        if rules.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Redactor { rules }))
+    }
+}
+
+/// Checks a match in the context of the whole line, a rejected match is kept.
+type Validate = fn(&[u8], Range<usize>) -> bool;
+
+struct CompiledRule {
+    regex: Regex,
+    replacement: Vec<u8>,
+    validate: Option<Validate>,
+}
+
+impl CompiledRule {
+    fn new(pattern: &str, replacement: &str, validate: Option<Validate>) -> Result<Self> {
+        Ok(Self {
+            regex: Regex::new(pattern).with_context(|| format!("Invalid redaction pattern '{pattern}'"))?,
+            replacement: replacement.as_bytes().to_vec(),
+            validate,
+        })
+    }
+
+    /// Returns a rule replacing a literal text, `None` if the text is unknown or empty.
+    ///
+    /// With `whole_word` the text is only replaced if it is not part of a longer name, see [`is_whole_word`].
+    fn literal(literal: Option<String>, replacement: &str, whole_word: bool) -> Result<Option<Self>> {
+        match literal.filter(|literal| !literal.is_empty()) {
+            Some(literal) => {
+                let validate = if whole_word { Some(is_whole_word as _) } else { None };
+                Ok(Some(Self::new(&regex::escape(&literal), replacement, validate)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Returns a rule replacing a literal path and its subpaths, e.g. `/home/bob` but not `/home/bobby`.
+    fn path(path: Option<String>, replacement: &str) -> Result<Option<Self>> {
+        match path.filter(|path| !path.is_empty()) {
+            Some(path) => {
+                // The regex crate has no look-ahead, so the separator or end of the token is matched and kept
+                let pattern = format!(r"{}(?P<end>[^\w.-]|$)", regex::escape(&path));
+                Ok(Some(Self::new(&pattern, &format!("{replacement}${{end}}"), None)?))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+pub(crate) struct Redactor {
+    rules: Vec<CompiledRule>,
+}
+
+impl Redactor {
+    /// Applies all rules to a line and returns the redacted line and the number of replacements.
+    pub(crate) fn redact<'a>(&self, line: &'a [u8]) -> (Cow<'a, [u8]>, usize) {
+        let mut count = 0;
+        let mut result = Cow::Borrowed(line);
+        for rule in self.rules.iter() {
+            let redacted = rule.regex.replace_all(&result, |captures: &regex::bytes::Captures| {
+                let matched = captures.get(0).unwrap();
+                if rule
+                    .validate
+                    .is_some_and(|validate| !validate(&result, matched.range()))
+                {
+                    return matched.as_bytes().to_vec();
+                }
+                count += 1;
+                let mut replacement = Vec::new();
+                captures.expand(&rule.replacement, &mut replacement);
+                replacement
+            });
+            if let Cow::Owned(redacted) = redacted {
+                result = Cow::Owned(redacted);
+            }
+        }
+        (result, count)
+    }
+}
+
+fn is_ipv4(line: &[u8], range: Range<usize>) -> bool {
+    std::str::from_utf8(&line[range]).is_ok_and(|text| text.parse::<std::net::Ipv4Addr>().is_ok())
+}
+
+fn is_ipv6(line: &[u8], range: Range<usize>) -> bool {
+    // A compressed address must not continue a word or another address, e.g. `x::1` or `1:::1`
+    if range.start > 0 && matches!(line[range.start - 1], b'0'..=b'9' | b'A'..=b'Z' | b'a'..=b'z' | b'_' | b':') {
+        return false;
+    }
+    let text = &line[range];
+    // Avoid redacting Rust paths like `a::b` in backtraces, real addresses nearly always contain a digit
+    text.iter().any(|c| c.is_ascii_digit())
+        && std::str::from_utf8(text).is_ok_and(|text| text.parse::<std::net::Ipv6Addr>().is_ok())
+}
+
+/// Returns `true` if the match is not part of a longer host or user name.
+///
+/// Letters, digits, `_`, `-` and non-ASCII bytes continue a name, a `.` only if it is followed or preceded by one of
+/// them, so the name at the end of a sentence is still replaced.
+fn is_whole_word(line: &[u8], range: Range<usize>) -> bool {
+    fn is_name_byte(byte: u8) -> bool {
+        byte.is_ascii_alphanumeric() || matches!(byte, b'_' | b'-') || !byte.is_ascii()
+    }
+    let before = range.start.checked_sub(1).map(|index| line[index]);
+    let before_dot = range.start.checked_sub(2).map(|index| line[index]);
+    let after = line.get(range.end).copied();
+    let after_dot = line.get(range.end + 1).copied();
+    let continues_before = match before {
+        Some(b'.') => before_dot.is_some_and(is_name_byte),
+        before => before.is_some_and(is_name_byte),
+    };
+    let continues_after = match after {
+        Some(b'.') => after_dot.is_some_and(is_name_byte),
+        after => after.is_some_and(is_name_byte),
+    };
+    !continues_before && !continues_after
+}
+
+fn home_dir() -> Option<String> {
+    directories::BaseDirs::new().map(|dirs| dirs.home_dir().to_string_lossy().to_string())
+}
+
+fn hostname() -> Option<String> {
+    #[cfg(feature = "sysinfo")]
+    {
+        sysinfo::System::host_name()
+    }
+    #[cfg(not(feature = "sysinfo"))]
+    {
+        std::env::var("HOSTNAME")
+            .or_else(|_| std::env::var("COMPUTERNAME"))
+            .ok()
+    }
+}
+
+fn username() -> Option<String> {
+    std::env::var("USER").or_else(|_| std::env::var("USERNAME")).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redact(redactor: &Redactor, line: &str) -> (String, usize) {
+        let (redacted, count) = redactor.redact(line.as_bytes());
+        (String::from_utf8(redacted.into_owned()).unwrap(), count)
+    }
+
+    fn redactor(rules: Vec<CompiledRule>) -> Redactor {
+        Redactor { rules }
+    }
+
+    #[test]
+    fn home_dir_boundary() {
+        let redactor = redactor(vec![CompiledRule::path(Some("/home/bob".into()), "<home>")
+            .unwrap()
+            .unwrap()]);
+        assert_eq!(
+            redact(&redactor, "open /home/bob/.config/app.toml"),
+            ("open <home>/.config/app.toml".into(), 1)
+        );
+        assert_eq!(redact(&redactor, "cwd=/home/bob"), ("cwd=<home>".into(), 1));
+        assert_eq!(redact(&redactor, "'/home/bob' missing"), ("'<home>' missing".into(), 1));
+        assert_eq!(
+            redact(&redactor, "open /home/bobby/file"),
+            ("open /home/bobby/file".into(), 0)
+        );
+        assert_eq!(
+            redact(&redactor, "open /home/bob.old/file"),
+            ("open /home/bob.old/file".into(), 0)
+        );
+    }
+
+    #[test]
+    fn whole_word_literal() {
+        let redactor = redactor(vec![CompiledRule::literal(Some("bob".into()), "<username>", true)
+            .unwrap()
+            .unwrap()]);
+        assert_eq!(
+            redact(&redactor, "user bob logged in"),
+            ("user <username> logged in".into(), 1)
+        );
+        assert_eq!(
+            redact(&redactor, "user bobby logged in"),
+            ("user bobby logged in".into(), 0)
+        );
+        assert_eq!(
+            redact(&redactor, "bob:bob, bob."),
+            ("<username>:<username>, <username>.".into(), 3)
+        );
+        assert_eq!(
+            redact(&redactor, "jimbob bob-1 bob_2 bob.lan www.bob"),
+            ("jimbob bob-1 bob_2 bob.lan www.bob".into(), 0)
+        );
+
+        let redactor = Redactor {
+            rules: vec![CompiledRule::literal(Some("localhost".into()), "<hostname>", true)
+                .unwrap()
+                .unwrap()],
+        };
+        assert_eq!(
+            redact(&redactor, "http://localhost:8080 localhost6 localhost.localdomain"),
+            ("http://<hostname>:8080 localhost6 localhost.localdomain".into(), 1)
+        );
+        assert!(CompiledRule::literal(Some(String::new()), "<username>", true)
+            .unwrap()
+            .is_none());
+        assert!(CompiledRule::literal(None, "<username>", true).unwrap().is_none());
+    }
+
+    #[test]
+    fn builtin_patterns() {
+        let config = RedactionConfig {
+            email: true,
+            mac: true,
+            ipv6: true,
+            ipv4: true,
+            ..Default::default()
+        };
+        let redactor = config.compile().unwrap().unwrap();
+        assert_eq!(
+            redact(&redactor, "mail a.b@example.com from 192.168.1.2"),
+            ("mail <email> from <ipv4>".into(), 2)
+        );
+        assert_eq!(redact(&redactor, "mac 00:1a:2B:3c:4d:5e"), ("mac <mac>".into(), 1));
+        assert_eq!(
+            redact(&redactor, "v6 fe80::1 and 2001:db8::2:1"),
+            ("v6 <ipv6> and <ipv6>".into(), 2)
+        );
+        assert_eq!(
+            redact(&redactor, "bind ::1 and [::ffff:10.0.0.1]:80"),
+            ("bind <ipv6> and [<ipv6>]:80".into(), 2)
+        );
+        assert_eq!(redact(&redactor, "peer ::"), ("peer ::".into(), 0));
+        assert_eq!(
+            redact(&redactor, "full 2001:db8:0:0:0:0:0:1 mapped 0:0:0:0:0:ffff:1.2.3.4"),
+            ("full <ipv6> mapped <ipv6>".into(), 2)
+        );
+        assert_eq!(redact(&redactor, "key x1::2"), ("key x1::2".into(), 0));
+        // Invalid addresses, Rust paths and versions are kept
+        assert_eq!(redact(&redactor, "ip 999.1.1.1"), ("ip 999.1.1.1".into(), 0));
+        assert_eq!(
+            redact(&redactor, "at std::panicking::begin_panic"),
+            ("at std::panicking::begin_panic".into(), 0)
+        );
+        assert_eq!(redact(&redactor, "time 12:30:45"), ("time 12:30:45".into(), 0));
+    }
+
+    #[test]
+    fn custom_rules() {
+        let config = RedactionConfig {
+            rules: vec![RedactionRule {
+                pattern: r"secret=(\w)\w*".into(),
+                replacement: "secret=$1***".into(),
+            }],
+            ..Default::default()
+        };
+        let redactor = config.compile().unwrap().unwrap();
+        assert_eq!(
+            redact(&redactor, "secret=abc secret=xyz"),
+            ("secret=a*** secret=x***".into(), 2)
+        );
+
+        let config = RedactionConfig {
+            rules: vec![RedactionRule {
+                pattern: "(".into(),
+                replacement: String::new(),
+            }],
+            ..Default::default()
+        };
+        assert!(config.compile().is_err());
+    }
+
+    #[test]
+    fn no_rules() {
+        assert!(RedactionConfig::default().compile().unwrap().is_none());
+    }
+}
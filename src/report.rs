@@ -0,0 +1,708 @@
+use crate::{
+    build_info::{BuildInfo, BUILD_INFO_FILE_NAME, BUILD_SECTION_HEADER},
+    encryption::ArchiveDecryption,
+    manifest::{
+        Manifest, ARCHIVE_CURRENT_DIR_NAME, ARCHIVE_DEFAULT_FAILED_SOURCE_NAME, ARCHIVE_FAILED_DIR_NAME,
+        ARCHIVE_SCHEMA_VERSION, MANIFEST_FILE_NAME,
+    },
+    misc::{STDERR_FILE_SUFFIX, STDOUT_FILE_SUFFIX, SYSINFO_FILE_NAME},
+    proc_dir::{
+        DUMP_FILE_EXTENSION, HANG_FILE_EXTENSION, LOCK_FILE_NAME, PANIC_FILE_EXTENSION, PROC_DIR_NAME,
+        PROC_FAILED_DIR_NAME, REPORT_FILE_NAME,
+    },
+};
+use anyhow::{Context, Result};
+use chrono::NaiveDateTime;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{Read, Seek},
+    path::Path,
+    time::SystemTime,
+};
+use zip::ZipArchive;
+
+/// Where a run was stored when the archive was created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunOrigin {
+    /// The process directory with the run that created the archive.
+    Current,
+    /// A failed directory with the given source name.
+    Failed { source: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunOutcome {
+    /// The run was still running when the archive was created.
+    Running,
+    /// The run panicked.
+    Panicked,
+    /// The run did not respond, a hang record was written by [`crate::hang_watchdog`].
+    Hung,
+    /// The run exited with an error.
+    Error,
+    /// The run was aborted unexpectedly, e.g. by a SIGKILL or a crash.
+    Aborted,
+    /// The run exited normally, but was kept because of a diagnostic dump of [`crate::diagnostic_dump`].
+    Kept,
+    /// The run failed for an unknown reason.
+    Failed,
+}
+
+/// Panic written by the panic hook of [`crate::proc_dir::setup_panic`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanicRecord {
+    /// Name of the entry in the archive.
+    pub file_name: String,
+    /// Time of the panic in RFC 3339 format.
+    pub time: String,
+    pub thread: Option<String>,
+    pub message: String,
+    /// Source location as `file:line:column`.
+    pub location: Option<String>,
+    pub backtrace: String,
+}
+
+/// Output of a command executed by [`crate::misc::exec_cmd_and_dump_pipes`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandOutput {
+    pub program: String,
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Run {
+    /// Name of the run directory, which starts with the start time of the run.
+    pub name: String,
+    /// Directory of the run in the archive.
+    pub path: String,
+    pub origin: RunOrigin,
+    pub outcome: RunOutcome,
+    pub panics: Vec<PanicRecord>,
+    pub exit_report: Option<String>,
+    /// End time of the run in local time, taken from the exit report or the last modified file of the run.
+    ///
+    /// `None` for running runs and runs without modification times.
+    pub ended: Option<NaiveDateTime>,
+    pub sysinfo: Option<String>,
+    /// Build of the application that produced the run, not present in runs of applications without build information.
+    pub build: Option<BuildInfo>,
+    /// Outputs of the executed commands, not read for the summary of a created archive.
+    pub command_outputs: Vec<CommandOutput>,
+    /// Names of all files of the run relative to the run directory.
+    pub files: Vec<String>,
+}
+
+static PANIC_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?s)^Thread '(?P<thread>[^']*)' panicked at '(?P<message>.*?)'(?:: (?P<location>[^\n]*:\d+:\d+))?(?:\n(?P<backtrace>.*))?$")
+        .expect("Valid panic regex")
+});
+
+fn parse_panic(file_name: &str, content: &str) -> PanicRecord {
+    let time = Path::new(file_name)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match PANIC_REGEX.captures(content) {
+        Some(captures) => PanicRecord {
+            file_name: file_name.to_string(),
+            time,
+            thread: captures.name("thread").map(|m| m.as_str().to_string()),
+            message: captures["message"].to_string(),
+            location: captures.name("location").map(|m| m.as_str().to_string()),
+            backtrace: captures
+                .name("backtrace")
+                .map(|m| {
+                    // The build section is not part of the backtrace
+                    let backtrace = m.as_str();
+                    match backtrace.find(&format!("\n{BUILD_SECTION_HEADER}\n")) {
+                        Some(end) => backtrace[..end].to_string(),
+                        None => backtrace.to_string(),
+                    }
+                })
+                .unwrap_or_default(),
+        },
+        None => PanicRecord {
+            file_name: file_name.to_string(),
+            time,
+            thread: None,
+            message: content.lines().next().unwrap_or_default().to_string(),
+            location: None,
+            backtrace: content.to_string(),
+        },
+    }
+}
+
+/// Splits an entry name into the run directory, its origin and the name relative to the run directory.
+fn split_run_entry_name(name: &str, schema_version: u32) -> Option<(String, RunOrigin, String)> {
+    let components = name.split('/').collect::<Vec<_>>();
+    let (run_len, origin) = if schema_version == 0 {
+        // Older archives contain the runs below the last three directories of the absolute path,
+        // e.g. `<data dir>/proc/<run>` or `<data dir>/proc_failed/<run>`
+        let origin = match *components.get(1)? {
+            PROC_DIR_NAME => RunOrigin::Current,
+            PROC_FAILED_DIR_NAME => RunOrigin::Failed {
+                source: ARCHIVE_DEFAULT_FAILED_SOURCE_NAME.to_string(),
+            },
+            source => RunOrigin::Failed {
+                source: source.to_string(),
+            },
+        };
+        (3, origin)
+    } else {
+        match components[0] {
+            ARCHIVE_CURRENT_DIR_NAME => (2, RunOrigin::Current),
+            ARCHIVE_FAILED_DIR_NAME => (
+                3,
+                RunOrigin::Failed {
+                    source: components.get(1)?.to_string(),
+                },
+            ),
+            _ => return None,
+        }
+    };
+    if components.len() < run_len || components[run_len - 1].is_empty() {
+        return None;
+    }
+    Some((components[..run_len].join("/"), origin, components[run_len..].join("/")))
+}
+
+trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+/// Reader for archives created by this crate.
+pub struct Reader {
+    archive: ZipArchive<Box<dyn ReadSeek>>,
+    passphrase: Option<String>,
+}
+
+impl std::fmt::Debug for Reader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Reader").field("len", &self.archive.len()).finish()
+    }
+}
+
+#[cfg(feature = "encryption")]
+pub(crate) fn open_encrypted(path: &Path, identity: &str) -> Result<impl Read + Seek + Send> {
+    let identity = crate::encryption::parse_identity(identity)?;
+    let file = File::open(path).with_context(|| format!("Cannot open archive '{}'", path.to_string_lossy()))?;
+    let decryptor = age::Decryptor::new(file)
+        .with_context(|| format!("Cannot read encrypted archive '{}'", path.to_string_lossy()))?;
+    decryptor
+        .decrypt(std::iter::once(&identity as &dyn age::Identity))
+        .with_context(|| format!("Cannot decrypt archive '{}'", path.to_string_lossy()))
+}
+
+/// Builds a run from the names of its files relative to the run directory.
+///
+/// If `running` is `None` a run in the process directory with a lock file is treated as running.
+/// The outputs of the executed commands are only read if `read_command_outputs` is set.
+fn build_run(
+    path: String,
+    origin: RunOrigin,
+    mut files: Vec<String>,
+    running: Option<bool>,
+    read_command_outputs: bool,
+    read: &mut dyn FnMut(&str) -> Result<String>,
+    modified: &dyn Fn(&str) -> Option<NaiveDateTime>,
+) -> Result<Run> {
+    files.sort();
+    let has_file = |file_name: &str| files.iter().any(|file| file == file_name);
+    let has_root_file_with_extension = |extension: &str| {
+        files
+            .iter()
+            .any(|file| !file.contains('/') && Path::new(file).extension().is_some_and(|ext| ext == extension))
+    };
+
+    let mut panics = Vec::new();
+    for file in files.iter().filter(|file| {
+        !file.contains('/')
+            && Path::new(file)
+                .extension()
+                .is_some_and(|ext| ext == PANIC_FILE_EXTENSION)
+    }) {
+        let content = read(file)?;
+        panics.push(parse_panic(&format!("{path}/{file}"), &content));
+    }
+    let exit_report = match has_file(REPORT_FILE_NAME) {
+        true => Some(read(REPORT_FILE_NAME)?),
+        false => None,
+    };
+    let sysinfo = match has_file(SYSINFO_FILE_NAME) {
+        true => Some(read(SYSINFO_FILE_NAME)?),
+        false => None,
+    };
+    let build = match has_file(BUILD_INFO_FILE_NAME) {
+        // An invalid file does not prevent reading the rest of the run
+        true => serde_json::from_str(&read(BUILD_INFO_FILE_NAME)?).ok(),
+        false => None,
+    };
+
+    let mut command_outputs = BTreeMap::<String, CommandOutput>::new();
+    for file in files.iter().filter(|file| read_command_outputs && !file.contains('/')) {
+        if let Some(program) = file.strip_suffix(STDOUT_FILE_SUFFIX) {
+            command_outputs.entry(program.to_string()).or_default().stdout = Some(read(file)?);
+        } else if let Some(program) = file.strip_suffix(STDERR_FILE_SUFFIX) {
+            command_outputs.entry(program.to_string()).or_default().stderr = Some(read(file)?);
+        }
+    }
+    let command_outputs = command_outputs
+        .into_iter()
+        .map(|(program, output)| CommandOutput { program, ..output })
+        .collect();
+
+    let running = running.unwrap_or_else(|| origin == RunOrigin::Current && has_file(LOCK_FILE_NAME));
+    let outcome = if running {
+        RunOutcome::Running
+    } else if !panics.is_empty() {
+        RunOutcome::Panicked
+    } else if has_root_file_with_extension(HANG_FILE_EXTENSION) {
+        RunOutcome::Hung
+    } else if let Some(exit_report) = exit_report.as_ref() {
+        if exit_report.contains(crate::proc_dir::REPORT_ABORTED_UNEXPECTED) {
+            RunOutcome::Aborted
+        } else {
+            RunOutcome::Error
+        }
+    } else if has_file(LOCK_FILE_NAME) {
+        // Lock file of a run that was aborted without an exit report yet
+        RunOutcome::Aborted
+    } else if has_root_file_with_extension(DUMP_FILE_EXTENSION) {
+        RunOutcome::Kept
+    } else {
+        RunOutcome::Failed
+    };
+    let ended = match outcome {
+        RunOutcome::Running => None,
+        // The exit report of an aborted run is written by the next process
+        RunOutcome::Error => modified(REPORT_FILE_NAME),
+        _ => files
+            .iter()
+            .filter(|file| *file != REPORT_FILE_NAME)
+            .filter_map(|file| modified(file))
+            .max(),
+    };
+
+    Ok(Run {
+        name: path.rsplit('/').next().unwrap_or_default().to_string(),
+        path,
+        origin,
+        outcome,
+        panics,
+        exit_report,
+        ended,
+        sysinfo,
+        build,
+        command_outputs,
+        files,
+    })
+}
+
+/// Builds a run of an archive that is created from the files of the run, as they are written to the archive.
+///
+/// The outputs of the executed commands are not read. Returns `None` if `path` is not the directory of a run
+/// in the archive.
+pub(crate) fn archive_run(
+    path: &str,
+    files: Vec<String>,
+    running: bool,
+    read: &mut dyn FnMut(&str) -> Result<String>,
+    modified: &dyn Fn(&str) -> Option<NaiveDateTime>,
+) -> Result<Option<Run>> {
+    match split_run_entry_name(path, ARCHIVE_SCHEMA_VERSION) {
+        Some((path, origin, _)) => build_run(path, origin, files, Some(running), false, read, modified).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Converts a modification time of a file to local time, as used in the names of the run directories.
+pub(crate) fn local_time(time: SystemTime) -> NaiveDateTime {
+    chrono::DateTime::<chrono::Local>::from(time).naive_local()
+}
+
+fn read_local_run(run_dir: &Path, path: String, origin: RunOrigin) -> Result<Run> {
+    let files = walkdir::WalkDir::new(run_dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            let relative_path = entry.path().strip_prefix(run_dir).ok()?;
+            Some(
+                relative_path
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/"),
+            )
+        })
+        .collect();
+    let running = origin == RunOrigin::Current && crate::proc_dir::run_dir_in_use(run_dir);
+    let mut read = |file: &str| -> Result<String> {
+        let file_path = run_dir.join(file);
+        let content =
+            std::fs::read(&file_path).with_context(|| format!("Cannot read file '{}'", file_path.to_string_lossy()))?;
+        Ok(String::from_utf8_lossy(&content).to_string())
+    };
+    let modified = |file: &str| {
+        let metadata = run_dir.join(file).metadata().ok()?;
+        Some(local_time(metadata.modified().ok()?))
+    };
+    build_run(path, origin, files, Some(running), true, &mut read, &modified)
+}
+
+/// Reads the runs of the process directory and of all failed directories.
+///
+/// The runs are named like in the layout of created archives, e.g. `current/<run>` or `failed/default/<run>`.
+pub fn local_runs() -> Result<Vec<Run>> {
+    let mut dirs = vec![(
+        crate::proc_dir::default_proc_dir().clone(),
+        ARCHIVE_CURRENT_DIR_NAME.to_string(),
+        RunOrigin::Current,
+    )];
+    for (source, dir) in crate::proc_dir::failed_dirs() {
+        dirs.push((
+            dir,
+            format!("{ARCHIVE_FAILED_DIR_NAME}/{source}"),
+            RunOrigin::Failed { source },
+        ));
+    }
+
+    let mut runs = Vec::new();
+    for (dir, prefix, origin) in dirs {
+        if !dir.is_dir() {
+            continue;
+        }
+        let mut run_dirs = std::fs::read_dir(&dir)
+            .with_context(|| format!("Cannot list directories in '{}'", dir.to_string_lossy()))?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        run_dirs.retain(|run_dir| run_dir.is_dir());
+        run_dirs.sort();
+        for run_dir in run_dirs {
+            let name = run_dir.file_name().unwrap_or_default().to_string_lossy().to_string();
+            runs.push(read_local_run(&run_dir, format!("{prefix}/{name}"), origin.clone())?);
+        }
+    }
+    Ok(runs)
+}
+
+impl Reader {
+    /// Opens an unencrypted archive.
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::open_with_decryption(path, None)
+    }
+
+    /// Opens an archive that may be encrypted with the given secret.
+    pub fn open_with_decryption(path: &Path, decryption: Option<ArchiveDecryption>) -> Result<Self> {
+        let (source, passphrase): (Box<dyn ReadSeek>, _) = match decryption {
+            None => (
+                Box::new(
+                    File::open(path).with_context(|| format!("Cannot open archive '{}'", path.to_string_lossy()))?,
+                ),
+                None,
+            ),
+            Some(ArchiveDecryption::Passphrase(passphrase)) => (
+                Box::new(
+                    File::open(path).with_context(|| format!("Cannot open archive '{}'", path.to_string_lossy()))?,
+                ),
+                Some(passphrase),
+            ),
+            #[cfg(feature = "encryption")]
+            Some(ArchiveDecryption::Identity(identity)) => (Box::new(open_encrypted(path, &identity)?), None),
+        };
+        let archive =
+            ZipArchive::new(source).with_context(|| format!("Cannot read archive '{}'", path.to_string_lossy()))?;
+        Ok(Self { archive, passphrase })
+    }
+
+    /// Returns the names of all entries in the archive.
+    pub fn file_names(&self) -> impl Iterator<Item = &str> {
+        self.archive.file_names()
+    }
+
+    /// Reads the content of an entry of the archive.
+    pub fn read(&mut self, name: &str) -> Result<Vec<u8>> {
+        let mut file = match self.passphrase.as_ref() {
+            Some(passphrase) => self.archive.by_name_decrypt(name, passphrase.as_bytes()),
+            None => self.archive.by_name(name),
+        }
+        .with_context(|| format!("Cannot open entry '{name}' of the archive"))?;
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)
+            .with_context(|| format!("Cannot read entry '{name}' of the archive"))?;
+        Ok(content)
+    }
+
+    fn read_string(&mut self, name: &str) -> Result<String> {
+        Ok(String::from_utf8_lossy(&self.read(name)?).to_string())
+    }
+
+    /// Reads all runs of the archive.
+    pub fn runs(&mut self) -> Result<Vec<Run>> {
+        let schema_version = self.manifest()?.schema_version;
+
+        // Modification times are read without decrypting the entries
+        let mut modified = HashMap::new();
+        for index in 0..self.archive.len() {
+            let file = self
+                .archive
+                .by_index_raw(index)
+                .with_context(|| format!("Cannot open entry {index} of the archive"))?;
+            if let Some(time) = file.last_modified().and_then(|time| NaiveDateTime::try_from(time).ok()) {
+                modified.insert(file.name().to_string(), time);
+            }
+        }
+
+        let mut runs = BTreeMap::<String, (RunOrigin, Vec<String>)>::new();
+        for name in self.archive.file_names() {
+            if let Some((path, origin, file)) = split_run_entry_name(name, schema_version) {
+                let (_, files) = runs.entry(path).or_insert_with(|| (origin, Vec::new()));
+                if !file.is_empty() && !file.ends_with('/') {
+                    files.push(file);
+                }
+            }
+        }
+
+        let mut result = Vec::new();
+        for (path, (origin, files)) in runs {
+            let prefix = path.clone();
+            let mut read = |file: &str| self.read_string(&format!("{prefix}/{file}"));
+            let modified = |file: &str| modified.get(&format!("{prefix}/{file}")).copied();
+            result.push(build_run(path, origin, files, None, true, &mut read, &modified)?);
+        }
+        Ok(result)
+    }
+
+    /// Reads every entry of the archive to check its checksum and parses the manifest.
+    ///
+    /// Returns the number of verified entries.
+    pub fn verify(&mut self) -> Result<usize> {
+        for index in 0..self.archive.len() {
+            let mut file = match self.passphrase.as_ref() {
+                Some(passphrase) => self.archive.by_index_decrypt(index, passphrase.as_bytes()),
+                None => self.archive.by_index(index),
+            }
+            .with_context(|| format!("Cannot open entry {index} of the archive"))?;
+            let name = file.name().to_string();
+            std::io::copy(&mut file, &mut std::io::sink())
+                .with_context(|| format!("Cannot read entry '{name}' of the archive"))?;
+        }
+        self.manifest()?;
+        Ok(self.archive.len())
+    }
+
+    /// Reads the manifest of the archive, archives of older versions without manifest return an empty one.
+    pub fn manifest(&mut self) -> Result<Manifest> {
+        if self.archive.index_for_name(MANIFEST_FILE_NAME).is_none() {
+            return Ok(Manifest::default());
+        }
+        let content = self.read(MANIFEST_FILE_NAME)?;
+        serde_json::from_slice(&content).with_context(|| format!("Cannot parse '{MANIFEST_FILE_NAME}'"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    const PANIC: &str = "Thread 'main' panicked at 'boom': src/main.rs:3:5\n   0: app::main\n";
+
+    fn write_zip(path: &Path, entries: &[(&str, &str)]) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        for (name, content) in entries {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn read_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("report.zip");
+        let manifest = serde_json::to_string(&Manifest {
+            schema_version: ARCHIVE_SCHEMA_VERSION,
+            crate_version: "1.0.0".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        write_zip(
+            &archive,
+            &[
+                (MANIFEST_FILE_NAME, &manifest),
+                ("current/2024-01-03_10_00_00/run.lock", ""),
+                ("current/2024-01-03_10_00_00/app.log", "log"),
+                ("failed/default/2024-01-01_10_00_00/2024-01-01T10:00:01Z.panic", PANIC),
+                ("failed/default/2024-01-01_10_00_00/logs/app.log", "log"),
+                (
+                    "failed/plugins/2024-01-02_10_00_00/exit_report.txt",
+                    "The program run exited with error",
+                ),
+                ("failed/plugins/2024-01-02_10_00_00/ls_stdout.txt", "output"),
+                ("collected/config/app.toml", "key = 1"),
+            ],
+        );
+
+        let mut reader = Reader::open(&archive).unwrap();
+        assert_eq!(reader.verify().unwrap(), 8);
+        assert_eq!(reader.manifest().unwrap().crate_version, "1.0.0");
+        let runs = reader.runs().unwrap();
+        assert_eq!(
+            runs.iter().map(|run| run.path.as_str()).collect::<Vec<_>>(),
+            [
+                "current/2024-01-03_10_00_00",
+                "failed/default/2024-01-01_10_00_00",
+                "failed/plugins/2024-01-02_10_00_00",
+            ]
+        );
+
+        assert_eq!(runs[0].origin, RunOrigin::Current);
+        assert_eq!(runs[0].outcome, RunOutcome::Running);
+
+        assert_eq!(runs[1].name, "2024-01-01_10_00_00");
+        assert_eq!(
+            runs[1].origin,
+            RunOrigin::Failed {
+                source: "default".to_string()
+            }
+        );
+        assert_eq!(runs[1].outcome, RunOutcome::Panicked);
+        assert_eq!(runs[1].files, ["2024-01-01T10:00:01Z.panic", "logs/app.log"]);
+        let panic = &runs[1].panics[0];
+        assert_eq!(panic.time, "2024-01-01T10:00:01Z");
+        assert_eq!(panic.thread.as_deref(), Some("main"));
+        assert_eq!(panic.message, "boom");
+        assert_eq!(panic.location.as_deref(), Some("src/main.rs:3:5"));
+        assert_eq!(panic.backtrace, "   0: app::main\n");
+
+        assert_eq!(
+            runs[2].origin,
+            RunOrigin::Failed {
+                source: "plugins".to_string()
+            }
+        );
+        assert_eq!(runs[2].outcome, RunOutcome::Error);
+        assert_eq!(
+            runs[2].exit_report.as_deref(),
+            Some("The program run exited with error")
+        );
+        assert_eq!(
+            runs[2].command_outputs,
+            [CommandOutput {
+                program: "ls".to_string(),
+                stdout: Some("output".to_string()),
+                stderr: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn read_legacy_archive() {
+        // Older crate versions stripped the first directories of the absolute paths and wrote no manifest
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("report.zip");
+        write_zip(
+            &archive,
+            &[
+                ("app/proc/2024-01-03_10_00_00/app.log", "log"),
+                ("app/proc_failed/2024-01-01_10_00_00/2024-01-01T10:00:01Z.panic", PANIC),
+                (
+                    "app/proc_failed/2024-01-02_10_00_00/exit_report.txt",
+                    crate::proc_dir::REPORT_ABORTED_UNEXPECTED,
+                ),
+                ("app/plugins/2024-01-04_10_00_00/app.log", "log"),
+                ("app/unrelated.txt", "ignored"),
+            ],
+        );
+
+        let mut reader = Reader::open(&archive).unwrap();
+        assert_eq!(reader.manifest().unwrap(), Manifest::default());
+        let runs = reader.runs().unwrap();
+        assert_eq!(
+            runs.iter()
+                .map(|run| (run.path.as_str(), &run.origin, run.outcome))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    "app/plugins/2024-01-04_10_00_00",
+                    &RunOrigin::Failed {
+                        source: "plugins".to_string()
+                    },
+                    RunOutcome::Failed
+                ),
+                ("app/proc/2024-01-03_10_00_00", &RunOrigin::Current, RunOutcome::Failed),
+                (
+                    "app/proc_failed/2024-01-01_10_00_00",
+                    &RunOrigin::Failed {
+                        source: ARCHIVE_DEFAULT_FAILED_SOURCE_NAME.to_string()
+                    },
+                    RunOutcome::Panicked
+                ),
+                (
+                    "app/proc_failed/2024-01-02_10_00_00",
+                    &RunOrigin::Failed {
+                        source: ARCHIVE_DEFAULT_FAILED_SOURCE_NAME.to_string()
+                    },
+                    RunOutcome::Aborted
+                ),
+            ]
+        );
+        assert_eq!(runs[2].panics[0].message, "boom");
+    }
+
+    #[test]
+    fn parse_panic_with_build() {
+        let details = crate::panic_record::PanicDetails {
+            thread: "main".to_string(),
+            message: "boom".to_string(),
+            location: Some("src/main.rs:3:5".to_string()),
+            frames: vec![crate::panic_record::PanicFrame {
+                ip: 0x10,
+                symbols: vec![crate::panic_record::FrameSymbol {
+                    name: Some("app::main".to_string()),
+                    file: Some("src/main.rs".to_string()),
+                    line: Some(3),
+                }],
+            }],
+            build: Some(BuildInfo {
+                package_name: "app".to_string(),
+                package_version: "1.0.0".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let content = details.format_panic_file(&crate::panic_record::BacktraceFilter::full());
+        assert!(
+            content.contains(&format!("\n{BUILD_SECTION_HEADER}\nPackage:  app 1.0.0\n")),
+            "{content}"
+        );
+
+        let record = parse_panic("failed/2024-01-01_10_00_00/2024-01-01_10_00_05.panic", &content);
+        assert_eq!(record.time, "2024-01-01_10_00_05");
+        assert_eq!(record.thread.as_deref(), Some("main"));
+        assert_eq!(record.message, "boom");
+        assert_eq!(record.location.as_deref(), Some("src/main.rs:3:5"));
+        // The build section is removed from the backtrace
+        assert_eq!(record.backtrace, "*  0: app::main\n             at src/main.rs:3\n");
+        assert_eq!(record.backtrace, details.format_backtrace());
+
+        // Panic files without build section are parsed as before
+        assert_eq!(parse_panic("a.panic", PANIC).backtrace, "   0: app::main\n");
+    }
+
+    #[test]
+    fn read_missing_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("report.zip");
+        write_zip(&archive, &[("current/2024-01-01_10_00_00/app.log", "log")]);
+        let mut reader = Reader::open(&archive).unwrap();
+        assert!(reader.read("missing.txt").is_err());
+        assert!(Reader::open(&dir.path().join("missing.zip")).is_err());
+    }
+}
@@ -0,0 +1,340 @@
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, SystemTime},
+};
+use sysinfo::{CpuRefreshKind, MemoryRefreshKind, Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+
+pub(crate) const RESOURCES_FILE_STEM: &str = "resources";
+const CSV_HEADER: &str = "time,process_cpu_usage,process_memory,process_virtual_memory,threads,open_fds,\
+    process_read_bytes,process_written_bytes,system_cpu_usage,system_used_memory,system_available_memory,\
+    run_dir_size,free_space";
+
+static SAMPLER_RUNNING: AtomicBool = AtomicBool::new(false);
+static SAMPLER_THREAD: Lazy<Mutex<Option<JoinHandle<()>>>> = Lazy::new(|| Mutex::new(None));
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResourceSampleFormat {
+    /// Comma separated values with a header line, written to `resources.csv`.
+    #[default]
+    Csv,
+    /// One JSON object per line, written to `resources.jsonl`.
+    JsonLines,
+}
+
+impl ResourceSampleFormat {
+    fn file_extension(&self) -> &'static str {
+        match self {
+            ResourceSampleFormat::Csv => "csv",
+            ResourceSampleFormat::JsonLines => "jsonl",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ResourceSamplerConfig {
+    pub interval: Duration,
+    pub format: ResourceSampleFormat,
+    /// Maximum size of the samples file in bytes, a full file is renamed to `resources.1.<ext>`
+    /// replacing the previous one, so at most twice the size is used.
+    pub max_file_size: u64,
+}
+
+impl Default for ResourceSamplerConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(5),
+            format: ResourceSampleFormat::default(),
+            max_file_size: 1024 * 1024,
+        }
+    }
+}
+
+/// Resource usage of the process and the system at a moment, memory and disk values in bytes.
+///
+/// Values that are not available on the platform are `None`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResourceSample {
+    /// Time of the sample as RFC 3339 timestamp.
+    pub time: String,
+    /// CPU usage of the process in percent of one core.
+    pub process_cpu_usage: Option<f32>,
+    pub process_memory: Option<u64>,
+    pub process_virtual_memory: Option<u64>,
+    pub threads: Option<usize>,
+    pub open_fds: Option<usize>,
+    pub process_read_bytes: Option<u64>,
+    pub process_written_bytes: Option<u64>,
+    /// CPU usage of the system in percent of all cores.
+    pub system_cpu_usage: f32,
+    pub system_used_memory: u64,
+    pub system_available_memory: u64,
+    pub run_dir_size: u64,
+    /// Free space of the file system of the run directory.
+    pub free_space: Option<u64>,
+}
+
+impl ResourceSample {
+    fn to_csv(&self) -> String {
+        fn value<T: ToString>(value: Option<T>) -> String {
+            value.map(|value| value.to_string()).unwrap_or_default()
+        }
+
+        [
+            self.time.clone(),
+            value(self.process_cpu_usage),
+            value(self.process_memory),
+            value(self.process_virtual_memory),
+            value(self.threads),
+            value(self.open_fds),
+            value(self.process_read_bytes),
+            value(self.process_written_bytes),
+            self.system_cpu_usage.to_string(),
+            self.system_used_memory.to_string(),
+            self.system_available_memory.to_string(),
+            self.run_dir_size.to_string(),
+            value(self.free_space),
+        ]
+        .join(",")
+    }
+}
+
+fn count_dir_entries(path: &str) -> Option<usize> {
+    if cfg!(target_os = "linux") {
+        Some(std::fs::read_dir(path).ok()?.count())
+    } else {
+        None
+    }
+}
+
+struct Sampler {
+    sys: System,
+    pid: Option<Pid>,
+    run_dir: PathBuf,
+}
+
+impl Sampler {
+    fn new(run_dir: PathBuf) -> Self {
+        Self {
+            sys: System::new(),
+            pid: sysinfo::get_current_pid().ok(),
+            run_dir,
+        }
+    }
+
+    fn sample(&mut self) -> ResourceSample {
+        self.sys.refresh_cpu_specifics(CpuRefreshKind::new().with_cpu_usage());
+        self.sys.refresh_memory_specifics(MemoryRefreshKind::new().with_ram());
+        if let Some(pid) = self.pid {
+            self.sys.refresh_processes_specifics(
+                ProcessesToUpdate::Some(&[pid]),
+                true,
+                ProcessRefreshKind::new().with_cpu().with_memory().with_disk_usage(),
+            );
+        }
+        let process = self.pid.and_then(|pid| self.sys.process(pid));
+        ResourceSample {
+            time: humantime::format_rfc3339_millis(SystemTime::now()).to_string(),
+            process_cpu_usage: process.map(|process| process.cpu_usage()),
+            process_memory: process.map(|process| process.memory()),
+            process_virtual_memory: process.map(|process| process.virtual_memory()),
+            threads: count_dir_entries("/proc/self/task"),
+            open_fds: count_dir_entries("/proc/self/fd"),
+            process_read_bytes: process.map(|process| process.disk_usage().total_read_bytes),
+            process_written_bytes: process.map(|process| process.disk_usage().total_written_bytes),
+            system_cpu_usage: self.sys.global_cpu_usage(),
+            system_used_memory: self.sys.used_memory(),
+            system_available_memory: self.sys.available_memory(),
+            run_dir_size: crate::disk_quota::dir_size(&self.run_dir),
+            free_space: fs4::available_space(&self.run_dir).ok(),
+        }
+    }
+}
+
+/// Writes the samples to a file, which is rotated if it exceeds the maximum size.
+struct SampleWriter {
+    path: PathBuf,
+    rotated_path: PathBuf,
+    format: ResourceSampleFormat,
+    max_file_size: u64,
+}
+
+impl SampleWriter {
+    fn new(run_dir: &Path, config: &ResourceSamplerConfig) -> Self {
+        let extension = config.format.file_extension();
+        Self {
+            path: run_dir.join(format!("{RESOURCES_FILE_STEM}.{extension}")),
+            rotated_path: run_dir.join(format!("{RESOURCES_FILE_STEM}.1.{extension}")),
+            format: config.format,
+            max_file_size: config.max_file_size,
+        }
+    }
+
+    fn write(&self, sample: &ResourceSample) -> Result<()> {
+        let line = match self.format {
+            ResourceSampleFormat::Csv => sample.to_csv(),
+            ResourceSampleFormat::JsonLines => serde_json::to_string(sample)?,
+        };
+        let size = std::fs::metadata(&self.path).map(|metadata| metadata.len()).ok();
+        if size.is_some_and(|size| size + line.len() as u64 + 1 > self.max_file_size) {
+            std::fs::rename(&self.path, &self.rotated_path)
+                .with_context(|| format!("Cannot rotate resource samples file '{}'", self.path.to_string_lossy()))?;
+        }
+        let new_file = !self.path.exists();
+        let mut file = File::options()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Cannot open file '{}'", self.path.to_string_lossy()))?;
+        if new_file && self.format == ResourceSampleFormat::Csv {
+            writeln!(file, "{CSV_HEADER}")?;
+        }
+        writeln!(file, "{line}").with_context(|| format!("Cannot write file '{}'", self.path.to_string_lossy()))
+    }
+}
+
+/// Starts sampling the resource usage into the run directory in a background thread.
+///
+/// Does nothing if the sampler is already running.
+pub fn resource_sampler_start(config: ResourceSamplerConfig) -> Result<()> {
+    if SAMPLER_RUNNING.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+    let run_dir = crate::proc_dir::proc_dir().clone();
+    let spawned = std::thread::Builder::new()
+        .name("resource-sampler".to_string())
+        .spawn(move || {
+            let mut sampler = Sampler::new(run_dir.clone());
+            let writer = SampleWriter::new(&run_dir, &config);
+            let mut last_error = None;
+            // The run directory is removed by cleanup at the end of the run
+            while SAMPLER_RUNNING.load(Ordering::SeqCst) && run_dir.is_dir() {
+                let sample = sampler.sample();
+                if crate::disk_quota::diagnostics_allowed() {
+                    match writer.write(&sample) {
+                        Ok(()) => last_error = None,
+                        Err(err) => {
+                            // Log a repeating error only once
+                            let err = format!("{err:?}");
+                            if last_error.as_ref() != Some(&err) {
+                                log::warn!("Cannot write resource sample: {err}");
+                            }
+                            last_error = Some(err);
+                        }
+                    }
+                }
+                park_timeout(config.interval);
+            }
+            SAMPLER_RUNNING.store(false, Ordering::SeqCst);
+        });
+    match spawned {
+        Ok(handle) => {
+            *SAMPLER_THREAD.lock().unwrap() = Some(handle);
+            Ok(())
+        }
+        Err(err) => {
+            SAMPLER_RUNNING.store(false, Ordering::SeqCst);
+            Err(err).with_context(|| "Cannot start resource sampler")
+        }
+    }
+}
+
+// Sleeps for the interval, but returns early if the sampler is stopped
+fn park_timeout(interval: Duration) {
+    let end = std::time::Instant::now() + interval;
+    while SAMPLER_RUNNING.load(Ordering::SeqCst) {
+        let now = std::time::Instant::now();
+        if now >= end {
+            break;
+        }
+        std::thread::park_timeout(end - now);
+    }
+}
+
+/// Stops the sampler and waits until its thread has finished.
+pub fn resource_sampler_stop() {
+    SAMPLER_RUNNING.store(false, Ordering::SeqCst);
+    if let Some(handle) = SAMPLER_THREAD.lock().unwrap().take() {
+        handle.thread().unpark();
+        if handle.join().is_err() {
+            log::warn!("Resource sampler thread panicked");
+        }
+    }
+}
+
+pub fn resource_sampler_is_running() -> bool {
+    SAMPLER_RUNNING.load(Ordering::SeqCst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(second: u32) -> ResourceSample {
+        ResourceSample {
+            time: format!("2024-01-01T00:00:{second:02}Z"),
+            process_cpu_usage: Some(1.5),
+            process_memory: Some(1000),
+            process_virtual_memory: None,
+            threads: Some(4),
+            open_fds: None,
+            process_read_bytes: None,
+            process_written_bytes: None,
+            system_cpu_usage: 10.0,
+            system_used_memory: 2000,
+            system_available_memory: 3000,
+            run_dir_size: 100,
+            free_space: Some(5000),
+        }
+    }
+
+    fn times(path: &Path) -> Vec<String> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| line.split(',').next().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn rotate_samples_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let line_len = sample(0).to_csv().len() as u64 + 1;
+        // The header and two samples fit into a file
+        let config = ResourceSamplerConfig {
+            max_file_size: CSV_HEADER.len() as u64 + 1 + 2 * line_len,
+            ..Default::default()
+        };
+        let writer = SampleWriter::new(dir.path(), &config);
+        for second in 1..=3 {
+            writer.write(&sample(second)).unwrap();
+        }
+        let path = dir.path().join("resources.csv");
+        let rotated_path = dir.path().join("resources.1.csv");
+        assert_eq!(
+            times(&rotated_path),
+            ["time", "2024-01-01T00:00:01Z", "2024-01-01T00:00:02Z"]
+        );
+        assert_eq!(times(&path), ["time", "2024-01-01T00:00:03Z"]);
+
+        // The previous rotated file is replaced
+        for second in 4..=5 {
+            writer.write(&sample(second)).unwrap();
+        }
+        assert_eq!(
+            times(&rotated_path),
+            ["time", "2024-01-01T00:00:03Z", "2024-01-01T00:00:04Z"]
+        );
+        assert_eq!(times(&path), ["time", "2024-01-01T00:00:05Z"]);
+        assert!(std::fs::metadata(&rotated_path).unwrap().len() <= config.max_file_size);
+    }
+}
@@ -0,0 +1,33 @@
+use std::path::PathBuf;
+
+pub(super) mod internal {
+    #[derive(Debug)]
+    pub enum PrivateMsg {
+        UploadReport,
+        SendReportMail,
+    }
+
+    /// Results of the background work, tagged with the report they belong to.
+    #[derive(Debug)]
+    pub enum CommandMsg {
+        UploadProgress(u64, u32),
+        UploadFinished(u64, anyhow::Result<()>),
+        MailFinished(u64, anyhow::Result<()>),
+    }
+}
+
+#[derive(Debug)]
+pub enum SendReportGroupInput {
+    PrivateMessage(internal::PrivateMsg),
+    /// Sets the report file to send, a running upload or mail of the previous report is cancelled.
+    SetReport {
+        path: PathBuf,
+        subject: String,
+        body: String,
+    },
+    /// Cancels a running upload or mail.
+    Cancel,
+}
+
+#[derive(Debug)]
+pub enum SendReportGroupOutput {}
@@ -0,0 +1,3 @@
+pub mod messages;
+pub mod model;
+mod widget;
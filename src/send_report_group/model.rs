@@ -0,0 +1,29 @@
+use crate::proc_dir::CancellationToken;
+use std::path::PathBuf;
+
+/// Upload and mail actions for a created report file, shared by the dialogs.
+#[derive(Debug, Default)]
+pub struct SendReportGroup {
+    pub(super) path: PathBuf,
+    pub(super) subject: String,
+    pub(super) body: String,
+    /// Incremented for each report, results of a previous report are ignored.
+    pub(super) report_id: u64,
+    pub(super) upload_cancellation: Option<CancellationToken>,
+    pub(super) mail_cancellation: Option<CancellationToken>,
+}
+
+impl SendReportGroup {
+    pub(super) fn is_busy(&self) -> bool {
+        self.upload_cancellation.is_some() || self.mail_cancellation.is_some()
+    }
+
+    pub(super) fn cancel(&mut self) {
+        for cancellation in [&self.upload_cancellation, &self.mail_cancellation]
+            .into_iter()
+            .flatten()
+        {
+            cancellation.cancel();
+        }
+    }
+}
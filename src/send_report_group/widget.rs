@@ -0,0 +1,200 @@
+use super::{
+    messages::{
+        internal::{CommandMsg, PrivateMsg},
+        SendReportGroupInput, SendReportGroupOutput,
+    },
+    model::SendReportGroup,
+};
+use crate::{localization::helper::fl, proc_dir::CancellationToken};
+use mxl_relm4_components::relm4::{
+    self,
+    adw::{self, prelude::*},
+    gtk::glib,
+    prelude::*,
+    Component, ComponentParts, ComponentSender,
+};
+use relm4_icons::icon_names;
+
+#[relm4::component(pub)]
+impl Component for SendReportGroup {
+    type Init = ();
+    type Input = SendReportGroupInput;
+    type Output = SendReportGroupOutput;
+    type CommandOutput = CommandMsg;
+
+    view! {
+        adw::PreferencesGroup {
+            #[watch]
+            set_visible: crate::misc::upload_available() || crate::misc::mail_available(),
+            #[wrap(Some)]
+            set_header_suffix = &gtk::Button {
+                set_label: &fl!("send-report-group", "btn-cancel"),
+                add_css_class: "flat",
+                #[watch]
+                set_visible: model.is_busy(),
+                connect_clicked => SendReportGroupInput::Cancel,
+            },
+
+            #[name(upload_row)]
+            adw::ActionRow {
+                set_title: &fl!("send-report-group", "btn-upload"),
+                #[watch]
+                set_visible: crate::misc::upload_available(),
+                set_activatable: true,
+                add_suffix = &gtk::Image::from_icon_name(icon_names::RIGHT_LARGE) {},
+                connect_activated => SendReportGroupInput::PrivateMessage(PrivateMsg::UploadReport),
+            },
+
+            #[name(mail_row)]
+            adw::ActionRow {
+                set_title: &fl!("send-report-group", "btn-send-mail"),
+                #[watch]
+                set_visible: crate::misc::mail_available(),
+                set_activatable: true,
+                add_suffix = &gtk::Image::from_icon_name(icon_names::RIGHT_LARGE) {},
+                connect_activated => SendReportGroupInput::PrivateMessage(PrivateMsg::SendReportMail),
+            },
+        }
+    }
+
+    fn init(_init: Self::Init, root: Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self> {
+        let model = SendReportGroup::default();
+        let widgets = view_output!();
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update_with_view(
+        &mut self,
+        widgets: &mut Self::Widgets,
+        msg: Self::Input,
+        sender: ComponentSender<Self>,
+        _root: &Self::Root,
+    ) {
+        match msg {
+            SendReportGroupInput::PrivateMessage(msg) => match msg {
+                PrivateMsg::UploadReport => {
+                    widgets.upload_row.set_sensitive(false);
+                    widgets
+                        .upload_row
+                        .set_subtitle(&fl!("send-report-group", "upload-progress", percent = 0));
+                    let cancellation = CancellationToken::new();
+                    self.upload_cancellation = Some(cancellation.clone());
+                    let report_id = self.report_id;
+                    let path = self.path.clone();
+                    sender.spawn_command(move |out| {
+                        let progress_out = out.clone();
+                        let result = crate::misc::upload_report(
+                            &path,
+                            cancellation,
+                            Box::new(move |percent| {
+                                progress_out
+                                    .send(CommandMsg::UploadProgress(report_id, percent))
+                                    .unwrap_or_default()
+                            }),
+                        );
+                        out.send(CommandMsg::UploadFinished(report_id, result))
+                            .unwrap_or_default();
+                    });
+                }
+                PrivateMsg::SendReportMail => {
+                    widgets.mail_row.set_sensitive(false);
+                    widgets.mail_row.set_subtitle(&fl!("send-report-group", "mail-sending"));
+                    let cancellation = CancellationToken::new();
+                    self.mail_cancellation = Some(cancellation.clone());
+                    let report_id = self.report_id;
+                    let subject = self.subject.clone();
+                    let body = self.body.clone();
+                    let path = self.path.clone();
+                    sender.spawn_command(move |out| {
+                        let result = crate::misc::send_report_mail(&subject, &body, &path, cancellation);
+                        out.send(CommandMsg::MailFinished(report_id, result))
+                            .unwrap_or_default();
+                    });
+                }
+            },
+            SendReportGroupInput::SetReport { path, subject, body } => {
+                self.cancel();
+                self.upload_cancellation = None;
+                self.mail_cancellation = None;
+                self.report_id += 1;
+                self.path = path;
+                self.subject = subject;
+                self.body = body;
+                widgets.upload_row.set_sensitive(true);
+                widgets.upload_row.set_subtitle("");
+                widgets.mail_row.set_sensitive(true);
+                widgets.mail_row.set_subtitle("");
+            }
+            SendReportGroupInput::Cancel => self.cancel(),
+        }
+        self.update_view(widgets, sender);
+    }
+
+    fn update_cmd_with_view(
+        &mut self,
+        widgets: &mut Self::Widgets,
+        message: Self::CommandOutput,
+        sender: ComponentSender<Self>,
+        _root: &Self::Root,
+    ) {
+        match message {
+            CommandMsg::UploadProgress(report_id, percent) if report_id == self.report_id => {
+                widgets
+                    .upload_row
+                    .set_subtitle(&fl!("send-report-group", "upload-progress", percent = percent));
+            }
+            CommandMsg::UploadFinished(report_id, result) if report_id == self.report_id => {
+                let cancelled = self
+                    .upload_cancellation
+                    .take()
+                    .is_some_and(|cancellation| cancellation.is_cancelled());
+                match result {
+                    Ok(()) => widgets
+                        .upload_row
+                        .set_subtitle(&fl!("send-report-group", "upload-success")),
+                    Err(_) if cancelled => {
+                        widgets
+                            .upload_row
+                            .set_subtitle(&fl!("send-report-group", "upload-cancelled"));
+                        widgets.upload_row.set_sensitive(true);
+                    }
+                    Err(err) => {
+                        let error = glib::markup_escape_text(&format!("{err:#}"));
+                        widgets.upload_row.set_subtitle(&fl!(
+                            "send-report-group",
+                            "upload-error",
+                            error = error.as_str()
+                        ));
+                        widgets.upload_row.set_sensitive(true);
+                    }
+                }
+            }
+            CommandMsg::MailFinished(report_id, result) if report_id == self.report_id => {
+                let cancelled = self
+                    .mail_cancellation
+                    .take()
+                    .is_some_and(|cancellation| cancellation.is_cancelled());
+                match result {
+                    Ok(()) => widgets.mail_row.set_subtitle(&fl!("send-report-group", "mail-success")),
+                    Err(_) if cancelled => {
+                        widgets
+                            .mail_row
+                            .set_subtitle(&fl!("send-report-group", "mail-cancelled"));
+                        widgets.mail_row.set_sensitive(true);
+                    }
+                    Err(err) => {
+                        let error = glib::markup_escape_text(&format!("{err:#}"));
+                        widgets
+                            .mail_row
+                            .set_subtitle(&fl!("send-report-group", "mail-error", error = error.as_str()));
+                        widgets.mail_row.set_sensitive(true);
+                    }
+                }
+            }
+            // Results of a previous report
+            CommandMsg::UploadProgress(..) | CommandMsg::UploadFinished(..) | CommandMsg::MailFinished(..) => {}
+        }
+        self.update_view(widgets, sender);
+    }
+}
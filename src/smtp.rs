@@ -0,0 +1,637 @@
+use crate::proc_dir::CancellationToken;
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use lettre::{
+    message::{
+        header::{self, Headers},
+        Mailbox, Mailboxes, SinglePart,
+    },
+    transport::smtp::{
+        authentication::{Credentials, Mechanism},
+        client::{SmtpConnection, TlsParameters},
+        commands::{Data, Mail, Rcpt},
+        extension::ClientId,
+    },
+    Address,
+};
+use once_cell::sync::Lazy;
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+    sync::RwLock,
+    time::Duration,
+};
+
+// Number of bytes encoded in a base64 line of 76 characters
+const BASE64_LINE_BYTES: usize = 57;
+
+static SMTP_CONFIG: Lazy<RwLock<Option<SmtpConfig>>> = Lazy::new(|| RwLock::new(None));
+
+/// Transport security of the connection to the SMTP server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SmtpSecurity {
+    /// Unencrypted connection, only for servers in trusted networks.
+    None,
+    /// Unencrypted connection upgraded with `STARTTLS`, usually on port 587.
+    #[default]
+    StartTls,
+    /// TLS from the start of the connection, usually on port 465.
+    Tls,
+}
+
+/// SMTP server and addresses used to send reports.
+#[derive(Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub security: SmtpSecurity,
+    /// User name and password, if the server requires authentication.
+    pub credentials: Option<(String, String)>,
+    /// Allows to send the credentials with [`SmtpSecurity::None`], e.g. to a server in a trusted network.
+    pub allow_insecure_credentials: bool,
+    /// Sender address of the mails.
+    pub from: String,
+    /// Recipient addresses of the mails, e.g. the support address.
+    pub to: Vec<String>,
+    /// Name sent with `EHLO`.
+    pub hello_name: String,
+    pub timeout: Duration,
+}
+
+impl SmtpConfig {
+    pub fn new(host: impl Into<String>, from: impl Into<String>, to: impl Into<String>) -> Self {
+        Self {
+            host: host.into(),
+            port: 587,
+            security: SmtpSecurity::default(),
+            credentials: None,
+            allow_insecure_credentials: false,
+            from: from.into(),
+            to: vec![to.into()],
+            hello_name: "localhost".to_string(),
+            timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+impl std::fmt::Debug for SmtpConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The password must not be logged
+        f.debug_struct("SmtpConfig")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("security", &self.security)
+            .field("user", &self.credentials.as_ref().map(|(user, _)| user))
+            .field("allow_insecure_credentials", &self.allow_insecure_credentials)
+            .field("from", &self.from)
+            .field("to", &self.to)
+            .field("hello_name", &self.hello_name)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+/// Sets the SMTP server to send reports, which enables the mail actions of the dialogs.
+pub fn smtp_set_config(config: SmtpConfig) {
+    *SMTP_CONFIG.write().unwrap() = Some(config);
+}
+
+pub fn smtp_config() -> Option<SmtpConfig> {
+    SMTP_CONFIG.read().unwrap().clone()
+}
+
+pub fn smtp_is_configured() -> bool {
+    SMTP_CONFIG.read().unwrap().is_some()
+}
+
+/// Error returned if sending a mail was cancelled with a [`CancellationToken`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MailCancelled;
+
+impl std::fmt::Display for MailCancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Mail cancelled")
+    }
+}
+
+impl std::error::Error for MailCancelled {}
+
+/// Options of a single report mail.
+#[derive(Debug, Default)]
+pub struct SmtpOptions {
+    /// Cancels sending until the message is transferred, a started transfer of the message is completed.
+    pub cancellation: Option<CancellationToken>,
+}
+
+impl SmtpOptions {
+    fn check_cancelled(&self) -> Result<()> {
+        if self.cancellation.as_ref().is_some_and(CancellationToken::is_cancelled) {
+            return Err(MailCancelled.into());
+        }
+        Ok(())
+    }
+}
+
+// Size of the attachment read and encoded at once while the message is sent
+const ATTACHMENT_CHUNK_SIZE: usize = BASE64_LINE_BYTES * 1024;
+
+fn parse_address(address: &str) -> Result<Address> {
+    address
+        .parse::<Address>()
+        .with_context(|| format!("Invalid mail address '{address}'"))
+}
+
+/// Returns the message up to the content of the attachment.
+fn message_head(
+    from: &Address,
+    to: &[Address],
+    subject: &str,
+    body: &str,
+    file_name: &str,
+    boundary: &str,
+) -> Result<Vec<u8>> {
+    let mut headers = Headers::new();
+    headers.set(header::From::from(Mailboxes::from(Mailbox::new(None, from.clone()))));
+    headers.set(header::To::from(
+        to.iter()
+            .map(|to| Mailbox::new(None, to.clone()))
+            .collect::<Mailboxes>(),
+    ));
+    headers.set(header::Subject::from(subject.to_string()));
+    headers.set(header::Date::now());
+    headers.set(header::MessageId::from(format!("<{boundary}@{}>", from.domain())));
+    headers.set(header::MIME_VERSION_1_0);
+    headers.set(header::ContentType::parse(&format!(
+        "multipart/mixed; boundary=\"{boundary}\""
+    ))?);
+
+    let mut attachment_headers = Headers::new();
+    attachment_headers.set(header::ContentType::parse(crate::proc_dir::ARCHIVE_MIME_TYPE)?);
+    attachment_headers.set(header::ContentDisposition::attachment(file_name));
+    attachment_headers.set(header::ContentTransferEncoding::Base64);
+
+    let mut head = format!("{headers}\r\n--{boundary}\r\n").into_bytes();
+    head.extend_from_slice(&SinglePart::plain(body.to_string()).formatted());
+    head.extend_from_slice(format!("--{boundary}\r\n{attachment_headers}\r\n").as_bytes());
+    Ok(head)
+}
+
+/// Reads the complete attachment once before it is sent.
+///
+/// The message cannot be aborted after `DATA` without the server accepting what was sent so far, so a report that
+/// cannot be read must be detected before.
+fn check_attachment(attachment: &Path, options: &SmtpOptions) -> Result<()> {
+    let mut file =
+        File::open(attachment).with_context(|| format!("Cannot read attachment '{}'", attachment.to_string_lossy()))?;
+    let mut buffer = vec![0; ATTACHMENT_CHUNK_SIZE];
+    loop {
+        options.check_cancelled()?;
+        let count = file
+            .read(&mut buffer)
+            .with_context(|| format!("Cannot read attachment '{}'", attachment.to_string_lossy()))?;
+        if count == 0 {
+            return Ok(());
+        }
+    }
+}
+
+/// Encodes the attachment in base64 lines chunk by chunk while the message is sent.
+///
+/// A read error ends the attachment early, it is stored in `error` to be returned after sending. As the attachment
+/// is checked by [`check_attachment`] before, this only happens if the file is changed while it is sent.
+struct AttachmentChunks<'a, R> {
+    reader: R,
+    error: &'a mut Option<std::io::Error>,
+    done: bool,
+}
+
+impl<R: Read> Iterator for AttachmentChunks<'_, R> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mut chunk = Vec::with_capacity(ATTACHMENT_CHUNK_SIZE);
+        if let Err(err) = (&mut self.reader)
+            .take(ATTACHMENT_CHUNK_SIZE as u64)
+            .read_to_end(&mut chunk)
+        {
+            *self.error = Some(err);
+            self.done = true;
+            return None;
+        }
+        self.done = chunk.len() < ATTACHMENT_CHUNK_SIZE;
+        if chunk.is_empty() {
+            return None;
+        }
+        let mut lines = Vec::with_capacity(chunk.len() / BASE64_LINE_BYTES * (BASE64_LINE_BYTES * 4 / 3 + 2) + 80);
+        for line in chunk.chunks(BASE64_LINE_BYTES) {
+            lines.extend_from_slice(BASE64.encode(line).as_bytes());
+            lines.extend_from_slice(b"\r\n");
+        }
+        Some(lines)
+    }
+}
+
+fn connect(config: &SmtpConfig) -> Result<SmtpConnection> {
+    let hello_name = ClientId::Domain(config.hello_name.clone());
+    let tls_parameters = match config.security {
+        SmtpSecurity::None => None,
+        SmtpSecurity::StartTls | SmtpSecurity::Tls => Some(
+            TlsParameters::new(config.host.clone())
+                .with_context(|| format!("Cannot set up TLS for SMTP server '{}'", config.host))?,
+        ),
+    };
+    // All resolved addresses of the server are tried
+    let mut connection = SmtpConnection::connect(
+        (config.host.as_str(), config.port),
+        Some(config.timeout),
+        &hello_name,
+        tls_parameters.as_ref().filter(|_| config.security == SmtpSecurity::Tls),
+        None,
+    )
+    .with_context(|| format!("Cannot connect to SMTP server '{}:{}'", config.host, config.port))?;
+    if let (SmtpSecurity::StartTls, Some(tls_parameters)) = (config.security, tls_parameters.as_ref()) {
+        connection
+            .starttls(tls_parameters, &hello_name)
+            .with_context(|| format!("Cannot start TLS with SMTP server '{}'", config.host))?;
+    }
+    Ok(connection)
+}
+
+fn send_message(
+    connection: &mut SmtpConnection,
+    config: &SmtpConfig,
+    options: &SmtpOptions,
+    from: Address,
+    to: Vec<Address>,
+    message: impl Iterator<Item = Vec<u8>>,
+) -> Result<()> {
+    options.check_cancelled()?;
+    if let Some((user, password)) = config.credentials.as_ref() {
+        connection
+            .auth(
+                &[Mechanism::Plain, Mechanism::Login],
+                &Credentials::new(user.clone(), password.clone()),
+            )
+            .with_context(|| "SMTP server rejected the authentication")?;
+    }
+    options.check_cancelled()?;
+    connection
+        .command(Mail::new(Some(from), Vec::new()))
+        .with_context(|| "SMTP server rejected the sender")?;
+    for to in to {
+        options.check_cancelled()?;
+        connection
+            .command(Rcpt::new(to.clone(), Vec::new()))
+            .with_context(|| format!("SMTP server rejected the recipient '{to}'"))?;
+    }
+    options.check_cancelled()?;
+    connection
+        .command(Data)
+        .with_context(|| "SMTP server rejected the message")?;
+    connection
+        .message_iter(message)
+        .with_context(|| "SMTP server rejected the message")?;
+    Ok(())
+}
+
+/// Sends a mail with the given subject and body and the report file as attachment.
+///
+/// The attachment is encoded while it is sent, so the report file is never loaded into memory at once.
+/// Credentials are refused with [`SmtpSecurity::None`] unless [`SmtpConfig::allow_insecure_credentials`] is set.
+pub fn smtp_send_report_with_config(
+    config: &SmtpConfig,
+    subject: &str,
+    body: &str,
+    attachment: &Path,
+    options: SmtpOptions,
+) -> Result<()> {
+    if config.to.is_empty() {
+        anyhow::bail!("No recipient for the report mail configured");
+    }
+    if config.credentials.is_some() && config.security == SmtpSecurity::None && !config.allow_insecure_credentials {
+        anyhow::bail!(
+            "Refuse to send the credentials unencrypted to SMTP server '{}', use TLS or allow insecure credentials",
+            config.host
+        );
+    }
+    let from = parse_address(&config.from)?;
+    let to = config
+        .to
+        .iter()
+        .map(|to| parse_address(to))
+        .collect::<Result<Vec<_>>>()?;
+    check_attachment(attachment, &options)?;
+    let file =
+        File::open(attachment).with_context(|| format!("Cannot read attachment '{}'", attachment.to_string_lossy()))?;
+    let file_name = attachment.file_name().unwrap_or_default().to_string_lossy();
+    let boundary = format!(
+        "mxl-investigator-{:x}",
+        chrono::Local::now().timestamp_nanos_opt().unwrap_or_default()
+    );
+    let head = message_head(&from, &to, subject, body, &file_name, &boundary)?;
+
+    let mut read_error = None;
+    let message = std::iter::once(head)
+        .chain(AttachmentChunks {
+            reader: BufReader::new(file),
+            error: &mut read_error,
+            done: false,
+        })
+        .chain(std::iter::once(format!("--{boundary}--\r\n").into_bytes()));
+
+    options.check_cancelled()?;
+    let mut connection = connect(config)?;
+    if let Err(err) = send_message(&mut connection, config, &options, from, to, message) {
+        connection.abort();
+        return Err(err);
+    }
+    // The mail is accepted, a failing QUIT does not matter
+    _ = connection.quit();
+    if let Some(err) = read_error {
+        return Err(anyhow::Error::new(err).context(format!(
+            "Cannot read attachment '{}', the sent mail is incomplete",
+            attachment.to_string_lossy()
+        )));
+    }
+    log::info!(
+        "sent '{}' to {} via '{}'",
+        attachment.to_string_lossy(),
+        config.to.join(", "),
+        config.host
+    );
+    Ok(())
+}
+
+/// Sends a report file with the SMTP server set by [`smtp_set_config`].
+pub fn smtp_send_report(subject: &str, body: &str, attachment: &Path, options: SmtpOptions) -> Result<()> {
+    let config = smtp_config().with_context(|| "No SMTP server configured")?;
+    smtp_send_report_with_config(&config, subject, body, attachment, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, Write},
+        net::{TcpListener, TcpStream},
+        thread::JoinHandle,
+    };
+
+    /// Connection of the SMTP server stand-in.
+    struct Session {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+    }
+
+    impl Session {
+        fn reply(&mut self, reply: &str) {
+            self.writer.write_all(format!("{reply}\r\n").as_bytes()).unwrap();
+        }
+
+        fn read_line(&mut self) -> String {
+            let mut line = String::new();
+            self.reader.read_line(&mut line).unwrap();
+            line.trim_end_matches(['\r', '\n']).to_string()
+        }
+
+        fn expect(&mut self, prefix: &str) -> String {
+            let line = self.read_line();
+            assert!(line.starts_with(prefix), "expected '{prefix}', received '{line}'");
+            line
+        }
+
+        /// Returns the message lines as sent, i.e. with dot-stuffing.
+        fn data(&mut self) -> Vec<String> {
+            let mut lines = Vec::new();
+            loop {
+                let line = self.read_line();
+                if line == "." {
+                    return lines;
+                }
+                lines.push(line);
+            }
+        }
+
+        /// Answers the connection, `EHLO` and the sender with success.
+        fn greet(&mut self, capabilities: &str) {
+            self.reply("220 smtp.test ESMTP");
+            self.expect("EHLO test.local");
+            self.reply(&format!("250-smtp.test\r\n250 {capabilities}"));
+        }
+    }
+
+    fn serve<T: Send + 'static>(script: impl FnOnce(&mut Session) -> T + Send + 'static) -> (u16, JoinHandle<T>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut session = Session {
+                reader: BufReader::new(stream.try_clone().unwrap()),
+                writer: stream,
+            };
+            script(&mut session)
+        });
+        (port, server)
+    }
+
+    fn test_config(port: u16) -> SmtpConfig {
+        SmtpConfig {
+            port,
+            security: SmtpSecurity::None,
+            hello_name: "test.local".to_string(),
+            timeout: Duration::from_secs(5),
+            ..SmtpConfig::new("127.0.0.1", "app@example.com", "support@example.com")
+        }
+    }
+
+    fn test_attachment(dir: &Path) -> (std::path::PathBuf, Vec<u8>) {
+        // Larger than a chunk to encode it in several parts
+        let content = (0..ATTACHMENT_CHUNK_SIZE * 2 + 100)
+            .map(|index| (index * 7 % 251) as u8)
+            .collect::<Vec<_>>();
+        let path = dir.join("report.zip");
+        std::fs::write(&path, &content).unwrap();
+        (path, content)
+    }
+
+    #[test]
+    fn send_report() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, content) = test_attachment(dir.path());
+        let (port, server) = serve(|session| {
+            session.greet("AUTH PLAIN LOGIN");
+            let auth = session.expect("AUTH PLAIN ");
+            let token = BASE64.decode(auth.trim_start_matches("AUTH PLAIN ")).unwrap();
+            assert_eq!(token, b"\0user\0secret");
+            session.reply("235 2.7.0 Authentication successful");
+            session.expect("MAIL FROM:<app@example.com>");
+            session.reply("250 OK");
+            session.expect("RCPT TO:<support@example.com>");
+            session.reply("250 OK");
+            session.expect("RCPT TO:<second@example.com>");
+            session.reply("251 User not local; will forward");
+            session.expect("DATA");
+            session.reply("354 End data with <CR><LF>.<CR><LF>");
+            let data = session.data();
+            session.reply("250 OK queued");
+            session.expect("QUIT");
+            session.reply("221 Bye");
+            data
+        });
+
+        let config = SmtpConfig {
+            credentials: Some(("user".to_string(), "secret".to_string())),
+            allow_insecure_credentials: true,
+            to: vec!["support@example.com".to_string(), "second@example.com".to_string()],
+            ..test_config(port)
+        };
+        let body = "Report of the problem:\n.hidden line\n..two dots";
+        smtp_send_report_with_config(&config, "Problem report", body, &path, SmtpOptions::default()).unwrap();
+
+        let data = server.join().unwrap();
+        assert!(data.contains(&"Subject: Problem report".to_string()));
+        assert!(data.contains(&"To: support@example.com, second@example.com".to_string()));
+        // Lines starting with a dot are escaped with another dot
+        assert!(data.contains(&"..hidden line".to_string()));
+        assert!(data.contains(&"...two dots".to_string()));
+
+        let start = data
+            .iter()
+            .position(|line| line == "Content-Transfer-Encoding: base64")
+            .unwrap();
+        let start = start + data[start..].iter().position(String::is_empty).unwrap() + 1;
+        let end = start + data[start..].iter().position(|line| line.starts_with("--")).unwrap();
+        assert!(data[end].ends_with("--"));
+        assert!(data[start..end].iter().all(|line| line.len() <= 76));
+        assert_eq!(BASE64.decode(data[start..end].concat()).unwrap(), content);
+    }
+
+    #[test]
+    fn error_replies() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, _) = test_attachment(dir.path());
+
+        let (port, server) = serve(|session| {
+            session.greet("AUTH LOGIN");
+            session.expect("AUTH LOGIN");
+            session.reply("535 5.7.8 Authentication credentials invalid");
+        });
+        let config = SmtpConfig {
+            credentials: Some(("user".to_string(), "secret".to_string())),
+            allow_insecure_credentials: true,
+            ..test_config(port)
+        };
+        let err = smtp_send_report_with_config(&config, "Report", "", &path, SmtpOptions::default()).unwrap_err();
+        server.join().unwrap();
+        assert!(format!("{err:#}").contains("rejected the authentication"), "{err:#}");
+        assert!(format!("{err:#}").contains("535"), "{err:#}");
+
+        let (port, server) = serve(|session| {
+            session.greet("SIZE 1000000");
+            session.expect("MAIL FROM:<app@example.com>");
+            session.reply("250 OK");
+            session.expect("RCPT TO:<support@example.com>");
+            session.reply("550 5.1.1 No such user");
+        });
+        let err =
+            smtp_send_report_with_config(&test_config(port), "Report", "", &path, SmtpOptions::default()).unwrap_err();
+        server.join().unwrap();
+        assert!(
+            format!("{err:#}").contains("rejected the recipient 'support@example.com'"),
+            "{err:#}"
+        );
+        assert!(format!("{err:#}").contains("550"), "{err:#}");
+
+        let (port, server) = serve(|session| {
+            session.greet("SIZE 1000000");
+            session.expect("MAIL FROM:");
+            session.reply("250 OK");
+            session.expect("RCPT TO:");
+            session.reply("250 OK");
+            session.expect("DATA");
+            session.reply("354 Go ahead");
+            session.data();
+            session.reply("552 5.3.4 Message too big");
+        });
+        let err =
+            smtp_send_report_with_config(&test_config(port), "Report", "", &path, SmtpOptions::default()).unwrap_err();
+        server.join().unwrap();
+        assert!(format!("{err:#}").contains("552"), "{err:#}");
+    }
+
+    #[test]
+    fn cancelled() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, _) = test_attachment(dir.path());
+        let cancellation = CancellationToken::new();
+        let server_cancellation = cancellation.clone();
+        let (port, server) = serve(move |session| {
+            session.greet("SIZE 1000000");
+            session.expect("MAIL FROM:<app@example.com>");
+            server_cancellation.cancel();
+            session.reply("250 OK");
+            // The transaction is given up before any recipient is sent
+            session.expect("QUIT");
+            session.reply("221 Bye");
+        });
+        let options = SmtpOptions {
+            cancellation: Some(cancellation),
+        };
+        let err = smtp_send_report_with_config(&test_config(port), "Report", "", &path, options).unwrap_err();
+        server.join().unwrap();
+        assert!(err.is::<MailCancelled>(), "{err:#}");
+    }
+
+    #[test]
+    fn unreadable_attachment() {
+        let dir = tempfile::tempdir().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // A directory can be opened, but reading it fails
+        let err = smtp_send_report_with_config(&test_config(port), "Report", "", dir.path(), SmtpOptions::default())
+            .unwrap_err();
+        assert!(err.to_string().starts_with("Cannot read attachment"), "{err}");
+
+        // The server never received a message, not even a connection
+        listener.set_nonblocking(true).unwrap();
+        let accepted = listener.accept();
+        assert!(
+            accepted
+                .as_ref()
+                .is_err_and(|err| err.kind() == std::io::ErrorKind::WouldBlock),
+            "{accepted:?}"
+        );
+    }
+
+    #[test]
+    fn refuse_insecure_credentials() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, _) = test_attachment(dir.path());
+        // Fails before connecting, no server is needed
+        let config = SmtpConfig {
+            credentials: Some(("user".to_string(), "secret".to_string())),
+            ..test_config(1)
+        };
+        let err = smtp_send_report_with_config(&config, "Report", "", &path, SmtpOptions::default()).unwrap_err();
+        assert!(
+            err.to_string().contains("Refuse to send the credentials unencrypted"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn invalid_address() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, _) = test_attachment(dir.path());
+        let config = SmtpConfig {
+            to: vec!["support@example.com>\r\nBcc: other@example.com".to_string()],
+            ..test_config(1)
+        };
+        let err = smtp_send_report_with_config(&config, "Report", "", &path, SmtpOptions::default()).unwrap_err();
+        assert!(err.to_string().starts_with("Invalid mail address"), "{err}");
+    }
+}
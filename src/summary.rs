@@ -0,0 +1,251 @@
+use crate::{
+    manifest::Manifest,
+    report::{Run, RunOrigin, RunOutcome},
+};
+use std::fmt::Write;
+
+pub const SUMMARY_FILE_NAME: &str = "index.html";
+
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; color: #222; }
+h2 { border-bottom: 1px solid #ccc; padding-bottom: 0.2em; }
+table { border-collapse: collapse; margin-bottom: 1em; }
+th, td { text-align: left; padding: 0.2em 1em 0.2em 0; vertical-align: top; }
+pre { background: #f4f4f4; padding: 0.5em; overflow-x: auto; }
+.Panicked, .Hung, .Error, .Aborted { color: #b00020; font-weight: bold; }
+.Running { color: #0060b0; font-weight: bold; }
+.notice { background: #fff4d0; padding: 0.5em; }
+";
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Escapes an entry name of the archive to use it as a relative link.
+///
+/// The result contains no characters that need HTML escaping, so it is also used as id of the run headings.
+fn escape_href(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for byte in name.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => escaped.push(byte as char),
+            byte => _ = write!(escaped, "%{byte:02X}"),
+        }
+    }
+    escaped
+}
+
+/// Returns the id of the heading of a run, the links to it use the same encoding to match it exactly.
+fn run_anchor(run: &Run) -> String {
+    escape_href(&run.path)
+}
+
+fn origin_name(origin: &RunOrigin) -> String {
+    match origin {
+        RunOrigin::Current => "Current".to_string(),
+        RunOrigin::Failed { source } => format!("Failed ({source})"),
+    }
+}
+
+fn start_time(run: &Run) -> String {
+    chrono::NaiveDateTime::parse_from_str(&run.name, crate::proc_dir::CURRENT_DIR_FMT)
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|_| run.name.clone())
+}
+
+fn end_time(run: &Run) -> String {
+    match (run.ended, run.outcome) {
+        (_, RunOutcome::Running) => "Still running".to_string(),
+        (Some(time), _) => time.format("%Y-%m-%d %H:%M:%S").to_string(),
+        (None, _) => "Unknown".to_string(),
+    }
+}
+
+fn write_pre(html: &mut String, title: &str, text: &str, open: bool) {
+    _ = writeln!(
+        html,
+        "<details{}><summary>{}</summary><pre>{}</pre></details>",
+        if open { " open" } else { "" },
+        escape_html(title),
+        escape_html(text.trim_end())
+    );
+}
+
+fn write_run(html: &mut String, run: &Run) {
+    let outcome = format!("{:?}", run.outcome);
+    _ = write!(
+        html,
+        "<h2 id=\"{id}\">{name}</h2>\n<table>\n\
+         <tr><th>Origin</th><td>{origin}</td></tr>\n\
+         <tr><th>Started</th><td>{started}</td></tr>\n\
+         <tr><th>Ended</th><td>{ended}</td></tr>\n\
+         <tr><th>Outcome</th><td class=\"{outcome}\">{outcome}</td></tr>\n",
+        id = run_anchor(run),
+        name = escape_html(&run.name),
+        origin = escape_html(&origin_name(&run.origin)),
+        started = escape_html(&start_time(run)),
+        ended = escape_html(&end_time(run)),
+    );
+    if let Some(build) = run.build.as_ref() {
+        _ = writeln!(
+            html,
+            "<tr><th>Build</th><td>{name} {version}</td></tr>",
+            name = escape_html(&build.package_name),
+            version = escape_html(&build.version_description()),
+        );
+    }
+    html.push_str("</table>\n");
+    if let Some(build) = run.build.as_ref() {
+        write_pre(html, "Build information", &build.to_text(), false);
+    }
+
+    for panic in run.panics.iter() {
+        _ = write!(
+            html,
+            "<h3>Panic at {time}</h3>\n<p>Thread '{thread}' panicked: <code>{message}</code>",
+            time = escape_html(&panic.time),
+            thread = escape_html(panic.thread.as_deref().unwrap_or("<unknown>")),
+            message = escape_html(&panic.message),
+        );
+        if let Some(location) = panic.location.as_ref() {
+            _ = write!(html, " at <code>{}</code>", escape_html(location));
+        }
+        html.push_str("</p>\n");
+        if !panic.backtrace.is_empty() {
+            write_pre(html, "Backtrace", &panic.backtrace, false);
+        }
+    }
+    if let Some(exit_report) = run.exit_report.as_ref() {
+        write_pre(html, "Exit report", exit_report, true);
+    }
+    if let Some(sysinfo) = run.sysinfo.as_ref() {
+        write_pre(html, "System information", sysinfo, false);
+    }
+
+    html.push_str("<h3>Files</h3>\n<ul>\n");
+    for file in run.files.iter() {
+        _ = writeln!(
+            html,
+            "<li><a href=\"{}\">{}</a></li>",
+            escape_href(&format!("{}/{file}", run.path)),
+            escape_html(file)
+        );
+    }
+    html.push_str("</ul>\n");
+}
+
+/// Renders a self-contained HTML page summarizing the runs of an archive.
+pub(crate) fn render(runs: &[Run], manifest: &Manifest) -> String {
+    let mut html = String::new();
+    _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Report summary</title>\n<style>\n{STYLE}</style>\n</head>\n<body>\n\
+         <h1>Report summary</h1>\n<p>Created {created} by mxl-investigator {version}</p>\n",
+        created = escape_html(&manifest.created),
+        version = escape_html(&manifest.crate_version),
+    );
+    if let Some(build) = manifest.build.as_ref() {
+        _ = writeln!(
+            html,
+            "<p>Application {name} {version}</p>",
+            name = escape_html(&build.package_name),
+            version = escape_html(&build.version_description()),
+        );
+    }
+    if manifest.is_partial() {
+        html.push_str(
+            "<p class=\"notice\">This report is partial, some files were excluded or truncated or runs were dropped. \
+             See <a href=\"manifest.json\">manifest.json</a> for details.</p>\n",
+        );
+    }
+
+    if runs.is_empty() {
+        html.push_str("<p>The report contains no runs.</p>\n");
+    } else {
+        html.push_str("<table>\n<tr><th>Run</th><th>Origin</th><th>Outcome</th><th>Panics</th></tr>\n");
+        for run in runs {
+            let outcome = format!("{:?}", run.outcome);
+            _ = writeln!(
+                html,
+                "<tr><td><a href=\"#{id}\">{name}</a></td><td>{origin}</td><td class=\"{outcome}\">{outcome}</td>\
+                 <td>{panics}</td></tr>",
+                id = run_anchor(run),
+                name = escape_html(&run.name),
+                origin = escape_html(&origin_name(&run.origin)),
+                panics = run.panics.len(),
+            );
+        }
+        html.push_str("</table>\n");
+    }
+    for run in runs {
+        write_run(&mut html, run);
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::ExcludedEntry;
+
+    fn run(path: &str) -> Run {
+        Run {
+            name: path.rsplit('/').next().unwrap().to_string(),
+            path: path.to_string(),
+            origin: RunOrigin::Failed {
+                source: "default".to_string(),
+            },
+            outcome: RunOutcome::Failed,
+            panics: Vec::new(),
+            exit_report: None,
+            ended: None,
+            sysinfo: None,
+            build: None,
+            command_outputs: Vec::new(),
+            files: vec!["a b.log".to_string()],
+        }
+    }
+
+    #[test]
+    fn run_anchors() {
+        let html = render(&[run("failed/my plugins/run \"1\"&<2>")], &Manifest::default());
+        let id = "failed/my%20plugins/run%20%221%22%26%3C2%3E";
+        assert!(
+            html.contains(&format!("<a href=\"#{id}\">run &quot;1&quot;&amp;&lt;2&gt;</a>")),
+            "{html}"
+        );
+        assert!(html.contains(&format!("<h2 id=\"{id}\">")), "{html}");
+        assert!(
+            html.contains(&format!("<a href=\"{id}/a%20b.log\">a b.log</a>")),
+            "{html}"
+        );
+    }
+
+    #[test]
+    fn partial_notice() {
+        let runs = [run("failed/default/2024-01-01_10_00_00")];
+        assert!(!render(&runs, &Manifest::default()).contains("This report is partial"));
+        let manifest = Manifest {
+            excluded: vec![ExcludedEntry {
+                name: "failed/default/2024-01-01_10_00_00/core".to_string(),
+                reason: "filter".to_string(),
+            }],
+            ..Default::default()
+        };
+        assert!(manifest.is_partial());
+        assert!(render(&runs, &manifest).contains("This report is partial"));
+    }
+}
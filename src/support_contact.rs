@@ -0,0 +1,164 @@
+use once_cell::sync::Lazy;
+use std::sync::RwLock;
+
+static SUPPORT_CONTACT: Lazy<RwLock<SupportContact>> = Lazy::new(|| RwLock::new(SupportContact::default()));
+
+/// Support contact shown by the dialogs and the templates of the messages sent to it.
+///
+/// The templates can contain the placeholders `{app_name}`, `{app_version}`, `{report_id}` and `{file_name}`.
+#[derive(Debug, Clone)]
+pub struct SupportContact {
+    pub email: Option<String>,
+    pub url: Option<String>,
+    pub phone: Option<String>,
+    pub subject_template: String,
+    pub body_template: String,
+}
+
+impl Default for SupportContact {
+    fn default() -> Self {
+        Self {
+            email: Some("support@x-software.com".to_string()),
+            url: None,
+            phone: None,
+            subject_template: "Report file for {app_name}".to_string(),
+            body_template: "Hello X-Software Support,\n\
+                            \n\
+                            \n\
+                            I would like get assistance for {app_name}.\n\
+                            \n\
+                            Thanks!"
+                .to_string(),
+        }
+    }
+}
+
+/// Values of the placeholders of the [`SupportContact`] templates.
+#[derive(Debug, Clone, Default)]
+pub struct SupportMessageContext {
+    pub app_name: String,
+    pub app_version: String,
+    /// ID of the report, the file name without extension.
+    pub report_id: String,
+    pub file_name: String,
+}
+
+impl SupportMessageContext {
+    pub fn new(app_name: &str, app_version: Option<&str>, report_file_path: &std::path::Path) -> Self {
+        Self {
+            app_name: app_name.to_string(),
+            app_version: app_version.unwrap_or_default().to_string(),
+            report_id: report_file_path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            file_name: report_file_path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+        }
+    }
+
+    fn render(&self, template: &str) -> String {
+        template
+            .replace("{app_name}", &self.app_name)
+            .replace("{app_version}", &self.app_version)
+            .replace("{report_id}", &self.report_id)
+            .replace("{file_name}", &self.file_name)
+    }
+}
+
+impl SupportContact {
+    pub fn subject(&self, context: &SupportMessageContext) -> String {
+        context.render(&self.subject_template)
+    }
+
+    pub fn body(&self, context: &SupportMessageContext) -> String {
+        context.render(&self.body_template)
+    }
+
+    /// Formats the contact as Pango markup with a `mailto:` link containing subject and body.
+    #[cfg(any(feature = "create_report_dialog", feature = "problem_report_dialog"))]
+    pub(crate) fn markup(&self, context: &SupportMessageContext) -> String {
+        use mxl_relm4_components::relm4::gtk::glib::markup_escape_text;
+        use urlencoding::encode;
+
+        let mut contacts = Vec::new();
+        if let Some(email) = self.email.as_ref() {
+            contacts.push(format!(
+                "<a href=\"mailto:{email}?subject={subject}&amp;body={body}\">{email}</a>",
+                email = markup_escape_text(email),
+                subject = encode(&self.subject(context)),
+                body = encode(&self.body(context))
+            ));
+        }
+        if let Some(url) = self.url.as_ref() {
+            let url = markup_escape_text(url);
+            contacts.push(format!("<a href=\"{url}\">{url}</a>"));
+        }
+        if let Some(phone) = self.phone.as_ref() {
+            contacts.push(markup_escape_text(phone).to_string());
+        }
+        contacts.join(", ")
+    }
+}
+
+/// Sets the support contact used by dialogs without their own contact.
+pub fn support_set_contact(contact: SupportContact) {
+    *SUPPORT_CONTACT.write().unwrap() = contact;
+}
+
+pub fn support_contact() -> SupportContact {
+    SUPPORT_CONTACT.read().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn context(app_name: &str) -> SupportMessageContext {
+        SupportMessageContext::new(app_name, Some("1.2.3"), Path::new("/reports/app_2024-01-01.zip"))
+    }
+
+    #[test]
+    fn render_placeholders() {
+        let contact = SupportContact {
+            subject_template: "{app_name} {app_version}".to_string(),
+            body_template: "Report {report_id} in {file_name} for {app_name}, {unknown} {app_name".to_string(),
+            ..Default::default()
+        };
+        let context = context("App");
+        assert_eq!(contact.subject(&context), "App 1.2.3");
+        // Unknown placeholders are kept as they are
+        assert_eq!(
+            contact.body(&context),
+            "Report app_2024-01-01 in app_2024-01-01.zip for App, {unknown} {app_name"
+        );
+
+        let context = SupportMessageContext::new("App", None, Path::new("report"));
+        assert_eq!(contact.subject(&context), "App ");
+        assert_eq!(SupportContact::default().subject(&context), "Report file for App");
+    }
+
+    #[cfg(any(feature = "create_report_dialog", feature = "problem_report_dialog"))]
+    #[test]
+    fn markup_escaping() {
+        let contact = SupportContact {
+            email: Some("support&help@example.com".to_string()),
+            url: Some("https://example.com/?a=1&b=2".to_string()),
+            phone: Some("<0123>".to_string()),
+            subject_template: "{app_name}".to_string(),
+            body_template: "{app_version}".to_string(),
+        };
+        // The app name in the link is encoded, it cannot break the markup of the label
+        assert_eq!(
+            contact.markup(&context("<App> & Co")),
+            "<a href=\"mailto:support&amp;help@example.com?subject=%3CApp%3E%20%26%20Co&amp;body=1.2.3\">\
+             support&amp;help@example.com</a>, \
+             <a href=\"https://example.com/?a=1&amp;b=2\">https://example.com/?a=1&amp;b=2</a>, &lt;0123&gt;"
+        );
+    }
+}
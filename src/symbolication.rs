@@ -0,0 +1,417 @@
+use crate::{
+    panic_record::{FrameSymbol, LoadedModule, PanicDetails, PANIC_DETAILS_FILE_SUFFIX},
+    proc_dir::PANIC_FILE_EXTENSION,
+    report::Reader,
+    summary::SUMMARY_FILE_NAME,
+};
+use anyhow::{Context, Result};
+use object::Object;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+/// Debug files in a directory, found by the build ID or the file name of the module.
+///
+/// Debug files are executables and shared libraries with debug information or separate debug files,
+/// e.g. created by `objcopy --only-keep-debug`.
+pub struct DebugFiles {
+    by_build_id: HashMap<String, PathBuf>,
+    by_name: HashMap<String, PathBuf>,
+    loaders: HashMap<PathBuf, Option<addr2line::Loader>>,
+}
+
+impl std::fmt::Debug for DebugFiles {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DebugFiles")
+            .field("by_build_id", &self.by_build_id)
+            .field("by_name", &self.by_name)
+            .finish()
+    }
+}
+
+fn format_uuid(uuid: [u8; 16]) -> String {
+    let mut text = String::new();
+    for (index, byte) in uuid.iter().enumerate() {
+        if matches!(index, 4 | 6 | 8 | 10) {
+            text.push('-');
+        }
+        text.push_str(&format!("{byte:02x}"));
+    }
+    text
+}
+
+/// Reads the build ID of an object file, `None` if the file is no object file or has no build ID.
+fn read_build_id(path: &Path) -> Option<String> {
+    let file = File::open(path).ok()?;
+    // SAFETY: The file is only read and not expected to be modified while the debug files are scanned
+    let data = unsafe { memmap2::Mmap::map(&file) }.ok()?;
+    let object = object::File::parse(&*data).ok()?;
+    if let Ok(Some(build_id)) = object.build_id() {
+        return Some(build_id.iter().map(|byte| format!("{byte:02x}")).collect());
+    }
+    if let Ok(Some(uuid)) = object.mach_uuid() {
+        return Some(format_uuid(uuid));
+    }
+    None
+}
+
+/// Returns `true` if the object file contains DWARF debug information.
+fn has_debug_info(path: &Path) -> bool {
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+    // SAFETY: The file is only read and not expected to be modified while the debug files are scanned
+    let Ok(data) = (unsafe { memmap2::Mmap::map(&file) }) else {
+        return false;
+    };
+    object::File::parse(&*data).is_ok_and(|object| object.section_by_name(".debug_info").is_some())
+}
+
+impl DebugFiles {
+    /// Scans a directory recursively for debug files.
+    pub fn open(dir: &Path) -> Result<Self> {
+        if !dir.is_dir() {
+            anyhow::bail!("Debug directory '{}' does not exist", dir.to_string_lossy());
+        }
+        let mut by_build_id = HashMap::new();
+        let mut by_name = HashMap::new();
+        for entry in walkdir::WalkDir::new(dir) {
+            let entry = entry.with_context(|| format!("Cannot scan debug directory '{}'", dir.to_string_lossy()))?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let path = entry.path();
+            if let Some(build_id) = read_build_id(path) {
+                // Prefer files with debug information, e.g. separate debug files over stripped binaries
+                if !by_build_id.contains_key(&build_id) || has_debug_info(path) {
+                    by_build_id.insert(build_id, path.to_path_buf());
+                }
+            }
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let name = name.strip_suffix(".debug").unwrap_or(&name).to_string();
+            by_name.entry(name).or_insert_with(|| path.to_path_buf());
+        }
+        log::debug!(
+            "found {} debug files with build ID in '{}'",
+            by_build_id.len(),
+            dir.to_string_lossy()
+        );
+        Ok(Self {
+            by_build_id,
+            by_name,
+            loaders: HashMap::new(),
+        })
+    }
+
+    fn find(&self, module: &LoadedModule) -> Option<PathBuf> {
+        if let Some(build_id) = module.build_id.as_ref() {
+            // A different build must not be used, its addresses do not match
+            return self.by_build_id.get(build_id).cloned();
+        }
+        let name = Path::new(&module.path).file_name()?.to_string_lossy();
+        self.by_name.get(name.as_ref()).cloned()
+    }
+
+    fn loader(&mut self, module: &LoadedModule) -> Option<&addr2line::Loader> {
+        let path = self.find(module)?;
+        self.loaders
+            .entry(path)
+            .or_insert_with_key(|path| match addr2line::Loader::new(path) {
+                Ok(loader) => Some(loader),
+                Err(error) => {
+                    log::warn!("Cannot load debug file '{}': {error}", path.to_string_lossy());
+                    None
+                }
+            })
+            .as_ref()
+    }
+
+    /// Resolves the unresolved frames of a panic.
+    ///
+    /// Returns the number of resolved frames.
+    pub fn symbolicate(&mut self, details: &mut PanicDetails) -> usize {
+        let mut resolved = 0;
+        for (index, frame) in details.frames.iter_mut().enumerate() {
+            if !frame.is_unresolved() {
+                continue;
+            }
+            let Some(module) = details
+                .modules
+                .iter()
+                .find(|module| module.start <= frame.ip && frame.ip < module.end)
+            else {
+                continue;
+            };
+            let Some(loader) = self.loader(module) else {
+                continue;
+            };
+            // Except for the first frame the instruction pointer is the return address after the call
+            let ip = match index {
+                0 => frame.ip,
+                _ => frame.ip.saturating_sub(1),
+            };
+            let probe = ip.wrapping_sub(module.bias);
+
+            let mut symbols = Vec::new();
+            if let Ok(mut frames) = loader.find_frames(probe) {
+                while let Ok(Some(location_frame)) = frames.next() {
+                    symbols.push(FrameSymbol {
+                        name: location_frame
+                            .function
+                            .as_ref()
+                            .and_then(|function| function.demangle().ok())
+                            .map(|name| name.to_string()),
+                        file: location_frame
+                            .location
+                            .as_ref()
+                            .and_then(|location| location.file)
+                            .map(str::to_string),
+                        line: location_frame.location.as_ref().and_then(|location| location.line),
+                    });
+                }
+            }
+            if symbols.iter().all(|symbol| symbol.name.is_none()) {
+                if let Some(name) = loader.find_symbol(probe) {
+                    symbols = vec![FrameSymbol {
+                        name: Some(addr2line::demangle_auto(name.into(), None).to_string()),
+                        ..symbols.into_iter().next().unwrap_or_default()
+                    }];
+                }
+            }
+            if !symbols.is_empty() {
+                frame.symbols = symbols;
+                resolved += 1;
+            }
+        }
+        resolved
+    }
+}
+
+/// Symbolicates the backtraces of all panics in a report archive with the debug files in `debug_dir`.
+///
+/// The archive must not be encrypted. The symbolicated archive is written to `output_file_path`.
+/// The `.panic` files are rewritten with the filter of [`crate::proc_dir::panic_set_backtrace_filter`]
+/// and the summary of the archive is updated. Panic details that cannot be parsed are copied unchanged.
+/// Returns the number of resolved frames.
+pub fn symbolicate_archive(archive_file_path: &Path, debug_dir: &Path, output_file_path: &Path) -> Result<usize> {
+    let mut debug_files = DebugFiles::open(debug_dir)?;
+    let file = File::open(archive_file_path)
+        .with_context(|| format!("Cannot open archive '{}'", archive_file_path.to_string_lossy()))?;
+    let mut archive = ZipArchive::new(file)
+        .with_context(|| format!("Cannot read archive '{}'", archive_file_path.to_string_lossy()))?;
+
+    // Symbolicate all panic details first, the `.panic` files can be stored before their details
+    let mut resolved = 0;
+    let mut symbolicated = HashMap::new();
+    let details_names = archive
+        .file_names()
+        .filter(|name| name.ends_with(PANIC_DETAILS_FILE_SUFFIX))
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    for name in details_names {
+        let mut content = Vec::new();
+        archive
+            .by_name(&name)
+            .and_then(|mut entry| Ok(entry.read_to_end(&mut content)?))
+            .with_context(|| format!("Cannot read entry '{name}' of the archive"))?;
+        // Details may be truncated to fit the size limit or damaged by the redaction
+        let mut details: PanicDetails = match serde_json::from_slice(&content) {
+            Ok(details) => details,
+            Err(err) => {
+                log::warn!("Skipping entry '{name}' of the archive, it cannot be parsed: {err}");
+                continue;
+            }
+        };
+        resolved += debug_files.symbolicate(&mut details);
+        let panic_name = format!(
+            "{}.{PANIC_FILE_EXTENSION}",
+            name.strip_suffix(PANIC_DETAILS_FILE_SUFFIX).unwrap_or_default()
+        );
+        symbolicated.insert(panic_name, (name, details));
+    }
+
+    let output = File::create(output_file_path)
+        .with_context(|| format!("Cannot create archive '{}'", output_file_path.to_string_lossy()))?;
+    let mut zip = ZipWriter::new(output);
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Bzip2);
+    let filter = crate::proc_dir::panic_backtrace_filter();
+    let details_by_name = symbolicated
+        .iter()
+        .map(|(_, (name, details))| (name.clone(), details))
+        .collect::<HashMap<_, _>>();
+    let mut has_summary = false;
+    for index in 0..archive.len() {
+        let name = archive.name_for_index(index).unwrap_or_default().to_string();
+        if name == SUMMARY_FILE_NAME {
+            // Rendered again from the symbolicated archive
+            has_summary = true;
+        } else if let Some(details) = details_by_name.get(&name) {
+            zip.start_file(name.as_str(), options)?;
+            serde_json::to_writer_pretty(&mut zip, details)
+                .with_context(|| format!("Cannot write entry '{name}' to the archive"))?;
+        } else if let Some((_, details)) = symbolicated.get(&name) {
+            zip.start_file(name.as_str(), options)?;
+            zip.write_all(details.format_panic_file(&filter).as_bytes())
+                .with_context(|| format!("Cannot write entry '{name}' to the archive"))?;
+        } else {
+            let entry = archive.by_index_raw(index)?;
+            zip.raw_copy_file(entry)
+                .with_context(|| format!("Cannot copy entry '{name}' to the archive"))?;
+        }
+    }
+    zip.finish()
+        .with_context(|| format!("Cannot finish archive '{}'", output_file_path.to_string_lossy()))?;
+
+    if has_summary {
+        write_summary(output_file_path, options)?;
+    }
+    Ok(resolved)
+}
+
+/// Appends the summary of the runs in the archive.
+fn write_summary(archive_file_path: &Path, options: SimpleFileOptions) -> Result<()> {
+    let mut reader = Reader::open(archive_file_path)?;
+    let summary = crate::summary::render(&reader.runs()?, &reader.manifest()?);
+    drop(reader);
+
+    let file = File::options()
+        .read(true)
+        .write(true)
+        .open(archive_file_path)
+        .with_context(|| format!("Cannot open archive '{}'", archive_file_path.to_string_lossy()))?;
+    let mut zip = ZipWriter::new_append(file)
+        .with_context(|| format!("Cannot read archive '{}'", archive_file_path.to_string_lossy()))?;
+    zip.start_file(SUMMARY_FILE_NAME, options)
+        .with_context(|| format!("Cannot add file '{SUMMARY_FILE_NAME}' to archive"))?;
+    zip.write_all(summary.as_bytes())
+        .with_context(|| format!("Cannot write file '{SUMMARY_FILE_NAME}' to the archive"))?;
+    zip.finish()
+        .with_context(|| format!("Cannot finish archive '{}'", archive_file_path.to_string_lossy()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{manifest::Manifest, panic_record::PanicFrame};
+
+    const RUN_DIR: &str = "failed/default/2024-01-01_10_00_00";
+
+    fn frame(ip: u64, name: Option<&str>) -> PanicFrame {
+        PanicFrame {
+            ip,
+            symbols: vec![FrameSymbol {
+                name: name.map(str::to_string),
+                ..Default::default()
+            }],
+        }
+    }
+
+    fn write_archive(path: &Path, details: &PanicDetails) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        let options = SimpleFileOptions::default();
+        let time = "2024-01-01T10:00:00Z";
+        zip.start_file(format!("{RUN_DIR}/{time}.{PANIC_FILE_EXTENSION}"), options)
+            .unwrap();
+        zip.write_all(b"Thread 'main' panicked at 'boom'\n").unwrap();
+        zip.start_file(format!("{RUN_DIR}/{time}{PANIC_DETAILS_FILE_SUFFIX}"), options)
+            .unwrap();
+        serde_json::to_writer(&mut zip, details).unwrap();
+        // Truncated details
+        zip.start_file(
+            format!("{RUN_DIR}/2024-01-01T10:00:01Z{PANIC_DETAILS_FILE_SUFFIX}"),
+            options,
+        )
+        .unwrap();
+        zip.write_all(b"{\"thread\": \"ma").unwrap();
+        zip.start_file(SUMMARY_FILE_NAME, options).unwrap();
+        zip.write_all(b"outdated summary").unwrap();
+        zip.start_file(crate::manifest::MANIFEST_FILE_NAME, options).unwrap();
+        serde_json::to_writer(&mut zip, &Manifest::new()).unwrap();
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn symbolicate_archive_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let debug_dir = dir.path().join("debug");
+        std::fs::create_dir(&debug_dir).unwrap();
+        let details = PanicDetails {
+            thread: "main".into(),
+            message: "boom".into(),
+            frames: vec![
+                frame(1, Some("std::panicking::begin_panic")),
+                frame(2, Some("app::run")),
+                frame(3, None),
+                frame(4, Some("std::rt::lang_start")),
+            ],
+            ..Default::default()
+        };
+        let archive = dir.path().join("report.zip");
+        write_archive(&archive, &details);
+
+        let output = dir.path().join("symbolicated.zip");
+        assert_eq!(symbolicate_archive(&archive, &debug_dir, &output).unwrap(), 0);
+
+        let mut reader = Reader::open(&output).unwrap();
+        reader.verify().unwrap();
+        let runs = reader.runs().unwrap();
+        assert_eq!(runs.len(), 1);
+        let backtrace = &runs[0].panics[0].backtrace;
+        assert!(backtrace.contains("app::run"));
+        assert!(!backtrace.contains("std::rt::lang_start"), "{backtrace}");
+        assert_eq!(
+            reader
+                .read(&format!("{RUN_DIR}/2024-01-01T10:00:01Z{PANIC_DETAILS_FILE_SUFFIX}"))
+                .unwrap(),
+            b"{\"thread\": \"ma"
+        );
+        let summary = String::from_utf8(reader.read(SUMMARY_FILE_NAME).unwrap()).unwrap();
+        assert!(summary.contains("app::run"));
+        assert_eq!(reader.file_names().filter(|name| *name == SUMMARY_FILE_NAME).count(), 1);
+    }
+
+    /// Returns an unresolved backtrace and the line of its capture.
+    #[inline(never)]
+    fn capture_unresolved_backtrace() -> (backtrace::Backtrace, u32) {
+        (backtrace::Backtrace::new_unresolved(), line!())
+    }
+
+    #[test]
+    fn symbolicate_with_debug_file() {
+        let (backtrace, line) = capture_unresolved_backtrace();
+        let mut details = PanicDetails::new("main", "boom", None, &backtrace);
+        assert!(details.frames.iter().all(PanicFrame::is_unresolved));
+
+        // The test binary is its own debug file
+        let dir = tempfile::tempdir().unwrap();
+        let exe = std::env::current_exe().unwrap();
+        std::fs::copy(&exe, dir.path().join(exe.file_name().unwrap())).unwrap();
+        let mut debug_files = DebugFiles::open(dir.path()).unwrap();
+        assert!(debug_files.symbolicate(&mut details) > 0);
+
+        let symbol = details
+            .frames
+            .iter()
+            .flat_map(|frame| frame.symbols.iter())
+            .find(|symbol| {
+                symbol
+                    .name
+                    .as_deref()
+                    .is_some_and(|name| name.ends_with("::capture_unresolved_backtrace"))
+            })
+            .unwrap_or_else(|| panic!("function not resolved: {details:#?}"));
+        assert!(
+            symbol
+                .file
+                .as_deref()
+                .is_some_and(|file| file.ends_with("symbolication.rs")),
+            "{symbol:?}"
+        );
+        assert_eq!(symbol.line, Some(line));
+    }
+}
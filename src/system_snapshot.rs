@@ -0,0 +1,484 @@
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Write, sync::RwLock, time::SystemTime};
+use sysinfo::{
+    Components, CpuRefreshKind, Disks, MemoryRefreshKind, Networks, ProcessRefreshKind, RefreshKind, System,
+};
+
+static SYSTEM_SNAPSHOT_OPTIONS: Lazy<RwLock<SystemSnapshotOptions>> =
+    Lazy::new(|| RwLock::new(SystemSnapshotOptions::default()));
+
+/// Sections captured by a [`SystemSnapshot`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SystemSnapshotOptions {
+    pub memory: bool,
+    pub os: bool,
+    pub cpu: bool,
+    pub disks: bool,
+    pub networks: bool,
+    pub components: bool,
+    /// The process list is not captured by default, it may contain sensitive data of other applications.
+    pub processes: bool,
+}
+
+impl Default for SystemSnapshotOptions {
+    fn default() -> Self {
+        Self {
+            memory: true,
+            os: true,
+            cpu: true,
+            disks: true,
+            networks: true,
+            components: true,
+            processes: false,
+        }
+    }
+}
+
+/// Memory and swap in bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryInfo {
+    pub total_memory: u64,
+    pub used_memory: u64,
+    pub available_memory: u64,
+    pub total_swap: u64,
+    pub used_swap: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OsInfo {
+    pub name: Option<String>,
+    pub kernel_version: Option<String>,
+    pub os_version: Option<String>,
+    pub long_os_version: Option<String>,
+    pub host_name: Option<String>,
+    pub cpu_arch: Option<String>,
+    /// Uptime of the system in seconds.
+    pub uptime: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CpuInfo {
+    pub brand: String,
+    pub vendor_id: String,
+    pub physical_core_count: Option<usize>,
+    pub logical_core_count: usize,
+    /// Frequency of each logical core in MHz.
+    pub frequencies: Vec<u64>,
+}
+
+/// Disk with its space in bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiskInfo {
+    pub name: String,
+    pub mount_point: String,
+    pub file_system: String,
+    pub kind: String,
+    pub total_space: u64,
+    pub available_space: u64,
+    pub is_removable: bool,
+    pub is_read_only: bool,
+}
+
+/// Network interface with the transferred bytes since the system start.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkInfo {
+    pub name: String,
+    pub mac_address: String,
+    pub total_received: u64,
+    pub total_transmitted: u64,
+}
+
+/// Temperatures in degrees Celsius.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComponentInfo {
+    pub label: String,
+    pub temperature: f32,
+    pub max: f32,
+    pub critical: Option<f32>,
+}
+
+/// Process with its memory in bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub name: String,
+    pub status: String,
+    pub memory: u64,
+    pub virtual_memory: u64,
+    /// Run time in seconds.
+    pub run_time: u64,
+    pub total_read_bytes: u64,
+    pub total_written_bytes: u64,
+}
+
+/// Information about the system at a moment, only the sections selected by the options are captured.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SystemSnapshot {
+    /// Time of the snapshot as RFC 3339 timestamp.
+    pub time: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<MemoryInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os: Option<OsInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<CpuInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disks: Option<Vec<DiskInfo>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub networks: Option<Vec<NetworkInfo>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub components: Option<Vec<ComponentInfo>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub processes: Option<Vec<ProcessInfo>>,
+}
+
+impl SystemSnapshot {
+    pub fn capture(options: &SystemSnapshotOptions) -> Self {
+        let mut refresh = RefreshKind::new();
+        if options.memory {
+            refresh = refresh.with_memory(MemoryRefreshKind::everything());
+        }
+        if options.cpu {
+            refresh = refresh.with_cpu(CpuRefreshKind::everything());
+        }
+        if options.processes {
+            refresh = refresh.with_processes(ProcessRefreshKind::everything());
+        }
+        let sys = System::new_with_specifics(refresh);
+
+        Self {
+            time: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            memory: options.memory.then(|| MemoryInfo {
+                total_memory: sys.total_memory(),
+                used_memory: sys.used_memory(),
+                available_memory: sys.available_memory(),
+                total_swap: sys.total_swap(),
+                used_swap: sys.used_swap(),
+            }),
+            os: options.os.then(|| OsInfo {
+                name: System::name(),
+                kernel_version: System::kernel_version(),
+                os_version: System::os_version(),
+                long_os_version: System::long_os_version(),
+                host_name: System::host_name(),
+                cpu_arch: System::cpu_arch(),
+                uptime: System::uptime(),
+            }),
+            cpu: options.cpu.then(|| CpuInfo {
+                brand: sys
+                    .cpus()
+                    .first()
+                    .map(|cpu| cpu.brand().to_string())
+                    .unwrap_or_default(),
+                vendor_id: sys
+                    .cpus()
+                    .first()
+                    .map(|cpu| cpu.vendor_id().to_string())
+                    .unwrap_or_default(),
+                physical_core_count: sys.physical_core_count(),
+                logical_core_count: sys.cpus().len(),
+                frequencies: sys.cpus().iter().map(|cpu| cpu.frequency()).collect(),
+            }),
+            disks: options.disks.then(|| {
+                Disks::new_with_refreshed_list()
+                    .iter()
+                    .map(|disk| DiskInfo {
+                        name: disk.name().to_string_lossy().to_string(),
+                        mount_point: disk.mount_point().to_string_lossy().to_string(),
+                        file_system: disk.file_system().to_string_lossy().to_string(),
+                        kind: disk.kind().to_string(),
+                        total_space: disk.total_space(),
+                        available_space: disk.available_space(),
+                        is_removable: disk.is_removable(),
+                        is_read_only: disk.is_read_only(),
+                    })
+                    .collect()
+            }),
+            networks: options.networks.then(|| {
+                let mut networks: Vec<_> = Networks::new_with_refreshed_list()
+                    .iter()
+                    .map(|(name, data)| NetworkInfo {
+                        name: name.clone(),
+                        mac_address: data.mac_address().to_string(),
+                        total_received: data.total_received(),
+                        total_transmitted: data.total_transmitted(),
+                    })
+                    .collect();
+                networks.sort_by(|a, b| a.name.cmp(&b.name));
+                networks
+            }),
+            components: options.components.then(|| {
+                Components::new_with_refreshed_list()
+                    .iter()
+                    .map(|component| ComponentInfo {
+                        label: component.label().to_string(),
+                        temperature: component.temperature(),
+                        max: component.max(),
+                        critical: component.critical(),
+                    })
+                    .collect()
+            }),
+            processes: options.processes.then(|| {
+                let mut processes: Vec<_> = sys
+                    .processes()
+                    .values()
+                    .map(|process| ProcessInfo {
+                        pid: process.pid().as_u32(),
+                        name: process.name().to_string_lossy().to_string(),
+                        status: process.status().to_string(),
+                        memory: process.memory(),
+                        virtual_memory: process.virtual_memory(),
+                        run_time: process.run_time(),
+                        total_read_bytes: process.disk_usage().total_read_bytes,
+                        total_written_bytes: process.disk_usage().total_written_bytes,
+                    })
+                    .collect();
+                processes.sort_by_key(|process| process.pid);
+                processes
+            }),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Formats the snapshot as human readable text.
+    pub fn to_text(&self) -> String {
+        self.write_text().unwrap_or_default()
+    }
+
+    fn write_text(&self) -> Result<String, std::fmt::Error> {
+        fn or_unknown(value: &Option<String>) -> &str {
+            value.as_deref().unwrap_or("unknown")
+        }
+
+        let mut out = String::new();
+        writeln!(out, "=> snapshot: {}", self.time)?;
+        if let Some(memory) = self.memory.as_ref() {
+            writeln!(out, "=> memory:")?;
+            writeln!(out, "total memory    : {} bytes", memory.total_memory)?;
+            writeln!(out, "used memory     : {} bytes", memory.used_memory)?;
+            writeln!(out, "available memory: {} bytes", memory.available_memory)?;
+            writeln!(out, "total swap      : {} bytes", memory.total_swap)?;
+            writeln!(out, "used swap       : {} bytes", memory.used_swap)?;
+        }
+        if let Some(os) = self.os.as_ref() {
+            writeln!(out, "=> system:")?;
+            writeln!(out, "System name:             {}", or_unknown(&os.name))?;
+            writeln!(out, "System kernel version:   {}", or_unknown(&os.kernel_version))?;
+            writeln!(out, "System OS version:       {}", or_unknown(&os.os_version))?;
+            writeln!(out, "System long OS version:  {}", or_unknown(&os.long_os_version))?;
+            writeln!(out, "System host name:        {}", or_unknown(&os.host_name))?;
+            writeln!(out, "CPU architecture:        {}", or_unknown(&os.cpu_arch))?;
+            writeln!(out, "Uptime:                  {} s", os.uptime)?;
+        }
+        if let Some(cpu) = self.cpu.as_ref() {
+            writeln!(out, "=> cpu:")?;
+            writeln!(out, "brand          : {}", cpu.brand)?;
+            writeln!(out, "vendor         : {}", cpu.vendor_id)?;
+            match cpu.physical_core_count {
+                Some(count) => writeln!(out, "physical cores : {count}")?,
+                None => writeln!(out, "physical cores : unknown")?,
+            }
+            writeln!(out, "logical cores  : {}", cpu.logical_core_count)?;
+            let frequencies: Vec<_> = cpu.frequencies.iter().map(|frequency| frequency.to_string()).collect();
+            writeln!(out, "frequencies    : {} MHz", frequencies.join(", "))?;
+        }
+        if let Some(disks) = self.disks.as_ref() {
+            writeln!(out, "=> disks:")?;
+            for disk in disks {
+                writeln!(
+                    out,
+                    "{} on {}: {} {}, {} of {} bytes available{}{}",
+                    disk.name,
+                    disk.mount_point,
+                    disk.file_system,
+                    disk.kind,
+                    disk.available_space,
+                    disk.total_space,
+                    if disk.is_removable { ", removable" } else { "" },
+                    if disk.is_read_only { ", read-only" } else { "" },
+                )?;
+            }
+        }
+        if let Some(networks) = self.networks.as_ref() {
+            writeln!(out, "=> networks:")?;
+            for network in networks {
+                writeln!(
+                    out,
+                    "{} [{}]: {} B (down) / {} B (up)",
+                    network.name, network.mac_address, network.total_received, network.total_transmitted
+                )?;
+            }
+        }
+        if let Some(components) = self.components.as_ref() {
+            writeln!(out, "=> components:")?;
+            for component in components {
+                write!(
+                    out,
+                    "{}: {:.1} °C (max {:.1} °C",
+                    component.label, component.temperature, component.max
+                )?;
+                if let Some(critical) = component.critical {
+                    write!(out, ", critical {critical:.1} °C")?;
+                }
+                writeln!(out, ")")?;
+            }
+        }
+        if let Some(processes) = self.processes.as_ref() {
+            writeln!(out, "=> processes:")?;
+            for process in processes {
+                writeln!(
+                    out,
+                    "[{}] {} {}: {} bytes, {} s, {} B read / {} B written",
+                    process.pid,
+                    process.name,
+                    process.status,
+                    process.memory,
+                    process.run_time,
+                    process.total_read_bytes,
+                    process.total_written_bytes
+                )?;
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Sets the sections of the snapshots written by [`crate::misc::create_sysinfo_dump`].
+pub fn system_snapshot_set_options(options: SystemSnapshotOptions) {
+    *SYSTEM_SNAPSHOT_OPTIONS.write().unwrap() = options;
+}
+
+pub fn system_snapshot_options() -> SystemSnapshotOptions {
+    *SYSTEM_SNAPSHOT_OPTIONS.read().unwrap()
+}
+
+/// Captures a snapshot with the options set by [`system_snapshot_set_options`].
+pub fn system_snapshot() -> SystemSnapshot {
+    SystemSnapshot::capture(&system_snapshot_options())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> SystemSnapshot {
+        SystemSnapshot {
+            time: "2024-01-01T10:00:00Z".to_string(),
+            memory: Some(MemoryInfo {
+                total_memory: 8000,
+                used_memory: 3000,
+                available_memory: 5000,
+                total_swap: 2000,
+                used_swap: 0,
+            }),
+            os: None,
+            cpu: Some(CpuInfo {
+                brand: "Test CPU".to_string(),
+                vendor_id: "Vendor".to_string(),
+                physical_core_count: None,
+                logical_core_count: 2,
+                frequencies: vec![2400, 3000],
+            }),
+            disks: Some(vec![DiskInfo {
+                name: "/dev/sda1".to_string(),
+                mount_point: "/".to_string(),
+                file_system: "ext4".to_string(),
+                kind: "SSD".to_string(),
+                total_space: 1000,
+                available_space: 400,
+                is_removable: false,
+                is_read_only: true,
+            }]),
+            networks: Some(Vec::new()),
+            components: Some(vec![
+                ComponentInfo {
+                    label: "core 0".to_string(),
+                    temperature: 45.25,
+                    max: 60.0,
+                    critical: Some(100.0),
+                },
+                ComponentInfo {
+                    label: "nvme".to_string(),
+                    temperature: 30.0,
+                    max: 35.5,
+                    critical: None,
+                },
+            ]),
+            processes: None,
+        }
+    }
+
+    #[test]
+    fn capture_selected_sections() {
+        let none = SystemSnapshotOptions {
+            memory: false,
+            os: false,
+            cpu: false,
+            disks: false,
+            networks: false,
+            components: false,
+            processes: false,
+        };
+        let snapshot = SystemSnapshot::capture(&none);
+        assert!(humantime::parse_rfc3339(&snapshot.time).is_ok());
+        assert_eq!(
+            snapshot,
+            SystemSnapshot {
+                time: snapshot.time.clone(),
+                memory: None,
+                os: None,
+                cpu: None,
+                disks: None,
+                networks: None,
+                components: None,
+                processes: None,
+            }
+        );
+        assert_eq!(snapshot.to_text(), format!("=> snapshot: {}\n", snapshot.time));
+
+        let snapshot = SystemSnapshot::capture(&SystemSnapshotOptions { memory: true, ..none });
+        assert!(snapshot.memory.is_some_and(|memory| memory.total_memory > 0));
+        assert!(snapshot.os.is_none() && snapshot.cpu.is_none() && snapshot.processes.is_none());
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let snapshot = snapshot();
+        let json = snapshot.to_json().unwrap();
+        // Sections not captured are omitted
+        assert!(!json.contains("\"os\""), "{json}");
+        assert!(!json.contains("\"processes\""), "{json}");
+        assert_eq!(serde_json::from_str::<SystemSnapshot>(&json).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn text_format() {
+        assert_eq!(
+            snapshot().to_text(),
+            "=> snapshot: 2024-01-01T10:00:00Z\n\
+             => memory:\n\
+             total memory    : 8000 bytes\n\
+             used memory     : 3000 bytes\n\
+             available memory: 5000 bytes\n\
+             total swap      : 2000 bytes\n\
+             used swap       : 0 bytes\n\
+             => cpu:\n\
+             brand          : Test CPU\n\
+             vendor         : Vendor\n\
+             physical cores : unknown\n\
+             logical cores  : 2\n\
+             frequencies    : 2400, 3000 MHz\n\
+             => disks:\n\
+             /dev/sda1 on /: ext4 SSD, 400 of 1000 bytes available, read-only\n\
+             => networks:\n\
+             => components:\n\
+             core 0: 45.2 °C (max 60.0 °C, critical 100.0 °C)\n\
+             nvme: 30.0 °C (max 35.5 °C)\n"
+        );
+    }
+}
@@ -0,0 +1,315 @@
+use anyhow::Result;
+use std::fmt::Write;
+
+/// Frame of a thread stack with its resolved symbol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackFrame {
+    pub ip: usize,
+    pub symbol: Option<String>,
+    /// Source location as `file:line`.
+    pub location: Option<String>,
+}
+
+/// Stack of a thread of the current process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThreadStack {
+    /// Thread ID of the operating system.
+    pub tid: i32,
+    pub name: String,
+    /// Scheduling state, e.g. `R (running)` or `S (sleeping)`.
+    pub state: String,
+    /// `None` if the thread did not respond to the unwinding signal in time.
+    pub frames: Option<Vec<StackFrame>>,
+}
+
+/// Sets the signal used to unwind the stacks of other threads, `SIGUSR2` by default.
+///
+/// The handler of the signal is replaced by the first stack capture.
+#[cfg(target_os = "linux")]
+pub fn thread_stacks_set_signal(signal: i32) {
+    linux::UNWIND_SIGNAL.store(signal, std::sync::atomic::Ordering::SeqCst);
+}
+
+/// Captures the stacks of all threads of the current process.
+///
+/// The stacks are unwound by the threads themselves in a signal handler,
+/// which is only supported on Linux.
+pub fn capture_thread_stacks() -> Result<Vec<ThreadStack>> {
+    #[cfg(target_os = "linux")]
+    return linux::capture();
+    #[cfg(not(target_os = "linux"))]
+    anyhow::bail!("Capturing thread stacks is not supported on this platform")
+}
+
+/// Formats the stacks as human readable text.
+pub fn format_thread_stacks(stacks: &[ThreadStack]) -> String {
+    let mut out = String::new();
+    for stack in stacks {
+        _ = writeln!(out, "Thread {} '{}' {}:", stack.tid, stack.name, stack.state);
+        match stack.frames.as_ref() {
+            Some(frames) => {
+                for (index, frame) in frames.iter().enumerate() {
+                    _ = writeln!(
+                        out,
+                        "  {index:>3}: {:#018x} {}",
+                        frame.ip,
+                        frame.symbol.as_deref().unwrap_or("<unknown>")
+                    );
+                    if let Some(location) = frame.location.as_ref() {
+                        _ = writeln!(out, "                at {location}");
+                    }
+                }
+            }
+            None => _ = writeln!(out, "  <stack not available>"),
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::{StackFrame, ThreadStack};
+    use anyhow::{Context, Result};
+    use once_cell::sync::OnceCell;
+    use std::{
+        sync::{
+            atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+            Mutex,
+        },
+        time::{Duration, Instant},
+    };
+
+    const MAX_FRAMES: usize = 128;
+    // Time a thread has to handle the unwinding signal, e.g. a thread blocked in a system call handles it immediately
+    const UNWIND_TIMEOUT: Duration = Duration::from_millis(500);
+    // Symbol of the handler, the frames up to the signal trampoline after it do not belong to the interrupted code
+    const HANDLER_SYMBOL: &str = "thread_stacks::linux::unwind_handler";
+
+    pub(super) static UNWIND_SIGNAL: AtomicI32 = AtomicI32::new(libc::SIGUSR2);
+    static HANDLER: OnceCell<std::result::Result<i32, String>> = OnceCell::new();
+    static CAPTURE_LOCK: Mutex<()> = Mutex::new(());
+    // Current unwinding request with its sequence number in the upper and the thread ID in the lower 32 bits,
+    // only the handler of this request writes the frames, a late handler of a timed out request is ignored
+    static REQUEST: AtomicU64 = AtomicU64::new(0);
+    static SEQUENCE: AtomicU32 = AtomicU32::new(0);
+    // Request whose frames were written last
+    static CAPTURED: AtomicU64 = AtomicU64::new(0);
+    // Set while a handler writes the frames
+    static WRITING: AtomicBool = AtomicBool::new(false);
+    static FRAME_COUNT: AtomicUsize = AtomicUsize::new(0);
+    static FRAMES: [AtomicUsize; MAX_FRAMES] = [const { AtomicUsize::new(0) }; MAX_FRAMES];
+
+    fn gettid() -> i32 {
+        // SAFETY: The system call has no arguments and cannot fail.
+        unsafe { libc::syscall(libc::SYS_gettid) as i32 }
+    }
+
+    fn request_id(sequence: u32, tid: i32) -> u64 {
+        (u64::from(sequence) << 32) | u64::from(tid as u32)
+    }
+
+    extern "C" fn unwind_handler(_signal: libc::c_int) {
+        let request = REQUEST.load(Ordering::SeqCst);
+        if request as u32 as i32 != gettid() || WRITING.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        // The request may have timed out before the frames could be written
+        if REQUEST.load(Ordering::SeqCst) == request {
+            let mut count = 0;
+            // SAFETY: Other threads do not unwind concurrently, because only the handler of the current request
+            // unwinds and the captures are serialized by the lock.
+            unsafe {
+                backtrace::trace_unsynchronized(|frame| {
+                    FRAMES[count].store(frame.ip() as usize, Ordering::Relaxed);
+                    count += 1;
+                    count < MAX_FRAMES
+                });
+            }
+            FRAME_COUNT.store(count, Ordering::SeqCst);
+            CAPTURED.store(request, Ordering::SeqCst);
+        }
+        WRITING.store(false, Ordering::SeqCst);
+    }
+
+    fn install_handler() -> Result<i32> {
+        HANDLER
+            .get_or_init(|| {
+                let signal = UNWIND_SIGNAL.load(Ordering::SeqCst);
+                // SAFETY: The handler only accesses atomics and unwinds the stack of its thread.
+                let result = unsafe {
+                    let mut action: libc::sigaction = std::mem::zeroed();
+                    action.sa_sigaction = unwind_handler as extern "C" fn(libc::c_int) as libc::sighandler_t;
+                    action.sa_flags = libc::SA_RESTART;
+                    libc::sigemptyset(&mut action.sa_mask);
+                    libc::sigaction(signal, &action, std::ptr::null_mut())
+                };
+                match result {
+                    0 => Ok(signal),
+                    _ => Err(format!(
+                        "Cannot install handler of signal {signal}: {}",
+                        std::io::Error::last_os_error()
+                    )),
+                }
+            })
+            .clone()
+            .map_err(anyhow::Error::msg)
+    }
+
+    fn read_task_file(tid: i32, name: &str) -> Option<String> {
+        std::fs::read_to_string(format!("/proc/self/task/{tid}/{name}"))
+            .ok()
+            .map(|content| content.trim().to_string())
+    }
+
+    fn thread_state(tid: i32) -> String {
+        read_task_file(tid, "status")
+            .and_then(|status| {
+                status
+                    .lines()
+                    .find_map(|line| line.strip_prefix("State:").map(|state| state.trim().to_string()))
+            })
+            .unwrap_or_default()
+    }
+
+    fn unwind_thread(signal: i32, tid: i32) -> Option<Vec<usize>> {
+        let request = request_id(SEQUENCE.fetch_add(1, Ordering::SeqCst).wrapping_add(1), tid);
+        REQUEST.store(request, Ordering::SeqCst);
+        // SAFETY: Sends a signal to a thread of the current process.
+        let sent = unsafe { libc::syscall(libc::SYS_tgkill, libc::getpid(), tid, signal) } == 0;
+        let start = Instant::now();
+        while sent && CAPTURED.load(Ordering::SeqCst) != request && start.elapsed() < UNWIND_TIMEOUT {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        REQUEST.store(0, Ordering::SeqCst);
+        if CAPTURED.load(Ordering::SeqCst) != request {
+            return None;
+        }
+        let count = FRAME_COUNT.load(Ordering::SeqCst);
+        Some(FRAMES[..count].iter().map(|ip| ip.load(Ordering::Relaxed)).collect())
+    }
+
+    fn resolve(ips: &[usize]) -> Vec<StackFrame> {
+        let frames: Vec<_> = ips
+            .iter()
+            .map(|ip| {
+                let mut frame = StackFrame {
+                    ip: *ip,
+                    symbol: None,
+                    location: None,
+                };
+                backtrace::resolve(*ip as *mut std::ffi::c_void, |symbol| {
+                    if frame.symbol.is_none() {
+                        frame.symbol = symbol.name().map(|name| name.to_string());
+                        frame.location = symbol
+                            .filename()
+                            .map(|file| format!("{}:{}", file.to_string_lossy(), symbol.lineno().unwrap_or(0)));
+                    }
+                });
+                frame
+            })
+            .collect();
+        match frames.iter().position(|frame| {
+            frame
+                .symbol
+                .as_deref()
+                .is_some_and(|symbol| symbol.contains(HANDLER_SYMBOL))
+        }) {
+            Some(index) => frames.into_iter().skip(index + 2).collect(),
+            None => frames,
+        }
+    }
+
+    pub(super) fn capture() -> Result<Vec<ThreadStack>> {
+        let signal = install_handler()?;
+        let _lock = CAPTURE_LOCK.lock().unwrap();
+        let own_tid = gettid();
+        let mut tids = std::fs::read_dir("/proc/self/task")
+            .with_context(|| "Cannot list threads")?
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<i32>().ok())
+            .collect::<Vec<_>>();
+        tids.sort();
+
+        Ok(tids
+            .into_iter()
+            .map(|tid| {
+                let ips = match tid == own_tid {
+                    true => {
+                        let mut ips = Vec::new();
+                        backtrace::trace(|frame| {
+                            ips.push(frame.ip() as usize);
+                            ips.len() < MAX_FRAMES
+                        });
+                        Some(ips)
+                    }
+                    false => unwind_thread(signal, tid),
+                };
+                ThreadStack {
+                    tid,
+                    name: read_task_file(tid, "comm").unwrap_or_default(),
+                    state: thread_state(tid),
+                    frames: ips.map(|ips| resolve(&ips)),
+                }
+            })
+            .collect())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::thread_stacks::{capture_thread_stacks, format_thread_stacks};
+
+        #[test]
+        fn capture_other_thread() {
+            let (started_tx, started_rx) = std::sync::mpsc::channel();
+            let (stop_tx, stop_rx) = std::sync::mpsc::channel::<()>();
+            let thread = std::thread::Builder::new()
+                .name("stack-test".to_string())
+                .spawn(move || {
+                    started_tx.send(()).unwrap();
+                    _ = stop_rx.recv();
+                })
+                .unwrap();
+            started_rx.recv().unwrap();
+
+            let stacks = capture_thread_stacks().unwrap();
+            stop_tx.send(()).unwrap();
+            thread.join().unwrap();
+
+            let stack = stacks.iter().find(|stack| stack.name == "stack-test").unwrap();
+            let frames = stack.frames.as_ref().unwrap();
+            // The frames start at the interrupted code, not at the signal handler
+            assert!(frames.iter().all(|frame| !frame
+                .symbol
+                .as_deref()
+                .is_some_and(|symbol| symbol.contains("unwind_handler"))));
+            assert!(
+                frames.iter().any(|frame| frame
+                    .symbol
+                    .as_deref()
+                    .is_some_and(|symbol| symbol.contains("capture_other_thread"))),
+                "{}",
+                format_thread_stacks(std::slice::from_ref(stack))
+            );
+            assert!(format_thread_stacks(&stacks).contains("'stack-test'"));
+        }
+
+        #[test]
+        fn ignore_other_requests() {
+            let _lock = CAPTURE_LOCK.lock().unwrap();
+            let tid = gettid();
+            // A handler running late for a previous request of another thread writes nothing
+            let previous = CAPTURED.load(Ordering::SeqCst);
+            REQUEST.store(request_id(1, tid + 1), Ordering::SeqCst);
+            unwind_handler(0);
+            assert_eq!(CAPTURED.load(Ordering::SeqCst), previous);
+
+            let request = request_id(u32::MAX, tid);
+            REQUEST.store(request, Ordering::SeqCst);
+            unwind_handler(0);
+            REQUEST.store(0, Ordering::SeqCst);
+            assert_eq!(CAPTURED.load(Ordering::SeqCst), request);
+            assert!(FRAME_COUNT.load(Ordering::SeqCst) > 0);
+        }
+    }
+}
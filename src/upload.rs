@@ -0,0 +1,798 @@
+use crate::proc_dir::CancellationToken;
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use std::{
+    fs::File,
+    io::{Cursor, Read, Seek, SeekFrom},
+    path::Path,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+/// Header with the ID of a chunked upload, which is the same when an interrupted upload is resumed.
+pub const UPLOAD_ID_HEADER: &str = "Upload-Id";
+/// Header of the server response with the number of bytes of a chunked upload received so far.
+pub const UPLOAD_OFFSET_HEADER: &str = "Upload-Offset";
+
+// Interval to check the cancellation while waiting for the next attempt
+const CANCELLATION_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+// Result of a request, the error is boxed because of its size
+type RequestResult<T> = std::result::Result<T, Box<ureq::Error>>;
+
+static UPLOAD_CONFIG: Lazy<RwLock<Option<UploadConfig>>> = Lazy::new(|| RwLock::new(None));
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UploadFormat {
+    /// The archive is the body of the request.
+    #[default]
+    Raw,
+    /// The archive is sent as file of a `multipart/form-data` request.
+    Multipart,
+}
+
+/// Endpoint and behavior of report uploads.
+#[derive(Clone)]
+pub struct UploadConfig {
+    /// URL the reports are posted to.
+    pub url: String,
+    /// Authentication header sent with every request, e.g. `("Authorization", "Bearer <token>")`.
+    pub auth_header: Option<(String, String)>,
+    pub format: UploadFormat,
+    /// Name of the form field of the archive in multipart uploads.
+    pub multipart_field_name: String,
+    /// Number of retries of a failed request, requests failed with a client error are not retried.
+    pub max_retries: u32,
+    /// Delay before the first retry, it is doubled for each further retry.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Size of the chunks of resumable uploads, `None` uploads the archive in a single request.
+    ///
+    /// Each chunk is posted with the headers `Content-Range: bytes <start>-<end>/<total>` and
+    /// [`UPLOAD_ID_HEADER`]. The server may answer with [`UPLOAD_OFFSET_HEADER`] to set the offset of the next chunk.
+    /// To resume an interrupted upload, a request with an empty body and `Content-Range: bytes */<total>` is sent
+    /// first and the server answers with the received bytes in [`UPLOAD_OFFSET_HEADER`].
+    /// Chunked uploads are only supported with [`UploadFormat::Raw`].
+    pub chunk_size: Option<u64>,
+    /// Timeout of connecting and of each read and write.
+    pub timeout: Duration,
+}
+
+impl UploadConfig {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            auth_header: None,
+            format: UploadFormat::default(),
+            multipart_field_name: "report".to_string(),
+            max_retries: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            chunk_size: None,
+            timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+impl std::fmt::Debug for UploadConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UploadConfig")
+            .field("url", &self.url)
+            .field(
+                "auth_header",
+                &self.auth_header.as_ref().map(|(name, _)| (name, "<hidden>")),
+            )
+            .field("format", &self.format)
+            .field("multipart_field_name", &self.multipart_field_name)
+            .field("max_retries", &self.max_retries)
+            .field("initial_backoff", &self.initial_backoff)
+            .field("max_backoff", &self.max_backoff)
+            .field("chunk_size", &self.chunk_size)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+/// Sets the endpoint for report uploads, which enables the upload actions of the dialogs.
+///
+/// The reports queued in the outbox by previous runs are uploaded in a background thread, right away if the data
+/// directory is set by [`crate::init`], otherwise when the run directory is created by [`crate::proc_dir::proc_dir`].
+pub fn upload_set_config(config: UploadConfig) {
+    *UPLOAD_CONFIG.write().unwrap() = Some(config);
+    if crate::misc::data_dir_is_initialized() {
+        crate::outbox::outbox_flush_automatically();
+    }
+}
+
+pub fn upload_config() -> Option<UploadConfig> {
+    UPLOAD_CONFIG.read().unwrap().clone()
+}
+
+pub fn upload_is_configured() -> bool {
+    UPLOAD_CONFIG.read().unwrap().is_some()
+}
+
+/// Progress of an upload.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UploadProgress {
+    pub bytes_sent: u64,
+    pub bytes_total: u64,
+}
+
+impl UploadProgress {
+    /// Returns the progress as a fraction between 0.0 and 1.0.
+    pub fn fraction(&self) -> f64 {
+        if self.bytes_total == 0 {
+            return 1.0;
+        }
+        (self.bytes_sent as f64 / self.bytes_total as f64).min(1.0)
+    }
+}
+
+pub type UploadProgressCallback = Box<dyn FnMut(&UploadProgress) + Send>;
+
+/// Error returned if an upload was cancelled with a [`CancellationToken`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UploadCancelled;
+
+impl std::fmt::Display for UploadCancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Upload cancelled")
+    }
+}
+
+impl std::error::Error for UploadCancelled {}
+
+/// Options of a single upload.
+#[derive(Default)]
+pub struct UploadOptions {
+    pub progress: Option<UploadProgressCallback>,
+    pub cancellation: Option<CancellationToken>,
+}
+
+impl std::fmt::Debug for UploadOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UploadOptions")
+            .field("progress", &self.progress.is_some())
+            .field("cancellation", &self.cancellation)
+            .finish()
+    }
+}
+
+impl UploadOptions {
+    fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .as_ref()
+            .is_some_and(|cancellation| cancellation.is_cancelled())
+    }
+
+    fn check_cancelled(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(UploadCancelled.into());
+        }
+        Ok(())
+    }
+
+    fn report_progress(&mut self, progress: &UploadProgress) {
+        if let Some(callback) = self.progress.as_mut() {
+            callback(progress);
+        }
+    }
+
+    fn sleep(&self, duration: Duration) -> Result<()> {
+        let end = Instant::now() + duration;
+        while let Some(remaining) = end.checked_duration_since(Instant::now()) {
+            self.check_cancelled()?;
+            std::thread::sleep(remaining.min(CANCELLATION_CHECK_INTERVAL));
+        }
+        self.check_cancelled()
+    }
+}
+
+/// Response of the server to a successful upload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+/// Reader reporting the progress of the body of a request, it fails if the upload is cancelled.
+struct ProgressReader<'a, R> {
+    inner: R,
+    progress: UploadProgress,
+    options: &'a mut UploadOptions,
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.options.is_cancelled() {
+            return Err(std::io::Error::other(UploadCancelled));
+        }
+        let count = self.inner.read(buf)?;
+        self.progress.bytes_sent = (self.progress.bytes_sent + count as u64).min(self.progress.bytes_total);
+        self.options.report_progress(&self.progress);
+        Ok(count)
+    }
+}
+
+fn is_retryable(error: &ureq::Error) -> bool {
+    match error {
+        ureq::Error::Status(status, _) => *status == 408 || *status == 429 || *status >= 500,
+        ureq::Error::Transport(_) => true,
+    }
+}
+
+fn retry_after(error: &ureq::Error) -> Option<Duration> {
+    match error {
+        ureq::Error::Status(_, response) => response
+            .header("Retry-After")
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(Duration::from_secs),
+        ureq::Error::Transport(_) => None,
+    }
+}
+
+fn upload_error(config: &UploadConfig, error: ureq::Error) -> anyhow::Error {
+    match error {
+        ureq::Error::Status(status, response) => {
+            let body = response.into_string().unwrap_or_default();
+            anyhow::anyhow!(
+                "Upload to '{}' failed with status {status}: {}",
+                config.url,
+                body.trim()
+            )
+        }
+        ureq::Error::Transport(transport) => {
+            // The body reader fails with `UploadCancelled` if the upload is cancelled
+            let cancelled = std::error::Error::source(&transport)
+                .and_then(|source| source.downcast_ref::<std::io::Error>())
+                .and_then(|error| error.get_ref())
+                .is_some_and(|error| error.is::<UploadCancelled>());
+            if cancelled {
+                return UploadCancelled.into();
+            }
+            anyhow::Error::new(transport).context(format!("Cannot upload to '{}'", config.url))
+        }
+    }
+}
+
+/// Calls `request` until it succeeds, the error is not retryable or all retries are used.
+fn with_retries<T>(
+    config: &UploadConfig,
+    options: &mut UploadOptions,
+    mut request: impl FnMut(&mut UploadOptions) -> RequestResult<T>,
+) -> Result<T> {
+    let mut backoff = config.initial_backoff;
+    let mut attempt = 0;
+    loop {
+        options.check_cancelled()?;
+        match request(options) {
+            Ok(value) => return Ok(value),
+            Err(error) if attempt < config.max_retries && is_retryable(&error) && !options.is_cancelled() => {
+                attempt += 1;
+                let delay = retry_after(&error).unwrap_or(backoff).min(config.max_backoff);
+                log::warn!(
+                    "Upload attempt {attempt} to '{}' failed, retry in {delay:?}: {error}",
+                    config.url
+                );
+                options.sleep(delay)?;
+                backoff = (backoff * 2).min(config.max_backoff);
+            }
+            Err(error) => return Err(upload_error(config, *error)),
+        }
+    }
+}
+
+fn request(agent: &ureq::Agent, config: &UploadConfig) -> ureq::Request {
+    let request = agent.post(&config.url);
+    match config.auth_header.as_ref() {
+        Some((name, value)) => request.set(name, value),
+        None => request,
+    }
+}
+
+fn into_upload_response(response: ureq::Response) -> RequestResult<UploadResponse> {
+    let status = response.status();
+    let body = response.into_string().map_err(ureq::Error::from)?;
+    Ok(UploadResponse { status, body })
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap_or_default().to_string_lossy().replace('"', "")
+}
+
+/// Returns an ID that is stable for the same file, to resume the upload after a restart.
+fn upload_id(path: &Path, size: u64) -> String {
+    let modified = std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    // FNV-1a, which is stable across Rust versions in contrast to the hasher of the standard library
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in format!("{}|{size}|{}", path.to_string_lossy(), modified.as_nanos()).bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{hash:016x}")
+}
+
+fn open_file(path: &Path) -> RequestResult<File> {
+    Ok(File::open(path).map_err(ureq::Error::from)?)
+}
+
+fn upload_single(
+    agent: &ureq::Agent,
+    config: &UploadConfig,
+    path: &Path,
+    size: u64,
+    options: &mut UploadOptions,
+) -> Result<UploadResponse> {
+    let name = file_name(path);
+    with_retries(config, options, |options| {
+        let file = open_file(path)?;
+        let progress = UploadProgress {
+            bytes_sent: 0,
+            bytes_total: size,
+        };
+        let response = match config.format {
+            UploadFormat::Raw => {
+                let body = ProgressReader {
+                    inner: file,
+                    progress,
+                    options,
+                };
+                request(agent, config)
+                    .set("Content-Type", "application/octet-stream")
+                    .set("Content-Disposition", &format!("attachment; filename=\"{name}\""))
+                    .set("Content-Length", &size.to_string())
+                    .send(body)?
+            }
+            UploadFormat::Multipart => {
+                let boundary = format!("mxl-investigator-{}", upload_id(path, size));
+                let head = format!(
+                    "--{boundary}\r\nContent-Disposition: form-data; name=\"{field}\"; filename=\"{name}\"\r\n\
+                     Content-Type: application/octet-stream\r\n\r\n",
+                    field = config.multipart_field_name
+                );
+                let tail = format!("\r\n--{boundary}--\r\n");
+                let length = head.len() as u64 + size + tail.len() as u64;
+                let body = ProgressReader {
+                    inner: Cursor::new(head).chain(file).chain(Cursor::new(tail)),
+                    progress: UploadProgress {
+                        bytes_total: length,
+                        ..progress
+                    },
+                    options,
+                };
+                request(agent, config)
+                    .set("Content-Type", &format!("multipart/form-data; boundary={boundary}"))
+                    .set("Content-Length", &length.to_string())
+                    .send(body)?
+            }
+        };
+        into_upload_response(response)
+    })
+}
+
+fn upload_offset(response: &ureq::Response) -> Option<u64> {
+    response
+        .header(UPLOAD_OFFSET_HEADER)
+        .and_then(|value| value.trim().parse::<u64>().ok())
+}
+
+fn upload_chunked(
+    agent: &ureq::Agent,
+    config: &UploadConfig,
+    path: &Path,
+    size: u64,
+    chunk_size: u64,
+    options: &mut UploadOptions,
+) -> Result<UploadResponse> {
+    let name = file_name(path);
+    let id = upload_id(path, size);
+    let chunked_request = || {
+        request(agent, config)
+            .set(UPLOAD_ID_HEADER, &id)
+            .set("Content-Type", "application/octet-stream")
+            .set("Content-Disposition", &format!("attachment; filename=\"{name}\""))
+    };
+    let query_offset = || -> RequestResult<u64> {
+        let response = chunked_request()
+            .set("Content-Range", &format!("bytes */{size}"))
+            .send_bytes(&[])?;
+        Ok(upload_offset(&response).unwrap_or(0).min(size))
+    };
+
+    // Resume a previously interrupted upload of the same file
+    let mut offset = match query_offset() {
+        Ok(offset) => offset,
+        Err(error) => {
+            log::debug!("Cannot query offset of upload {id}, start from the beginning: {error}");
+            0
+        }
+    };
+    options.report_progress(&UploadProgress {
+        bytes_sent: offset,
+        bytes_total: size,
+    });
+
+    loop {
+        let mut resume = false;
+        let response = with_retries(config, options, |options| {
+            if resume {
+                offset = query_offset()?;
+            }
+            resume = true;
+            let end = (offset + chunk_size).min(size);
+            let mut file = open_file(path)?;
+            file.seek(SeekFrom::Start(offset)).map_err(ureq::Error::from)?;
+            let body = ProgressReader {
+                inner: file.take(end - offset),
+                progress: UploadProgress {
+                    bytes_sent: offset,
+                    bytes_total: size,
+                },
+                options,
+            };
+            let content_range = match size {
+                0 => "bytes */0".to_string(),
+                _ => format!("bytes {offset}-{}/{size}", end - 1),
+            };
+            let response = chunked_request()
+                .set("Content-Range", &content_range)
+                .set("Content-Length", &(end - offset).to_string())
+                .send(body)?;
+            let next_offset = upload_offset(&response).unwrap_or(end).min(size);
+            Ok((offset, next_offset, into_upload_response(response)?))
+        })?;
+        let (start, next_offset, response) = response;
+        if next_offset >= size {
+            return Ok(response);
+        }
+        // A server that does not take the chunk would otherwise be sent the same chunk forever
+        if next_offset <= start {
+            anyhow::bail!(
+                "Upload to '{}' does not advance, the server answered offset {next_offset} to the chunk at offset {start}",
+                config.url
+            );
+        }
+        offset = next_offset;
+    }
+}
+
+/// Uploads a file with the given configuration.
+pub fn upload_file_with_config(path: &Path, config: &UploadConfig, options: UploadOptions) -> Result<UploadResponse> {
+    let mut options = options;
+    let size = std::fs::metadata(path)
+        .with_context(|| format!("Cannot read size of file '{}'", path.to_string_lossy()))?
+        .len();
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(config.timeout)
+        .timeout_read(config.timeout)
+        .timeout_write(config.timeout)
+        .build();
+    let response = match (config.format, config.chunk_size) {
+        (UploadFormat::Raw, Some(chunk_size)) if chunk_size > 0 => {
+            upload_chunked(&agent, config, path, size, chunk_size, &mut options)?
+        }
+        _ => upload_single(&agent, config, path, size, &mut options)?,
+    };
+    log::info!(
+        "uploaded '{}' to '{}' with status {}",
+        path.to_string_lossy(),
+        config.url,
+        response.status
+    );
+    Ok(response)
+}
+
+/// Uploads a file to the endpoint set by [`upload_set_config`].
+pub fn upload_file(path: &Path, options: UploadOptions) -> Result<UploadResponse> {
+    let config = upload_config().with_context(|| "No upload endpoint configured")?;
+    upload_file_with_config(path, &config, options)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::{Arc, Mutex},
+        thread::JoinHandle,
+    };
+
+    #[derive(Debug, Clone)]
+    pub(crate) struct TestRequest {
+        pub(crate) headers: Vec<(String, String)>,
+        pub(crate) body: Vec<u8>,
+    }
+
+    impl TestRequest {
+        pub(crate) fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+        }
+    }
+
+    pub(crate) struct TestResponse {
+        status: u16,
+        headers: Vec<(&'static str, String)>,
+        body: &'static str,
+    }
+
+    impl TestResponse {
+        pub(crate) fn new(status: u16) -> Self {
+            Self {
+                status,
+                headers: Vec::new(),
+                body: "",
+            }
+        }
+
+        fn header(mut self, name: &'static str, value: impl ToString) -> Self {
+            self.headers.push((name, value.to_string()));
+            self
+        }
+    }
+
+    fn read_request(reader: &mut impl BufRead) -> TestRequest {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert!(line.starts_with("POST "), "unexpected request line: {line}");
+        let mut headers = Vec::new();
+        loop {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line.split_once(':').unwrap();
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+        let mut request = TestRequest {
+            headers,
+            body: Vec::new(),
+        };
+        let length = request
+            .header("Content-Length")
+            .expect("request without Content-Length")
+            .parse::<usize>()
+            .unwrap();
+        request.body.resize(length, 0);
+        reader.read_exact(&mut request.body).unwrap();
+        request
+    }
+
+    /// Serves `count` requests with `handler` and returns the URL and the received requests.
+    pub(crate) fn serve(
+        count: usize,
+        mut handler: impl FnMut(&TestRequest) -> TestResponse + Send + 'static,
+    ) -> (String, JoinHandle<Vec<TestRequest>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/reports", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for _ in 0..count {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let request = read_request(&mut reader);
+                let response = handler(&request);
+                let mut head = format!("HTTP/1.1 {} Test\r\n", response.status);
+                for (name, value) in &response.headers {
+                    head.push_str(&format!("{name}: {value}\r\n"));
+                }
+                head.push_str(&format!(
+                    "Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    response.body.len(),
+                    response.body
+                ));
+                let mut stream = stream;
+                stream.write_all(head.as_bytes()).unwrap();
+                requests.push(request);
+            }
+            requests
+        });
+        (url, server)
+    }
+
+    pub(crate) fn test_config(url: String) -> UploadConfig {
+        UploadConfig {
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(2),
+            timeout: Duration::from_secs(5),
+            ..UploadConfig::new(url)
+        }
+    }
+
+    fn test_file(dir: &Path, content: &[u8]) -> std::path::PathBuf {
+        let path = dir.join("report.zip");
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn retry_with_backoff() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = test_file(dir.path(), b"archive");
+        let mut statuses = vec![503, 500, 200].into_iter();
+        let (url, server) = serve(3, move |_| TestResponse {
+            body: "stored",
+            ..TestResponse::new(statuses.next().unwrap())
+        });
+
+        let start = Instant::now();
+        let response = upload_file_with_config(&path, &test_config(url), UploadOptions::default()).unwrap();
+        // Backoff of 50 ms before the first retry and 100 ms before the second one
+        assert!(start.elapsed() >= Duration::from_millis(150));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, "stored");
+        let requests = server.join().unwrap();
+        assert_eq!(requests.len(), 3);
+        for request in requests {
+            assert_eq!(request.body, b"archive");
+            assert_eq!(request.header("Content-Type"), Some("application/octet-stream"));
+        }
+    }
+
+    #[test]
+    fn no_retry_on_client_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = test_file(dir.path(), b"archive");
+        let (url, server) = serve(1, |_| TestResponse {
+            body: "invalid report",
+            ..TestResponse::new(400)
+        });
+
+        let error = upload_file_with_config(&path, &test_config(url), UploadOptions::default()).unwrap_err();
+        assert!(error.to_string().contains("status 400: invalid report"), "{error}");
+        assert_eq!(server.join().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn retry_after() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = test_file(dir.path(), b"archive");
+        let mut attempt = 0;
+        let (url, server) = serve(2, move |_| {
+            attempt += 1;
+            match attempt {
+                1 => TestResponse::new(429).header("Retry-After", 1),
+                _ => TestResponse::new(201),
+            }
+        });
+
+        let start = Instant::now();
+        let response = upload_file_with_config(&path, &test_config(url), UploadOptions::default()).unwrap();
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        assert_eq!(response.status, 201);
+        assert_eq!(server.join().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn retries_exhausted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = test_file(dir.path(), b"archive");
+        let (url, server) = serve(3, |_| TestResponse::new(502));
+
+        let config = UploadConfig {
+            max_retries: 2,
+            ..test_config(url)
+        };
+        let error = upload_file_with_config(&path, &config, UploadOptions::default()).unwrap_err();
+        assert!(error.to_string().contains("status 502"), "{error}");
+        assert_eq!(server.join().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn multipart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = test_file(dir.path(), b"archive");
+        let (url, server) = serve(1, |_| TestResponse::new(200));
+
+        let config = UploadConfig {
+            format: UploadFormat::Multipart,
+            ..test_config(url)
+        };
+        upload_file_with_config(&path, &config, UploadOptions::default()).unwrap();
+        let request = server.join().unwrap().remove(0);
+        let content_type = request.header("Content-Type").unwrap().to_string();
+        let boundary = content_type.strip_prefix("multipart/form-data; boundary=").unwrap();
+        let body = String::from_utf8(request.body).unwrap();
+        assert!(body.starts_with(&format!("--{boundary}\r\n")));
+        assert!(body.contains("name=\"report\"; filename=\"report.zip\""));
+        assert!(body.ends_with(&format!("\r\n\r\narchive\r\n--{boundary}--\r\n")));
+    }
+
+    #[test]
+    fn chunked_resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let content = b"0123456789abcdefghij";
+        let path = test_file(dir.path(), content);
+        // The server already received 6 bytes of an interrupted upload and fails once in between
+        let received = Arc::new(Mutex::new(content[..6].to_vec()));
+        let server_received = received.clone();
+        let mut failed = false;
+        let (url, server) = serve(6, move |request| {
+            let mut received = server_received.lock().unwrap();
+            let range = request.header("Content-Range").unwrap();
+            if range == "bytes */20" {
+                return TestResponse::new(200).header(UPLOAD_OFFSET_HEADER, received.len());
+            }
+            let (start, _) = range.strip_prefix("bytes ").unwrap().split_once('-').unwrap();
+            assert_eq!(start.parse::<usize>().unwrap(), received.len());
+            if received.len() >= 14 && !failed {
+                // Store a part of the chunk before the connection fails
+                failed = true;
+                received.extend_from_slice(&request.body[..2]);
+                return TestResponse::new(503);
+            }
+            received.extend_from_slice(&request.body);
+            TestResponse::new(200).header(UPLOAD_OFFSET_HEADER, received.len())
+        });
+
+        let config = UploadConfig {
+            chunk_size: Some(4),
+            ..test_config(url)
+        };
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let options = UploadOptions {
+            progress: Some(Box::new({
+                let progress = progress.clone();
+                move |upload_progress: &UploadProgress| progress.lock().unwrap().push(upload_progress.bytes_sent)
+            })),
+            cancellation: None,
+        };
+        upload_file_with_config(&path, &config, options).unwrap();
+
+        assert_eq!(received.lock().unwrap().as_slice(), content);
+        let requests = server.join().unwrap();
+        let ranges = requests
+            .iter()
+            .map(|request| request.header("Content-Range").unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ranges,
+            [
+                "bytes */20",
+                "bytes 6-9/20",
+                "bytes 10-13/20",
+                "bytes 14-17/20",
+                "bytes */20",
+                "bytes 16-19/20"
+            ]
+        );
+        let id = requests[0].header(UPLOAD_ID_HEADER).unwrap();
+        assert!(requests
+            .iter()
+            .all(|request| request.header(UPLOAD_ID_HEADER) == Some(id)));
+        let progress = progress.lock().unwrap();
+        assert_eq!(progress.first(), Some(&6));
+        assert_eq!(progress.last(), Some(&20));
+    }
+
+    #[test]
+    fn chunked_offset_not_advancing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = test_file(dir.path(), b"0123456789");
+        let (url, server) = serve(2, |_| TestResponse::new(200).header(UPLOAD_OFFSET_HEADER, 0));
+
+        let config = UploadConfig {
+            chunk_size: Some(4),
+            ..test_config(url)
+        };
+        let error = upload_file_with_config(&path, &config, UploadOptions::default()).unwrap_err();
+        assert!(error.to_string().contains("does not advance"), "{error}");
+        let ranges = server
+            .join()
+            .unwrap()
+            .iter()
+            .map(|request| request.header("Content-Range").unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, ["bytes */10", "bytes 0-3/10"]);
+    }
+}